@@ -0,0 +1 @@
+ALTER TABLE secrets ADD COLUMN last_used_at DATETIME;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE secrets ADD COLUMN auth_mode TEXT NOT NULL DEFAULT 'bearer';
\ No newline at end of file
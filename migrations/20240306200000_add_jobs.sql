@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS jobs
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    payload         TEXT NOT NULL,
+    status          TEXT NOT NULL DEFAULT 'pending',
+    attempts        INTEGER NOT NULL DEFAULT 0,
+    last_error      TEXT,
+    run_at          DATETIME NOT NULL,
+    created         DATETIME NOT NULL
+);
\ No newline at end of file
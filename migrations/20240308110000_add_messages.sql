@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS messages
+(
+    id              TEXT PRIMARY KEY NOT NULL,
+    name            TEXT NOT NULL,
+    email           TEXT NOT NULL,
+    content         TEXT NOT NULL,
+    received        DATETIME NOT NULL
+);
\ No newline at end of file
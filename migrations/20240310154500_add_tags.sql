@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS article_tags
+(
+    article         TEXT NOT NULL,
+    tag             TEXT NOT NULL,
+    PRIMARY KEY(article, tag),
+    FOREIGN KEY(article) REFERENCES articles(id)
+);
\ No newline at end of file
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS views
+(
+    article         TEXT NOT NULL,
+    viewed          DATETIME NOT NULL,
+    FOREIGN KEY(article) REFERENCES articles(id)
+);
+
+CREATE INDEX IF NOT EXISTS views_viewed ON views(viewed);
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE articles ADD COLUMN canonical_url TEXT;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE articles ADD COLUMN lang TEXT;
+ALTER TABLE articles ADD COLUMN translation_of TEXT REFERENCES articles(id);
\ No newline at end of file
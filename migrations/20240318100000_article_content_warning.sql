@@ -0,0 +1 @@
+ALTER TABLE articles ADD COLUMN content_warning TEXT;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE articles ADD COLUMN license TEXT;
\ No newline at end of file
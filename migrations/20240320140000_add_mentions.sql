@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS mentions
+(
+    id              TEXT PRIMARY KEY NOT NULL,
+    article         TEXT NOT NULL,
+    source          TEXT NOT NULL,
+    kind            TEXT NOT NULL,
+    received        DATETIME NOT NULL,
+    UNIQUE(article, source),
+    FOREIGN KEY(article) REFERENCES articles(id)
+);
\ No newline at end of file
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS article_authors
+(
+    article         TEXT NOT NULL,
+    author          TEXT NOT NULL,
+    position        INTEGER NOT NULL,
+    PRIMARY KEY(article, author),
+    FOREIGN KEY(article) REFERENCES articles(id)
+);
\ No newline at end of file
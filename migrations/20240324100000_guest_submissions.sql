@@ -0,0 +1,2 @@
+ALTER TABLE articles ADD COLUMN status TEXT NOT NULL DEFAULT 'published';
+ALTER TABLE secrets ADD COLUMN scope TEXT NOT NULL DEFAULT 'publish';
\ No newline at end of file
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS idempotency_keys
+(
+    key             TEXT PRIMARY KEY NOT NULL,
+    article         TEXT NOT NULL,
+    created         DATETIME NOT NULL,
+    FOREIGN KEY(article) REFERENCES articles(id)
+);
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE articles ADD COLUMN updated DATETIME;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE comments ADD COLUMN ip_hash TEXT;
\ No newline at end of file
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS article_aliases
+(
+    path            TEXT PRIMARY KEY NOT NULL,
+    article         TEXT NOT NULL,
+    FOREIGN KEY(article) REFERENCES articles(id)
+);
\ No newline at end of file
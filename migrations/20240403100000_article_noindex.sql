@@ -0,0 +1 @@
+ALTER TABLE articles ADD COLUMN noindex BOOLEAN NOT NULL DEFAULT FALSE;
\ No newline at end of file
@@ -0,0 +1,16 @@
+CREATE VIRTUAL TABLE IF NOT EXISTS articles_fts USING fts5(title, content, content='articles', content_rowid='rowid');
+
+INSERT INTO articles_fts ( rowid, title, content ) SELECT rowid, title, content FROM articles;
+
+CREATE TRIGGER IF NOT EXISTS articles_fts_insert AFTER INSERT ON articles BEGIN
+    INSERT INTO articles_fts ( rowid, title, content ) VALUES (new.rowid, new.title, new.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS articles_fts_delete AFTER DELETE ON articles BEGIN
+    INSERT INTO articles_fts ( articles_fts, rowid, title, content ) VALUES ('delete', old.rowid, old.title, old.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS articles_fts_update AFTER UPDATE ON articles BEGIN
+    INSERT INTO articles_fts ( articles_fts, rowid, title, content ) VALUES ('delete', old.rowid, old.title, old.content);
+    INSERT INTO articles_fts ( rowid, title, content ) VALUES (new.rowid, new.title, new.content);
+END;
\ No newline at end of file
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS media
+(
+    id              TEXT PRIMARY KEY NOT NULL,
+    filename        TEXT NOT NULL,
+    content_type    TEXT NOT NULL,
+    size            INTEGER NOT NULL,
+    data            BLOB NOT NULL,
+    uploaded        DATETIME NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE secrets ADD COLUMN expires_at DATETIME;
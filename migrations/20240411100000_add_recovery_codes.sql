@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS recovery_codes
+(
+    code_hash       TEXT PRIMARY KEY NOT NULL,
+    used_at         DATETIME
+);
@@ -0,0 +1 @@
+ALTER TABLE articles ADD COLUMN expires_at DATETIME;
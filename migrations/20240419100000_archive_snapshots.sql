@@ -0,0 +1,6 @@
+CREATE TABLE archive_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    article TEXT NOT NULL REFERENCES articles(id),
+    url TEXT NOT NULL,
+    created DATETIME NOT NULL
+);
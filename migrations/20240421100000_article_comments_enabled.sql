@@ -0,0 +1 @@
+ALTER TABLE articles ADD COLUMN comments_enabled BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,7 @@
+CREATE TABLE comment_subscriptions (
+    token TEXT PRIMARY KEY NOT NULL,
+    article TEXT NOT NULL REFERENCES articles(id),
+    email TEXT NOT NULL,
+    created DATETIME NOT NULL,
+    UNIQUE(article, email)
+);
@@ -0,0 +1,3 @@
+-- No foreign key, so articles still point to their secret after it is revoked
+ALTER TABLE articles ADD COLUMN created_by INTEGER;
+CREATE INDEX articles_created_by ON articles ( created_by );
@@ -0,0 +1,9 @@
+-- Feed items that drafts were created from, so each is only ingested once
+CREATE TABLE IF NOT EXISTS ingested_items
+(
+    feed        TEXT NOT NULL,
+    guid        TEXT NOT NULL,
+    article     TEXT NOT NULL,
+    created     DATETIME NOT NULL,
+    PRIMARY KEY ( feed, guid )
+);
@@ -0,0 +1,10 @@
+-- Significant updates to articles, announced in the changes feed
+CREATE TABLE IF NOT EXISTS article_changes
+(
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    article     TEXT NOT NULL,
+    note        TEXT NOT NULL,
+    created     DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS article_changes_created ON article_changes ( created );
@@ -0,0 +1,16 @@
+-- Every version of an article's title and content, with an optional summary of the edit
+CREATE TABLE IF NOT EXISTS article_revisions
+(
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    article     TEXT NOT NULL,
+    title       TEXT NOT NULL,
+    content     TEXT NOT NULL,
+    summary     TEXT,
+    created     DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS article_revisions_article ON article_revisions ( article );
+
+-- The history of existing articles starts with their current version
+INSERT INTO article_revisions ( article, title, content, created )
+SELECT id, title, content, COALESCE(updated, published) FROM articles;
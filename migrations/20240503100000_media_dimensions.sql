@@ -0,0 +1,3 @@
+-- Filled in on upload, or when the image is first shown for older uploads
+ALTER TABLE media ADD COLUMN width INTEGER;
+ALTER TABLE media ADD COLUMN height INTEGER;
@@ -0,0 +1,2 @@
+-- Overrides the blog's policy for HTML in this article: 'escape', 'sanitize' or 'allow'
+ALTER TABLE articles ADD COLUMN html TEXT;
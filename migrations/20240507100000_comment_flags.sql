@@ -0,0 +1,12 @@
+-- Readers reporting a comment, at most once per address
+CREATE TABLE IF NOT EXISTS comment_flags
+(
+    comment     TEXT NOT NULL,
+    ip_hash     TEXT NOT NULL,
+    created     DATETIME NOT NULL,
+    PRIMARY KEY (comment, ip_hash),
+    FOREIGN KEY(comment) REFERENCES comments(id)
+);
+
+-- Comments reported often enough are hidden until they are reviewed
+ALTER TABLE comments ADD COLUMN hidden BOOLEAN NOT NULL DEFAULT FALSE;
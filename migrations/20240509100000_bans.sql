@@ -0,0 +1,10 @@
+-- Commenters whose comments are discarded. Addresses and emails are stored as salted
+-- hashes like everywhere else, names as case-insensitive glob patterns.
+CREATE TABLE IF NOT EXISTS bans
+(
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind        TEXT NOT NULL,
+    value       TEXT NOT NULL,
+    reason      TEXT,
+    created     DATETIME NOT NULL
+);
@@ -0,0 +1,7 @@
+-- Proof of work challenges that were already used for a comment, so a solution can't be
+-- replayed. Rows are removed once the challenge has expired anyway.
+CREATE TABLE IF NOT EXISTS spent_challenges
+(
+    challenge   TEXT PRIMARY KEY NOT NULL,
+    spent       DATETIME NOT NULL
+);
@@ -0,0 +1,13 @@
+-- Idempotency keys only identify retries from the same client, so they are scoped by the
+-- secret that sent them. Keys only matter for a few moments after a publish attempt, so
+-- the old ones are not carried over.
+DROP TABLE idempotency_keys;
+CREATE TABLE idempotency_keys
+(
+    secret          INTEGER NOT NULL,
+    key             TEXT NOT NULL,
+    article         TEXT NOT NULL,
+    created         DATETIME NOT NULL,
+    PRIMARY KEY (secret, key),
+    FOREIGN KEY(article) REFERENCES articles(id)
+);
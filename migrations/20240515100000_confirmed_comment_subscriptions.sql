@@ -0,0 +1,3 @@
+-- Notifications only go to addresses that confirmed their subscription from an email.
+-- Earlier subscriptions were never confirmed, so they are pending as well.
+ALTER TABLE comment_subscriptions ADD COLUMN confirmed BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,46 @@
+-- Counts every change to articles, their tags and their authors, so cached feeds and pages
+-- notice all of them, including deletions and changes made from the command line
+CREATE TABLE IF NOT EXISTS content_version
+(
+    id      INTEGER PRIMARY KEY NOT NULL CHECK (id = 0),
+    version INTEGER NOT NULL,
+    changed DATETIME NOT NULL
+);
+
+INSERT INTO content_version ( id, version, changed ) VALUES (0, 0, CURRENT_TIMESTAMP);
+
+CREATE TRIGGER IF NOT EXISTS content_version_articles_insert AFTER INSERT ON articles BEGIN
+    UPDATE content_version SET version = version + 1, changed = CURRENT_TIMESTAMP;
+END;
+
+CREATE TRIGGER IF NOT EXISTS content_version_articles_update AFTER UPDATE ON articles BEGIN
+    UPDATE content_version SET version = version + 1, changed = CURRENT_TIMESTAMP;
+END;
+
+CREATE TRIGGER IF NOT EXISTS content_version_articles_delete AFTER DELETE ON articles BEGIN
+    UPDATE content_version SET version = version + 1, changed = CURRENT_TIMESTAMP;
+END;
+
+CREATE TRIGGER IF NOT EXISTS content_version_tags_insert AFTER INSERT ON article_tags BEGIN
+    UPDATE content_version SET version = version + 1, changed = CURRENT_TIMESTAMP;
+END;
+
+CREATE TRIGGER IF NOT EXISTS content_version_tags_update AFTER UPDATE ON article_tags BEGIN
+    UPDATE content_version SET version = version + 1, changed = CURRENT_TIMESTAMP;
+END;
+
+CREATE TRIGGER IF NOT EXISTS content_version_tags_delete AFTER DELETE ON article_tags BEGIN
+    UPDATE content_version SET version = version + 1, changed = CURRENT_TIMESTAMP;
+END;
+
+CREATE TRIGGER IF NOT EXISTS content_version_authors_insert AFTER INSERT ON article_authors BEGIN
+    UPDATE content_version SET version = version + 1, changed = CURRENT_TIMESTAMP;
+END;
+
+CREATE TRIGGER IF NOT EXISTS content_version_authors_update AFTER UPDATE ON article_authors BEGIN
+    UPDATE content_version SET version = version + 1, changed = CURRENT_TIMESTAMP;
+END;
+
+CREATE TRIGGER IF NOT EXISTS content_version_authors_delete AFTER DELETE ON article_authors BEGIN
+    UPDATE content_version SET version = version + 1, changed = CURRENT_TIMESTAMP;
+END;
@@ -0,0 +1,182 @@
+//! Requests in the combined log format, for log analyzers like GoAccess or fail2ban
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::State,
+    http::{header, HeaderMap, Request as HttpRequest},
+    middleware::Next,
+    response::Response as AxumResponse,
+    Extension,
+};
+use chrono::Utc;
+use miette::IntoDiagnostic;
+
+use crate::{datetime, net::ClientIp, AccessLogConfig, ServerConfig};
+
+/// The open log file and how much has been written to it
+struct Writer {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    keep: usize,
+}
+
+impl Writer {
+    fn open(path: &Path) -> std::io::Result<(File, u64)> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok((file, size))
+    }
+
+    /// Moves `access.log` to `access.log.1`, `access.log.1` to `access.log.2` and so on,
+    /// dropping the oldest one, and starts a new log
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{n}", self.path.display()));
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                if rotated(n).exists() {
+                    fs::rename(rotated(n), rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        (self.file, self.size) = Self::open(&self.path)?;
+        Ok(())
+    }
+
+    fn write(&mut self, line: &str) -> std::io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// The access log, if one is configured. It is kept apart from the application's log.
+#[derive(Default)]
+pub struct AccessLog {
+    writer: Option<Mutex<Writer>>,
+}
+
+impl AccessLog {
+    pub fn open(config: Option<&AccessLogConfig>) -> miette::Result<Self> {
+        let Some(config) = config else {
+            return Ok(Self::default());
+        };
+        let (file, size) = Writer::open(&config.path).into_diagnostic()?;
+        Ok(Self {
+            writer: Some(Mutex::new(Writer {
+                path: config.path.clone(),
+                file,
+                size,
+                max_size: config.max_size,
+                keep: config.keep,
+            })),
+        })
+    }
+
+    fn write(&self, line: &str) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+        let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writer.write(line) {
+            tracing::error!("could not write the access log: {e}");
+        }
+    }
+}
+
+/// Quotes a header value the way Apache does, so a client can't forge log fields
+fn quoted(value: Option<&str>) -> String {
+    match value {
+        Some(value) => {
+            let mut quoted = String::with_capacity(value.len() + 2);
+            quoted.push('"');
+            for c in value.chars() {
+                match c {
+                    '"' | '\\' => {
+                        quoted.push('\\');
+                        quoted.push(c);
+                    }
+                    c if c.is_control() => quoted.push_str(&c.escape_default().to_string()),
+                    c => quoted.push(c),
+                }
+            }
+            quoted.push('"');
+            quoted
+        }
+        None => "\"-\"".to_string(),
+    }
+}
+
+fn header_value<'a>(headers: &'a HeaderMap, name: header::HeaderName) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// A line in the combined log format:
+/// `host ident user [time] "request" status bytes "referer" "user agent"`
+fn line(
+    ip: IpAddr,
+    request: &str,
+    status: u16,
+    bytes: Option<u64>,
+    referer: Option<&str>,
+    user_agent: Option<&str>,
+) -> String {
+    let time = datetime::local(Utc::now().naive_utc()).format("%d/%b/%Y:%H:%M:%S %z");
+    format!(
+        "{ip} - - [{time}] {} {status} {} {} {}\n",
+        quoted(Some(request)),
+        bytes.map_or("-".to_string(), |bytes| bytes.to_string()),
+        quoted(referer),
+        quoted(user_agent)
+    )
+}
+
+/// Writes every request to the access log once it has been answered
+pub async fn record(
+    State(config): State<ServerConfig>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    request: HttpRequest<Body>,
+    next: Next,
+) -> AxumResponse {
+    if config.access_logger.writer.is_none() {
+        return next.run(request).await;
+    }
+
+    let target = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |target| target.as_str());
+    let request_line = format!("{} {target} {:?}", request.method(), request.version());
+    let referer = header_value(request.headers(), header::REFERER).map(str::to_string);
+    let user_agent = header_value(request.headers(), header::USER_AGENT).map(str::to_string);
+
+    let response = next.run(request).await;
+    // Streamed bodies only have a length once they have been sent
+    let bytes = response.body().size_hint().exact().or_else(|| {
+        header_value(response.headers(), header::CONTENT_LENGTH).and_then(|l| l.parse().ok())
+    });
+    config.access_logger.write(&line(
+        ip,
+        &request_line,
+        response.status().as_u16(),
+        bytes,
+        referer.as_deref(),
+        user_agent.as_deref(),
+    ));
+    response
+}
@@ -0,0 +1,83 @@
+use itertools::Itertools;
+use miette::IntoDiagnostic;
+use sqlx::SqliteConnection;
+
+use crate::article::url_of;
+
+/// Turns an alias into the path it is served at. Paths are kept as they are,
+/// anything else is taken to be an old article URL.
+pub fn normalize(alias: &str) -> String {
+    let alias = alias.trim();
+    if alias.starts_with('/') {
+        alias.trim_end_matches('/').to_string()
+    } else {
+        format!("/article/{alias}")
+    }
+}
+
+/// Replaces the aliases of an article. An alias that belonged to another article is moved.
+pub async fn set_aliases(
+    article: &str,
+    aliases: &[String],
+    conn: &mut SqliteConnection,
+) -> miette::Result<()> {
+    sqlx::query!("DELETE FROM article_aliases WHERE article = ?", article)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+
+    for path in aliases
+        .iter()
+        .filter(|a| !a.trim().is_empty())
+        .map(|a| normalize(a))
+        .unique()
+    {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO article_aliases ( path, article ) VALUES (?1, ?2)",
+            path,
+            article
+        )
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    }
+
+    Ok(())
+}
+
+/// Adds a single alias to an article, keeping its other aliases
+pub async fn add(article: &str, alias: &str, conn: &mut SqliteConnection) -> miette::Result<()> {
+    let path = normalize(alias);
+    sqlx::query!(
+        "INSERT OR REPLACE INTO article_aliases ( path, article ) VALUES (?1, ?2)",
+        path,
+        article
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?;
+    Ok(())
+}
+
+/// Removes an alias from whichever article it belongs to
+pub async fn remove(alias: &str, conn: &mut SqliteConnection) -> miette::Result<()> {
+    let path = normalize(alias);
+    sqlx::query!("DELETE FROM article_aliases WHERE path = ?", path)
+        .execute(conn)
+        .await
+        .into_diagnostic()?;
+    Ok(())
+}
+
+/// The URL of the article an alias path points to, if any
+pub async fn resolve(path: &str, conn: &mut SqliteConnection) -> miette::Result<Option<String>> {
+    let path = path.trim_end_matches('/');
+    Ok(sqlx::query!(
+        "SELECT title, slug FROM articles WHERE status = 'published' AND id = (SELECT article FROM article_aliases WHERE path = ?)",
+        path
+    )
+    .fetch_optional(conn)
+    .await
+    .into_diagnostic()?
+    .map(|r| url_of(&r.title, r.slug.as_deref())))
+}
@@ -0,0 +1,62 @@
+//! Links for deep-linking into long articles: on headings, and from footnotes back to
+//! where they are referenced
+
+use crate::sitemap::escape_xml;
+
+/// How comrak marks the empty link it puts into headings
+const HEADING_ANCHOR: &str = r#" aria-hidden="true" class="anchor" id=""#;
+/// How comrak marks the link at the end of a footnote
+const FOOTNOTE_BACKREF: &str = r#" class="footnote-backref""#;
+
+/// Makes the links comrak puts into headings point at the heading and show the symbol.
+/// comrak leaves the header ID prefix out of their `href`.
+pub fn heading_links(html: &str, symbol: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(marker) = rest.find(HEADING_ANCHOR) {
+        let (Some(start), Some(end)) = (
+            rest[..marker].rfind("<a "),
+            rest[marker..]
+                .find("</a>")
+                .map(|end| marker + end + "</a>".len()),
+        ) else {
+            break;
+        };
+        let id_start = marker + HEADING_ANCHOR.len();
+        let id = rest[id_start..].split('"').next().unwrap_or_default();
+        output.push_str(&rest[..start]);
+        output.push_str(&format!(
+            r##"<a href="#{id}" class="anchor" id="{id}" aria-label="Link to this section">{}</a>"##,
+            escape_xml(symbol)
+        ));
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Shows the symbol on the links from footnotes back to their references, or removes
+/// the links if it is empty
+pub fn footnote_backlinks(html: &str, symbol: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(marker) = rest.find(FOOTNOTE_BACKREF) {
+        let (Some(start), Some(text), Some(end)) = (
+            rest[..marker].rfind("<a "),
+            rest[marker..].find('>').map(|text| marker + text + 1),
+            rest[marker..].find("</a>").map(|end| marker + end),
+        ) else {
+            break;
+        };
+        if symbol.is_empty() {
+            output.push_str(rest[..start].trim_end_matches(' '));
+        } else {
+            output.push_str(&rest[..text]);
+            output.push_str(&escape_xml(symbol));
+            output.push_str("</a>");
+        }
+        rest = &rest[end + "</a>".len()..];
+    }
+    output.push_str(rest);
+    output
+}
@@ -0,0 +1,163 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, Request as HttpRequest, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response as AxumResponse},
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::{assets::Assets, AppearanceConfig, ServerConfig};
+
+/// Name of the cookie holding the visitor's choice
+const COOKIE: &str = "color-scheme";
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorScheme {
+    /// Follow the system setting through `prefers-color-scheme`
+    #[default]
+    Auto,
+    Light,
+    Dark,
+}
+
+impl ColorScheme {
+    fn parse(scheme: &str) -> Option<Self> {
+        match scheme {
+            "auto" => Some(Self::Auto),
+            "light" => Some(Self::Light),
+            "dark" => Some(Self::Dark),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Light => "light",
+            Self::Dark => "dark",
+        }
+    }
+
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(';'))
+            .find_map(|c| c.trim().strip_prefix(&format!("{COOKIE}=")))
+            .and_then(Self::parse)
+    }
+}
+
+tokio::task_local! {
+    /// The color scheme of the page being rendered, so the base template can pick its
+    /// stylesheets without every page passing it along
+    static CURRENT: ColorScheme;
+}
+
+/// A stylesheet link of the base template
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Stylesheet {
+    pub href: String,
+    pub media: String,
+}
+
+/// An entry of the color scheme toggle
+pub struct Choice {
+    pub scheme: &'static str,
+    pub label: &'static str,
+    pub active: bool,
+}
+
+impl AppearanceConfig {
+    /// The color scheme of the page being rendered
+    pub fn current(&self) -> ColorScheme {
+        CURRENT.try_with(|scheme| *scheme).unwrap_or(self.default)
+    }
+
+    /// The stylesheets for the current color scheme. With `auto`, the browser picks one.
+    pub fn stylesheets(&self, assets: &Assets) -> Vec<Stylesheet> {
+        let light = assets.url(&self.light);
+        let dark = assets.url(&self.dark);
+        match self.current() {
+            ColorScheme::Auto => vec![
+                Stylesheet {
+                    href: light,
+                    media: "(prefers-color-scheme: light)".to_string(),
+                },
+                Stylesheet {
+                    href: dark,
+                    media: "(prefers-color-scheme: dark)".to_string(),
+                },
+            ],
+            ColorScheme::Light => vec![Stylesheet {
+                href: light,
+                media: "all".to_string(),
+            }],
+            ColorScheme::Dark => vec![Stylesheet {
+                href: dark,
+                media: "all".to_string(),
+            }],
+        }
+    }
+
+    pub fn choices(&self) -> Vec<Choice> {
+        let current = self.current();
+        [
+            (ColorScheme::Auto, "Auto"),
+            (ColorScheme::Light, "Light"),
+            (ColorScheme::Dark, "Dark"),
+        ]
+        .into_iter()
+        .map(|(scheme, label)| Choice {
+            scheme: scheme.as_str(),
+            label,
+            active: scheme == current,
+        })
+        .collect()
+    }
+}
+
+/// Reads the visitor's color scheme from their cookie for the templates rendered while
+/// handling the request
+pub async fn color_scheme(
+    State(config): State<ServerConfig>,
+    request: HttpRequest<Body>,
+    next: Next,
+) -> AxumResponse {
+    let scheme = ColorScheme::from_headers(request.headers()).unwrap_or(config.appearance.default);
+    CURRENT.scope(scheme, next.run(request)).await
+}
+
+/// Remembers the chosen color scheme and goes back to the page the toggle was on
+pub async fn choose_color_scheme(Path(scheme): Path<String>, headers: HeaderMap) -> AxumResponse {
+    let Some(scheme) = ColorScheme::parse(&scheme) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    // Only the path is kept, so this can't redirect to other sites
+    let back = headers
+        .get(header::REFERER)
+        .and_then(|r| r.to_str().ok())
+        .and_then(|r| Url::parse(r).ok())
+        .map_or("/".to_string(), |url| match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        });
+    (
+        StatusCode::SEE_OTHER,
+        [
+            (header::LOCATION, back),
+            (
+                header::SET_COOKIE,
+                format!(
+                    "{COOKIE}={}; Path=/; Max-Age=31536000; SameSite=Lax",
+                    scheme.as_str()
+                ),
+            ),
+        ],
+    )
+        .into_response()
+}
@@ -0,0 +1,62 @@
+use chrono::Utc;
+use miette::{miette, IntoDiagnostic};
+use reqwest::Client;
+use sqlx::SqliteConnection;
+
+/// The Wayback Machine's endpoint for capturing a page on demand
+const SAVE_URL: &str = "https://web.archive.org/save/";
+
+/// Asks the Wayback Machine to capture a page and returns the URL of the snapshot
+pub async fn snapshot(client: &Client, url: &str) -> miette::Result<String> {
+    let response = client
+        .get(format!("{SAVE_URL}{url}"))
+        .send()
+        .await
+        .into_diagnostic()?
+        .error_for_status()
+        .into_diagnostic()?;
+
+    // Depending on its mood the Wayback Machine either redirects to the snapshot or
+    // names it in a header
+    if let Some(location) = response
+        .headers()
+        .get("content-location")
+        .and_then(|l| l.to_str().ok())
+    {
+        return Ok(format!("https://web.archive.org{location}"));
+    }
+    let url = response.url();
+    if url.path().starts_with("/web/") {
+        return Ok(url.to_string());
+    }
+    Err(miette!(
+        "the Wayback Machine did not say where the snapshot is"
+    ))
+}
+
+/// Remembers a snapshot of an article
+pub async fn record(article: &str, url: &str, conn: &mut SqliteConnection) -> miette::Result<()> {
+    let now = Utc::now().naive_utc();
+    sqlx::query!(
+        "INSERT INTO archive_snapshots ( article, url, created ) VALUES (?, ?, ?)",
+        article,
+        url,
+        now
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?;
+    Ok(())
+}
+
+/// The newest snapshot of an article, if it was archived
+pub async fn latest(article: &str, conn: &mut SqliteConnection) -> miette::Result<Option<String>> {
+    Ok(sqlx::query!(
+        "SELECT url FROM archive_snapshots WHERE article = ? ORDER BY created DESC LIMIT 1",
+        article
+    )
+    .fetch_optional(conn)
+    .await
+    .into_diagnostic()?
+    .map(|r| r.url))
+}
@@ -1,23 +1,86 @@
+use std::collections::BTreeMap;
+
 use askama::Template;
 use chrono::{NaiveDateTime, TimeZone, Utc};
-use comrak::Options;
-use figment::{
-    providers::{Format, Toml},
-    Figment,
+use comrak::{nodes::NodeValue, Arena, Options};
+use rss::{
+    extension::{dublincore::DublinCoreExtension, ExtensionBuilder},
+    Category, Guid, Item,
 };
-use itertools::Itertools;
-use rss::{Guid, Item};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{comment::Comment, Config, ServerConfig};
+use crate::{
+    comment::{self, Challenge, Comment},
+    datetime,
+    mention::Mention,
+    request::ArticleFields,
+    revision::EditNote,
+    sitemap::absolute_url,
+    HtmlConfig, ServerConfig,
+};
+
+/// Status of articles that are visible on the blog
+pub const PUBLISHED: &str = "published";
+/// Status of guest submissions that still need to be approved
+pub const SUBMITTED: &str = "submitted";
+/// Status of articles that are still being written
+pub const DRAFT: &str = "draft";
+/// Status of articles that were unpublished when they expired
+pub const EXPIRED: &str = "expired";
+
+/// Marks the end of the teaser, e.g. `<!--more-->` on a line of its own
+const MORE: &str = "<!--more-->";
+/// Words after which the automatic teaser ends with the current block
+const EXCERPT_WORDS: usize = 60;
+
+fn options() -> Options {
+    let mut options = Options::default();
+    options.extension.footnotes = true;
+    options.extension.table = true;
+    options
+}
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Article {
     pub id: String,
     pub title: String,
     pub content: String,
+    #[serde(with = "datetime::rfc3339")]
     pub published: NaiveDateTime,
+    /// Where the article was originally published, if not here
+    pub canonical_url: Option<String>,
+    pub lang: Option<String>,
+    /// ID of the original article if this is a translation
+    pub translation_of: Option<String>,
+    /// Shown instead of the article until the reader chooses to continue
+    pub content_warning: Option<String>,
+    /// The license of the article, if it differs from the blog's default license
+    pub license: Option<String>,
+    /// Either `published`, `draft`, `expired`, or `submitted` for guest posts awaiting approval
+    pub status: String,
+    /// When the article was last edited, if ever
+    #[serde(default, with = "datetime::rfc3339::option")]
+    pub updated: Option<NaiveDateTime>,
+    /// Asks search engines not to index the article
+    pub noindex: bool,
+    /// The URL of the article, if it was set instead of being derived from the title
+    pub slug: Option<String>,
+    /// Shown in article lists instead of an excerpt
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// When the article is unpublished, for time-limited announcements
+    #[serde(default, with = "datetime::rfc3339::option")]
+    pub expires_at: Option<NaiveDateTime>,
+    /// Whether readers may comment, unless comments are disabled for the whole blog
+    #[serde(default = "Article::default_comments_enabled")]
+    pub comments_enabled: bool,
+    /// The ID of the secret the article was created with
+    #[serde(default)]
+    pub created_by: Option<i64>,
+    /// Overrides the blog's policy for HTML in the article
+    #[serde(default)]
+    pub html: Option<String>,
 }
 
 impl Article {
@@ -27,55 +90,184 @@ impl Article {
             title,
             content,
             published: Utc::now().naive_utc(),
+            canonical_url: None,
+            lang: None,
+            translation_of: None,
+            content_warning: None,
+            license: None,
+            status: PUBLISHED.to_string(),
+            updated: None,
+            noindex: false,
+            slug: None,
+            summary: None,
+            expires_at: None,
+            comments_enabled: true,
+            created_by: None,
+            html: None,
+        }
+    }
+
+    fn default_comments_enabled() -> bool {
+        true
+    }
+
+    /// Applies the given optional fields. Empty strings remove a field.
+    pub fn apply(&mut self, fields: &ArticleFields) {
+        let set = |field: &mut Option<String>, value: &Option<String>| {
+            if let Some(value) = value {
+                *field = (!value.is_empty()).then(|| value.clone());
+            }
+        };
+        set(&mut self.canonical_url, &fields.canonical_url);
+        set(&mut self.lang, &fields.lang);
+        set(&mut self.translation_of, &fields.translation_of);
+        set(&mut self.content_warning, &fields.content_warning);
+        set(&mut self.license, &fields.license);
+        set(&mut self.summary, &fields.summary);
+        if let Some(policy) = fields.html {
+            self.html = Some(policy.as_str().to_string());
+        }
+        if let Some(noindex) = fields.noindex {
+            self.noindex = noindex;
+        }
+        if let Some(comments_enabled) = fields.comments_enabled {
+            self.comments_enabled = comments_enabled;
+        }
+        if let Some(expires_at) = fields.expires_at {
+            self.expires_at = Some(expires_at);
+            // Moving the expiry into the future brings an expired article back
+            if self.status == EXPIRED && expires_at > Utc::now().naive_utc() {
+                self.status = PUBLISHED.to_string();
+            }
         }
     }
 
     pub fn published(&self) -> String {
-        self.published.format("%d.%m.%Y %H:%M").to_string()
+        datetime::display(self.published)
     }
 
+    /// When the article was last edited, if that shows as different from its publication
+    pub fn updated(&self) -> Option<String> {
+        self.updated
+            .map(datetime::display)
+            .filter(|updated| *updated != self.published())
+    }
+
+    /// The time of the last change to the article, or its publication if it was never edited
+    pub fn last_modified(&self) -> NaiveDateTime {
+        self.updated.unwrap_or(self.published)
+    }
+
+    /// The start of the article shown in lists: its summary if it has one, everything
+    /// before a `<!--more-->` line, or else whole blocks up to about [`EXCERPT_WORDS`] words
     pub fn teaser(&self) -> String {
-        self.content.lines().take(5).join("\n")
+        if let Some(summary) = &self.summary {
+            return summary.clone();
+        }
+        let lines: Vec<_> = self.content.lines().collect();
+        if let Some(more) = lines
+            .iter()
+            .position(|l| l.split_whitespace().collect::<String>() == MORE)
+        {
+            return lines[..more].join("\n");
+        }
+
+        // Blocks are never cut, so code blocks and tables stay intact
+        let arena = Arena::new();
+        let root = comrak::parse_document(&arena, &self.content, &options());
+        let mut words = 0;
+        let mut end = 0;
+        for block in root.children() {
+            let position = block.data.borrow().sourcepos;
+            end = position.end.line.min(lines.len());
+            words += lines[position.start.line - 1..end]
+                .iter()
+                .map(|l| l.split_whitespace().count())
+                .sum::<usize>();
+            if words >= EXCERPT_WORDS {
+                break;
+            }
+        }
+        lines[..end].join("\n")
     }
 
     pub fn url(&self) -> String {
-        to_url(&self.title)
+        url_of(&self.title, self.slug.as_deref())
     }
 
-    pub fn content(&self) -> String {
-        let mut options = Options::default();
-        options.extension.footnotes = true;
-        options.extension.table = true;
-        comrak::markdown_to_html(&self.content, &options)
+    /// The URLs of all images referenced in the article
+    pub fn images(&self) -> Vec<String> {
+        let arena = Arena::new();
+        let root = comrak::parse_document(&arena, &self.content, &Options::default());
+        root.descendants()
+            .filter_map(|node| match &node.data.borrow().value {
+                NodeValue::Image(link) => Some(link.url.clone()),
+                _ => None,
+            })
+            .collect()
     }
-}
 
-impl From<Article> for Item {
-    fn from(article: Article) -> Self {
-        let config: Config = Figment::new()
-            .merge(Toml::file("blog.toml"))
-            .extract()
-            .unwrap();
-        let server = config.server.unwrap();
-        let domain = server.domain.unwrap();
-        let content = article.content();
-
-        let url = article.url();
+    /// The article's HTML as it appears in feeds, following the same policy as the page
+    pub fn content(&self, html: &HtmlConfig) -> String {
+        html.render(self, options())
+    }
+
+    /// Turns the article into a feed item credited to the given authors and filed under
+    /// the given tags
+    pub fn into_item(self, authors: Vec<String>, tags: Vec<String>, config: &ServerConfig) -> Item {
+        let url = absolute_url(
+            config.domain.as_deref().unwrap_or_default(),
+            &format!("/article/{}", self.url()),
+        );
+        let content = self.content(&config.html);
+        let updated = ExtensionBuilder::default()
+            .name("atom:updated")
+            .value(Some(
+                Utc.from_utc_datetime(&self.last_modified()).to_rfc3339(),
+            ))
+            .build();
+        let license = self.license.or(config.license.clone());
+        let categories = tags
+            .into_iter()
+            .map(|tag| Category {
+                domain: config
+                    .domain
+                    .as_deref()
+                    .map(|d| absolute_url(d, &format!("/tag/{tag}"))),
+                name: tag,
+            })
+            .collect();
+
         Item {
-            title: Some(article.title),
+            title: Some(self.title),
             content: Some(content),
-            author: Some(server.author),
+            author: Some(authors.join(", ")),
             guid: Some(Guid {
-                value: format!("https://{}/article/{}", &domain, url),
+                value: url.clone(),
                 permalink: true,
             }),
-            link: Some(format!("https://{}/article/{}", &domain, url)),
-            pub_date: Some(Utc.from_utc_datetime(&article.published).to_rfc2822()),
+            link: Some(url),
+            pub_date: Some(Utc.from_utc_datetime(&self.published).to_rfc2822()),
+            categories,
+            dublin_core_ext: Some(DublinCoreExtension {
+                creators: authors,
+                rights: license.into_iter().collect(),
+                ..Default::default()
+            }),
+            extensions: BTreeMap::from([(
+                "atom".to_string(),
+                BTreeMap::from([("updated".to_string(), vec![updated])]),
+            )]),
             ..Default::default()
         }
     }
 }
 
+/// The URL of an article: its slug if one was set, or else derived from its title
+pub fn url_of(title: &str, slug: Option<&str>) -> String {
+    slug.map(str::to_string).unwrap_or_else(|| to_url(title))
+}
+
 pub fn to_url(title: &str) -> String {
     title
         .chars()
@@ -93,9 +285,114 @@ pub fn to_url(title: &str) -> String {
 
 #[derive(Clone, Template)]
 #[template(path = "article.html")]
-pub struct ArticleTemplate<'a> {
+pub struct ArticleTemplate {
     pub config: ServerConfig,
     pub article: Article,
+    pub authors: Vec<String>,
+    pub tags: Vec<String>,
+    pub translations: Vec<Translation>,
     pub comments: Vec<Comment>,
-    pub options: &'a Options,
+    pub challenge: Challenge,
+    pub mentions: Vec<Mention>,
+    /// The rendered content, after the plugins had their say
+    pub content: String,
+    /// The newest copy in the Wayback Machine
+    pub snapshot: Option<String>,
+    /// Summarized edits, if they are shown
+    pub edit_notes: Vec<EditNote>,
+    pub csrf: String,
+}
+
+impl ArticleTemplate {
+    /// The article's license, falling back to the blog's default license
+    pub fn license(&self) -> Option<&String> {
+        self.article
+            .license
+            .as_ref()
+            .or(self.config.license.as_ref())
+    }
+
+    pub fn comment_count(&self) -> String {
+        comment::count_label(self.comments.len() as i64)
+    }
+
+    /// Whether the comment form is shown
+    pub fn comments_open(&self) -> bool {
+        self.config.comments.enabled && self.article.comments_enabled
+    }
+
+    /// Link to the page listing all articles by an author
+    pub fn author_url(&self, author: &str) -> String {
+        format!("/author/{}", to_url(author))
+    }
+
+    /// The link to share the article by: its canonical URL, or else its URL on the blog
+    pub fn permalink(&self) -> String {
+        if let Some(url) = &self.article.canonical_url {
+            return url.clone();
+        }
+        self.on_blog(&self.article.url())
+    }
+
+    /// Language and URL of every language version including this one, for `hreflang`
+    /// alternates, which search engines only follow if they link back to each other
+    pub fn alternates(&self) -> Vec<(&str, String)> {
+        if self.translations.is_empty() {
+            return Vec::new();
+        }
+        let own = self
+            .article
+            .lang
+            .as_deref()
+            .map(|lang| (lang, self.article.url()));
+        own.into_iter()
+            .chain(self.translations.iter().map(|t| (t.lang.as_str(), t.url())))
+            .map(|(lang, url)| (lang, self.on_blog(&url)))
+            .collect()
+    }
+
+    /// The absolute URL of an article on the blog, if the domain is known
+    fn on_blog(&self, url: &str) -> String {
+        let path = format!("/article/{url}");
+        match &self.config.domain {
+            Some(domain) => absolute_url(domain, &path),
+            None => path,
+        }
+    }
+}
+
+/// A plain version of an article for printing and read-later services, without
+/// navigation or comments
+#[derive(Template)]
+#[template(path = "print.html")]
+pub struct PrintPage {
+    pub config: ServerConfig,
+    pub article: Article,
+    pub authors: Vec<String>,
+    /// The rendered content, after the plugins had their say
+    pub content: String,
+}
+
+impl PrintPage {
+    /// The article's license, falling back to the blog's default license
+    pub fn license(&self) -> Option<&String> {
+        self.article
+            .license
+            .as_ref()
+            .or(self.config.license.as_ref())
+    }
+}
+
+/// Another language version of an article
+#[derive(Clone)]
+pub struct Translation {
+    pub title: String,
+    pub slug: Option<String>,
+    pub lang: String,
+}
+
+impl Translation {
+    pub fn url(&self) -> String {
+        url_of(&self.title, self.slug.as_deref())
+    }
 }
@@ -0,0 +1,96 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderValue, Request as HttpRequest, Uri},
+    middleware::Next,
+    response::Response,
+};
+use miette::IntoDiagnostic;
+use sha2::{Digest, Sha256};
+
+/// Fingerprinted names of the files in `/static`, so they can be cached forever
+/// and still be refreshed as soon as they change
+#[derive(Default)]
+pub struct Assets {
+    /// Original path to fingerprinted path, both relative to the static directory
+    fingerprinted: HashMap<String, String>,
+    /// The reverse mapping, to find the file a fingerprinted path refers to
+    originals: HashMap<String, String>,
+}
+
+impl Assets {
+    /// Hashes every file in the given directory
+    pub fn load(dir: &str) -> miette::Result<Self> {
+        let mut assets = Self::default();
+        let mut pending = vec![PathBuf::from(dir)];
+        while let Some(current) = pending.pop() {
+            for entry in std::fs::read_dir(&current).into_diagnostic()? {
+                let path = entry.into_diagnostic()?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+
+                let contents = std::fs::read(&path).into_diagnostic()?;
+                let hash = hex::encode(Sha256::digest(contents));
+                let Ok(relative) = path.strip_prefix(dir) else {
+                    continue;
+                };
+                let original = relative.to_string_lossy().replace('\\', "/");
+                let fingerprinted = fingerprint(relative, &hash[..8]);
+                assets
+                    .originals
+                    .insert(fingerprinted.clone(), original.clone());
+                assets.fingerprinted.insert(original, fingerprinted);
+            }
+        }
+        Ok(assets)
+    }
+
+    /// The URL of a static file, fingerprinted if it exists
+    pub fn url(&self, path: &str) -> String {
+        let path = path.trim_start_matches('/');
+        let path = self.fingerprinted.get(path).map_or(path, String::as_str);
+        format!("/static/{path}")
+    }
+}
+
+/// Inserts the hash before the file extension, e.g. `style.css` becomes `style.1a2b3c4d.css`
+fn fingerprint(path: &Path, hash: &str) -> String {
+    let stem = path.with_extension("");
+    let stem = stem.to_string_lossy().replace('\\', "/");
+    match path.extension() {
+        Some(extension) => format!("{stem}.{hash}.{}", extension.to_string_lossy()),
+        None => format!("{stem}.{hash}"),
+    }
+}
+
+/// Serves fingerprinted paths from the original files, marking them as immutable
+pub async fn serve_fingerprinted(
+    State(assets): State<Arc<Assets>>,
+    mut request: HttpRequest<Body>,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().trim_start_matches('/');
+    let Some(original) = assets.originals.get(path) else {
+        return next.run(request).await;
+    };
+
+    if let Ok(uri) = format!("/{original}").parse::<Uri>() {
+        *request.uri_mut() = uri;
+    }
+    let mut response = next.run(request).await;
+    if response.status().is_success() {
+        response.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("public, max-age=31536000, immutable"),
+        );
+    }
+    response
+}
@@ -0,0 +1,189 @@
+//! Files sent along with an article in a `multipart/form-data` API request, so a post
+//! folder with its images can be published in one go
+
+use std::collections::HashMap;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, Multipart},
+    http::{HeaderMap, Request as HttpRequest},
+};
+use comrak::{nodes::NodeValue, Arena, Options};
+use sqlx::SqliteConnection;
+use uuid::Uuid;
+
+use crate::{media, request::Request};
+
+/// A file referenced by an article by its path relative to the article
+pub struct Attachment {
+    pub path: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// A path without a leading `./`, so references to the same file compare equal
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./")
+}
+
+/// Whether a link target points into the post folder rather than somewhere on the web
+/// or the blog
+fn is_relative(url: &str) -> bool {
+    !url.is_empty() && !url.starts_with(['/', '#']) && !url.contains(':')
+}
+
+/// The relative paths of all images and links in the markdown, as they are written
+pub fn relative_references(markdown: &str) -> Vec<String> {
+    let arena = Arena::new();
+    let root = comrak::parse_document(&arena, markdown, &Options::default());
+    let mut references: Vec<String> = root
+        .descendants()
+        .filter_map(|node| match &node.data.borrow().value {
+            NodeValue::Image(link) | NodeValue::Link(link) if is_relative(&link.url) => {
+                Some(link.url.clone())
+            }
+            _ => None,
+        })
+        .collect();
+    references.sort();
+    references.dedup();
+    references
+}
+
+/// Whether a relative path stays within the post folder
+pub fn is_inside(path: &str) -> bool {
+    normalize(path).split('/').all(|part| part != "..")
+}
+
+/// Encodes the request and its attachments as `multipart/form-data`, returning the
+/// content type and body
+pub fn encode(request: &[u8], attachments: &[Attachment]) -> (String, Vec<u8>) {
+    let boundary = format!("thoughtkeeper-{}", Uuid::new_v4().simple());
+    let mut body = Vec::new();
+    body.extend(format!("--{boundary}\r\n").as_bytes());
+    body.extend(b"Content-Disposition: form-data; name=\"request\"\r\n");
+    body.extend(b"Content-Type: application/json\r\n\r\n");
+    body.extend(request);
+    for attachment in attachments {
+        let filename = attachment.path.replace('"', "%22");
+        body.extend(format!("\r\n--{boundary}\r\n").as_bytes());
+        body.extend(
+            format!(
+                "Content-Disposition: form-data; name=\"attachment\"; filename=\"{filename}\"\r\n"
+            )
+            .as_bytes(),
+        );
+        body.extend(format!("Content-Type: {}\r\n\r\n", attachment.content_type).as_bytes());
+        body.extend(&attachment.data);
+    }
+    body.extend(format!("\r\n--{boundary}--\r\n").as_bytes());
+    (format!("multipart/form-data; boundary={boundary}"), body)
+}
+
+/// Reads an API request sent as `multipart/form-data`: the request as JSON in the
+/// `request` field and any number of `attachment` fields named by their relative path
+pub async fn decode(
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<(Request, Vec<Attachment>), String> {
+    let mut http_request = HttpRequest::new(Body::from(body));
+    *http_request.headers_mut() = headers.clone();
+    let mut multipart = Multipart::from_request(http_request, &())
+        .await
+        .map_err(|e| e.body_text())?;
+
+    let mut request = None;
+    let mut attachments = Vec::new();
+    while let Some(field) = multipart.next_field().await.map_err(|e| e.body_text())? {
+        let name = field.name().map(str::to_string);
+        match name.as_deref() {
+            Some("request") => {
+                let json = field.bytes().await.map_err(|e| e.body_text())?;
+                request = Some(serde_json::from_slice(&json).map_err(|e| e.to_string())?);
+            }
+            Some("attachment") => {
+                let path = field
+                    .file_name()
+                    .ok_or("an attachment has no file name")?
+                    .to_string();
+                let content_type = field
+                    .content_type()
+                    .unwrap_or("application/octet-stream")
+                    .to_string();
+                let data = field.bytes().await.map_err(|e| e.body_text())?.to_vec();
+                attachments.push(Attachment {
+                    path,
+                    content_type,
+                    data,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    let request = request.ok_or("the request field is missing")?;
+    Ok((request, attachments))
+}
+
+/// Stores the attachments as media and points the images and links referencing them to
+/// their URLs
+pub async fn store(
+    content: &str,
+    attachments: Vec<Attachment>,
+    conn: &mut SqliteConnection,
+) -> miette::Result<String> {
+    let mut urls = HashMap::new();
+    for attachment in attachments {
+        let media = media::store(
+            &attachment.path,
+            &attachment.content_type,
+            attachment.data,
+            conn,
+        )
+        .await?;
+        urls.insert(normalize(&attachment.path).to_string(), media.url());
+    }
+
+    let mut content = content.to_string();
+    for path in relative_references(&content) {
+        if let Some(url) = urls.get(normalize(&path)) {
+            content = retarget(&content, &path, url);
+        }
+    }
+    Ok(content)
+}
+
+/// Replaces `path` with `url` where it is the whole target of an inline link or a reference
+/// definition, so `img.png` doesn't also match in `img.png.bak`
+fn retarget(content: &str, path: &str, url: &str) -> String {
+    let inline = |c: Option<char>| c.is_some_and(|c| c == ')' || c.is_whitespace());
+    let definition = |c: Option<char>| c.map_or(true, char::is_whitespace);
+    let bracketed = |c: Option<char>| c == Some('>');
+    let forms: [(&str, &dyn Fn(Option<char>) -> bool); 4] = [
+        ("](", &inline),
+        ("](<", &bracketed),
+        ("]: ", &definition),
+        ("]: <", &bracketed),
+    ];
+
+    let mut content = content.to_string();
+    for (before, ends) in forms {
+        let pattern = format!("{before}{path}");
+        let mut retargeted = String::with_capacity(content.len());
+        let mut rest = content.as_str();
+        while let Some(start) = rest.find(&pattern) {
+            let end = start + pattern.len();
+            retargeted.push_str(&rest[..start]);
+            retargeted.push_str(before);
+            if ends(rest[end..].chars().next()) {
+                retargeted.push_str(url);
+            } else {
+                retargeted.push_str(path);
+            }
+            rest = &rest[end..];
+        }
+        retargeted.push_str(rest);
+        content = retargeted;
+    }
+    content
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Header carrying the ID of the secret used to sign a request
+pub const KEY_HEADER: &str = "x-thoughtkeeper-key";
+/// Header carrying the unix timestamp the request was signed at
+pub const TIMESTAMP_HEADER: &str = "x-thoughtkeeper-timestamp";
+/// Header carrying the hex-encoded HMAC-SHA256 signature
+pub const SIGNATURE_HEADER: &str = "x-thoughtkeeper-signature";
+/// How far (in seconds) a signature's timestamp may be from the server's clock
+pub const MAX_CLOCK_SKEW: i64 = 300;
+
+/// The signature headers of an HMAC-authenticated request
+pub struct Signature {
+    pub key_id: i64,
+    pub timestamp: i64,
+    pub signature: String,
+}
+
+impl Signature {
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name| headers.get(name)?.to_str().ok();
+        Some(Signature {
+            key_id: header(KEY_HEADER)?.parse().ok()?,
+            timestamp: header(TIMESTAMP_HEADER)?.parse().ok()?,
+            signature: header(SIGNATURE_HEADER)?.to_string(),
+        })
+    }
+}
+
+fn mac(secret: &str, timestamp: i64, body: &[u8]) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+/// Signs the timestamp and request body with the given secret
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    hex::encode(mac(secret, timestamp, body).finalize().into_bytes())
+}
+
+/// Checks a hex-encoded signature in constant time
+pub fn verify(secret: &str, timestamp: i64, body: &[u8], signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    mac(secret, timestamp, body)
+        .verify_slice(&signature)
+        .is_ok()
+}
+
+/// What requests a secret may make
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Scope {
+    /// Full access to the API
+    Publish,
+    /// Only submit articles, which are hidden until approved
+    Submit,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Publish => "publish",
+            Scope::Submit => "submit",
+        }
+    }
+
+    /// Unknown scopes are treated as the most restricted one
+    pub fn from_db(scope: &str) -> Self {
+        match scope {
+            "publish" => Scope::Publish,
+            _ => Scope::Submit,
+        }
+    }
+}
+
+/// Compares two secrets without leaking the position of the first difference through timing
+pub fn secrets_equal(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+struct FailedAttempts {
+    failures: u32,
+    first_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Tracks failed authentication attempts per client address and locks out
+/// addresses that fail too often.
+#[derive(Default)]
+pub struct Lockout {
+    attempts: Mutex<HashMap<IpAddr, FailedAttempts>>,
+}
+
+impl Lockout {
+    /// Returns how much longer the address is locked out, if it is
+    pub fn remaining(&self, ip: IpAddr) -> Option<Duration> {
+        let mut attempts = self.attempts.lock().unwrap();
+        let locked_until = attempts.get(&ip)?.locked_until?;
+
+        match locked_until.checked_duration_since(Instant::now()) {
+            Some(remaining) => Some(remaining),
+            None => {
+                attempts.remove(&ip);
+                None
+            }
+        }
+    }
+
+    /// Records a failed attempt, counting only failures within `window` of the first one.
+    /// Returns `true` if this locked the address out.
+    pub fn record_failure(
+        &self,
+        ip: IpAddr,
+        max_failures: u32,
+        window: Duration,
+        lockout: Duration,
+    ) -> bool {
+        let now = Instant::now();
+        let mut attempts = self.attempts.lock().unwrap();
+        attempts.retain(|_, attempt| match attempt.locked_until {
+            Some(locked_until) => locked_until > now,
+            None => now.duration_since(attempt.first_failure) < window,
+        });
+        let entry = attempts.entry(ip).or_insert(FailedAttempts {
+            failures: 0,
+            first_failure: now,
+            locked_until: None,
+        });
+        entry.failures += 1;
+
+        if entry.failures >= max_failures {
+            entry.locked_until = Some(now + lockout);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn record_success(&self, ip: IpAddr) {
+        self.attempts.lock().unwrap().remove(&ip);
+    }
+}
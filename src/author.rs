@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use miette::IntoDiagnostic;
+use sqlx::SqliteConnection;
+
+/// Replaces the authors of an article, keeping the given order for the byline
+pub async fn set_authors(
+    article: &str,
+    authors: &[String],
+    conn: &mut SqliteConnection,
+) -> miette::Result<()> {
+    sqlx::query!("DELETE FROM article_authors WHERE article = ?", article)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+
+    for (position, author) in authors
+        .iter()
+        .map(|a| a.trim())
+        .filter(|a| !a.is_empty())
+        .unique()
+        .enumerate()
+    {
+        let position = position as i64;
+        sqlx::query!(
+            "INSERT INTO article_authors ( article, author, position ) VALUES (?1, ?2, ?3)",
+            article,
+            author,
+            position
+        )
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    }
+
+    Ok(())
+}
+
+/// The authors set for an article, in byline order
+pub async fn explicit_authors(
+    article: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Vec<String>> {
+    Ok(sqlx::query!(
+        "SELECT author FROM article_authors WHERE article = ? ORDER BY position",
+        article
+    )
+    .fetch_all(conn)
+    .await
+    .into_diagnostic()?
+    .into_iter()
+    .map(|r| r.author)
+    .collect_vec())
+}
+
+/// The authors of an article, or the blog's author if none were given
+pub async fn authors_for(
+    article: &str,
+    default: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Vec<String>> {
+    let authors = explicit_authors(article, conn).await?;
+
+    if authors.is_empty() {
+        Ok(vec![default.to_string()])
+    } else {
+        Ok(authors)
+    }
+}
+
+/// The explicitly set authors of all articles, keyed by article ID
+pub async fn all_authors(
+    conn: &mut SqliteConnection,
+) -> miette::Result<HashMap<String, Vec<String>>> {
+    Ok(
+        sqlx::query!("SELECT article, author FROM article_authors ORDER BY position")
+            .fetch_all(conn)
+            .await
+            .into_diagnostic()?
+            .into_iter()
+            .map(|r| (r.article, r.author))
+            .into_group_map(),
+    )
+}
+
+/// Every name that appears in a byline, including the blog's author
+pub async fn all_names(default: &str, conn: &mut SqliteConnection) -> miette::Result<Vec<String>> {
+    let mut names = sqlx::query!("SELECT DISTINCT author FROM article_authors")
+        .fetch_all(conn)
+        .await
+        .into_diagnostic()?
+        .into_iter()
+        .map(|r| r.author)
+        .collect_vec();
+    names.push(default.to_string());
+    Ok(names)
+}
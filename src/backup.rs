@@ -0,0 +1,72 @@
+use std::{path::PathBuf, time::Duration};
+
+use chrono::Utc;
+use miette::IntoDiagnostic;
+use sqlx::{Pool, Sqlite, SqliteConnection};
+
+use crate::BackupConfig;
+
+/// Backups are named `articles-<timestamp>.db`, so sorting them by name sorts them by age
+const PREFIX: &str = "articles-";
+const SUFFIX: &str = ".db";
+
+/// Writes a consistent copy of the database into the backup directory. Media lives in
+/// the database, so it is included.
+pub async fn snapshot(
+    config: &BackupConfig,
+    conn: &mut SqliteConnection,
+) -> miette::Result<PathBuf> {
+    tokio::fs::create_dir_all(&config.dir)
+        .await
+        .into_diagnostic()?;
+    let name = format!("{PREFIX}{}{SUFFIX}", Utc::now().format("%Y%m%d-%H%M%S"));
+    let path = config.dir.join(name);
+    let target = path.to_string_lossy().to_string();
+    sqlx::query!("VACUUM INTO ?", target)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    Ok(path)
+}
+
+/// Deletes all but the newest `keep` backups, returning how many were deleted
+pub async fn prune(config: &BackupConfig) -> miette::Result<usize> {
+    let mut backups = Vec::new();
+    let mut entries = tokio::fs::read_dir(&config.dir).await.into_diagnostic()?;
+    while let Some(entry) = entries.next_entry().await.into_diagnostic()? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(PREFIX) && name.ends_with(SUFFIX) {
+            backups.push(entry.path());
+        }
+    }
+    backups.sort();
+
+    let excess = backups.len().saturating_sub(config.keep);
+    for backup in &backups[..excess] {
+        tokio::fs::remove_file(backup).await.into_diagnostic()?;
+    }
+    Ok(excess)
+}
+
+/// Backs up the database in the configured interval until the server shuts down
+pub async fn run(pool: Pool<Sqlite>, config: BackupConfig) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(config.interval)).await;
+        let result = match pool.acquire().await {
+            Ok(mut conn) => snapshot(&config, &mut conn).await,
+            Err(e) => Err(e).into_diagnostic(),
+        };
+        match result {
+            Ok(path) => tracing::info!(path = %path.display(), "backed up the database"),
+            Err(e) => {
+                tracing::error!("backing up the database failed: {e}");
+                continue;
+            }
+        }
+        match prune(&config).await {
+            Ok(0) => (),
+            Ok(deleted) => tracing::info!(deleted, "deleted old backups"),
+            Err(e) => tracing::error!("deleting old backups failed: {e}"),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Commenters whose comments are discarded without telling them
+
+use std::net::IpAddr;
+
+use chrono::{NaiveDateTime, Utc};
+use comfy_table::{Row, Table};
+use miette::{miette, IntoDiagnostic};
+use serde::Deserialize;
+use sqlx::SqliteConnection;
+
+use crate::{comment, datetime, server::connect};
+
+/// What a ban matches against
+#[derive(Clone, Copy, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BanKind {
+    /// The commenter's address
+    Ip,
+    /// The email address given for notifications
+    Email,
+    /// The commenter's name, as a pattern with `*` and `?`, ignoring case
+    Name,
+}
+
+impl BanKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Ip => "ip",
+            Self::Email => "email",
+            Self::Name => "name",
+        }
+    }
+}
+
+pub struct Ban {
+    pub id: i64,
+    pub kind: String,
+    pub value: String,
+    pub reason: Option<String>,
+    pub created: NaiveDateTime,
+}
+
+impl Ban {
+    /// What the ban matches, without the hashes nobody can read anyway
+    pub fn describe(&self) -> String {
+        match self.kind.as_str() {
+            "ip" => format!("Address {}…", &self.value[..8.min(self.value.len())]),
+            "email" => format!("Email {}…", &self.value[..8.min(self.value.len())]),
+            _ => format!("Name {}", self.value),
+        }
+    }
+
+    pub fn created(&self) -> String {
+        datetime::display(self.created)
+    }
+}
+
+async fn insert(
+    kind: BanKind,
+    value: &str,
+    reason: Option<&str>,
+    conn: &mut SqliteConnection,
+) -> miette::Result<i64> {
+    let kind = kind.as_str();
+    let reason = reason.map(str::trim).filter(|r| !r.is_empty());
+    let now = Utc::now().naive_utc();
+    Ok(sqlx::query!(
+        "INSERT INTO bans ( kind, value, reason, created ) VALUES (?, ?, ?, ?)",
+        kind,
+        value,
+        reason,
+        now
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?
+    .last_insert_rowid())
+}
+
+/// Bans an address, email address or name pattern, returning the ID of the ban
+pub async fn add(
+    kind: BanKind,
+    value: &str,
+    reason: Option<&str>,
+    conn: &mut SqliteConnection,
+) -> miette::Result<i64> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(miette!("Nothing to ban"));
+    }
+    let value = match kind {
+        BanKind::Ip => {
+            let ip: IpAddr = value
+                .parse()
+                .map_err(|_| miette!("{value} is not an IP address"))?;
+            comment::hash_ip(ip, conn).await?
+        }
+        BanKind::Email => comment::hash_email(value, conn).await?,
+        BanKind::Name => value.to_string(),
+    };
+    insert(kind, &value, reason, conn).await
+}
+
+/// Bans the address a comment was posted from. Returns whether the comment's address is
+/// known.
+pub async fn ban_author(
+    comment: &str,
+    reason: Option<&str>,
+    conn: &mut SqliteConnection,
+) -> miette::Result<bool> {
+    let ip_hash = sqlx::query!("SELECT ip_hash FROM comments WHERE id = ?", comment)
+        .fetch_optional(&mut *conn)
+        .await
+        .into_diagnostic()?
+        .and_then(|r| r.ip_hash);
+    let Some(ip_hash) = ip_hash else {
+        return Ok(false);
+    };
+    insert(BanKind::Ip, &ip_hash, reason, conn).await?;
+    Ok(true)
+}
+
+/// All bans, newest first
+pub async fn list(conn: &mut SqliteConnection) -> miette::Result<Vec<Ban>> {
+    sqlx::query_as!(Ban, "SELECT * FROM bans ORDER BY id DESC")
+        .fetch_all(conn)
+        .await
+        .into_diagnostic()
+}
+
+/// Lifts a ban. Returns whether it existed.
+pub async fn remove(id: i64, conn: &mut SqliteConnection) -> miette::Result<bool> {
+    let deleted = sqlx::query!("DELETE FROM bans WHERE id = ?", id)
+        .execute(conn)
+        .await
+        .into_diagnostic()?
+        .rows_affected();
+    Ok(deleted > 0)
+}
+
+/// Whether a comment from this address, email address and name is discarded
+pub async fn is_banned(
+    ip_hash: &str,
+    email: Option<&str>,
+    name: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<bool> {
+    let email_hash = match email {
+        Some(email) => Some(comment::hash_email(email, conn).await?),
+        None => None,
+    };
+    let bans = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!: i64" FROM bans
+        WHERE (kind = 'ip' AND value = ?1)
+        OR (kind = 'email' AND value = ?2)
+        OR (kind = 'name' AND lower(?3) GLOB lower(value))"#,
+        ip_hash,
+        email_hash,
+        name
+    )
+    .fetch_one(conn)
+    .await
+    .into_diagnostic()?
+    .count;
+    Ok(bans > 0)
+}
+
+pub async fn ban(kind: BanKind, value: String, reason: Option<String>) -> miette::Result<()> {
+    let mut conn = connect().await?;
+    let id = add(kind, &value, reason.as_deref(), &mut conn).await?;
+    println!("Banned {value} (ban {id})");
+    Ok(())
+}
+
+pub async fn list_bans() -> miette::Result<()> {
+    let mut conn = connect().await?;
+    let mut table = Table::new();
+    table.set_header(Row::from(vec!["ID", "Banned", "Reason", "Since"]));
+    for ban in list(&mut conn).await? {
+        table.add_row([
+            &ban.id.to_string(),
+            &ban.describe(),
+            ban.reason.as_deref().unwrap_or("-"),
+            &ban.created(),
+        ]);
+    }
+    println!("{table}");
+    Ok(())
+}
+
+pub async fn unban(id: i64) -> miette::Result<()> {
+    let mut conn = connect().await?;
+    if !remove(id, &mut conn).await? {
+        return Err(miette!("No ban with id {id} found"));
+    }
+    println!("Lifted ban {id}");
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use std::{collections::HashMap, io::ErrorKind, path::PathBuf};
+
+use chrono::NaiveDateTime;
+use miette::{miette, IntoDiagnostic};
+use serde::{Deserialize, Serialize};
+
+use crate::{article::Article, frontmatter, request::InnerRequest};
+
+/// Articles and unsent edits kept on disk so the client keeps working offline
+#[derive(Serialize, Deserialize, Default)]
+pub struct Cache {
+    /// The newest change included in the cached articles, in server time
+    pub last_sync: NaiveDateTime,
+    pub articles: Vec<Article>,
+    /// Tags and explicitly set authors of the cached articles, by article ID
+    #[serde(default)]
+    pub tags: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub authors: HashMap<String, Vec<String>>,
+    /// Requests made while the server was unreachable, oldest first
+    pub pending: Vec<InnerRequest>,
+}
+
+/// `$XDG_CACHE_HOME/thoughtkeeper/articles.json`, falling back to `~/.cache`
+fn path() -> miette::Result<PathBuf> {
+    let dir = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .ok_or(miette!("could not determine the cache directory"))?;
+    Ok(dir.join("thoughtkeeper").join("articles.json"))
+}
+
+impl Cache {
+    pub async fn load() -> miette::Result<Self> {
+        match tokio::fs::read_to_string(path()?).await {
+            Ok(cache) => serde_json::from_str(&cache).into_diagnostic(),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).into_diagnostic(),
+        }
+    }
+
+    pub async fn save(&self) -> miette::Result<()> {
+        let path = path()?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await.into_diagnostic()?;
+        }
+        let cache = serde_json::to_string(self).into_diagnostic()?;
+        tokio::fs::write(path, cache).await.into_diagnostic()
+    }
+
+    /// Applies the changes reported by the server
+    pub fn merge(
+        &mut self,
+        changed: Vec<Article>,
+        existing: &[String],
+        tags: HashMap<String, Vec<String>>,
+        authors: HashMap<String, Vec<String>>,
+    ) {
+        let keep = |id: &String| existing.contains(id) && !changed.iter().any(|c| &c.id == id);
+        self.articles.retain(|a| keep(&a.id));
+        self.tags.retain(|id, _| keep(id));
+        self.authors.retain(|id, _| keep(id));
+        self.tags.extend(tags);
+        self.authors.extend(authors);
+        if let Some(newest) = changed.iter().map(Article::last_modified).max() {
+            self.last_sync = self.last_sync.max(newest);
+        }
+        self.articles.extend(changed);
+        self.articles.sort_by(|a, b| b.published.cmp(&a.published));
+    }
+
+    /// Renders a cached article in the format `publish` reads
+    pub fn render(&self, article: &Article) -> miette::Result<String> {
+        frontmatter::render(
+            article,
+            self.tags.get(&article.id).map_or(&[][..], Vec::as_slice),
+            self.authors.get(&article.id).map_or(&[][..], Vec::as_slice),
+        )
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Article> {
+        self.articles.iter().find(|a| a.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut Article> {
+        self.articles.iter_mut().find(|a| a.id == id)
+    }
+}
@@ -0,0 +1,89 @@
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use miette::IntoDiagnostic;
+use rss::{ChannelBuilder, Guid, Item};
+use sqlx::SqliteConnection;
+
+use crate::{article::url_of, sitemap::absolute_url, ServerConfig};
+
+/// Changes shown in the feed
+const LIMIT: i64 = 50;
+
+/// A significant update to a published article, described by a note from its author
+pub struct Change {
+    pub id: i64,
+    pub title: String,
+    pub slug: Option<String>,
+    pub note: String,
+    pub created: NaiveDateTime,
+}
+
+impl Change {
+    fn into_item(self, config: &ServerConfig) -> Item {
+        let url = absolute_url(
+            config.domain.as_deref().unwrap_or_default(),
+            &format!("/article/{}", url_of(&self.title, self.slug.as_deref())),
+        );
+        Item {
+            title: Some(format!("Updated: {}", self.title)),
+            description: Some(self.note),
+            guid: Some(Guid {
+                value: format!("{url}#change-{}", self.id),
+                permalink: false,
+            }),
+            link: Some(url),
+            pub_date: Some(Utc.from_utc_datetime(&self.created).to_rfc2822()),
+            ..Default::default()
+        }
+    }
+}
+
+pub async fn record(article: &str, note: &str, conn: &mut SqliteConnection) -> miette::Result<()> {
+    let now = Utc::now().naive_utc();
+    sqlx::query!(
+        "INSERT INTO article_changes ( article, note, created ) VALUES (?1, ?2, ?3)",
+        article,
+        note,
+        now
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?;
+    Ok(())
+}
+
+/// The latest changes to articles that are still published, newest first
+pub async fn recent(conn: &mut SqliteConnection) -> miette::Result<Vec<Change>> {
+    sqlx::query_as!(
+        Change,
+        r#"SELECT article_changes.id AS "id!: i64", articles.title, articles.slug, article_changes.note, article_changes.created
+        FROM article_changes JOIN articles ON articles.id = article_changes.article
+        WHERE articles.status = 'published'
+        ORDER BY article_changes.created DESC LIMIT ?"#,
+        LIMIT
+    )
+    .fetch_all(conn)
+    .await
+    .into_diagnostic()
+}
+
+/// The RSS feed of the given changes
+pub fn feed(changes: Vec<Change>, config: &ServerConfig) -> String {
+    ChannelBuilder::default()
+        .title(format!("{}: Changes", config.blog_name))
+        .link(
+            config
+                .domain
+                .as_ref()
+                .map(|d| absolute_url(d, "/"))
+                .unwrap_or_default(),
+        )
+        .description(format!("Updates to the articles of {}", config.blog_name))
+        .items(
+            changes
+                .into_iter()
+                .map(|change| change.into_item(config))
+                .collect::<Vec<_>>(),
+        )
+        .build()
+        .to_string()
+}
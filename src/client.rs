@@ -1,20 +1,164 @@
-use std::io::Write;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
 
+use chrono::{NaiveDateTime, Utc};
 use comfy_table::{Row, Table};
-use miette::IntoDiagnostic;
-use reqwest::Client;
+use miette::{miette, IntoDiagnostic};
+use percent_encoding::percent_decode_str;
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode};
+use uuid::Uuid;
 
 use crate::{
-    request::{InnerRequest, Request, Response},
-    ClientConfig, Publish,
+    article::Article,
+    attachment::{self, Attachment},
+    auth,
+    cache::Cache,
+    datetime,
+    frontmatter::{self, FrontMatter},
+    request::{ArticleFilter, ArticleMetadata, InnerRequest, Request, Response},
+    sitemap::escape_xml,
+    validation::ValidationError,
+    AuthMode, ClientConfig, Publish,
 };
 
+/// File in an export directory remembering when it was last synced
+const LAST_SYNC: &str = ".last_sync";
+
+/// Sends a request to the server's API, authenticated as configured
+async fn send(conf: &ClientConfig, request: InnerRequest) -> miette::Result<Response> {
+    send_with_attachments(conf, request, &[]).await
+}
+
+/// Sends a request along with files it references, as `multipart/form-data` if there are any
+async fn send_with_attachments(
+    conf: &ClientConfig,
+    request: InnerRequest,
+    attachments: &[Attachment],
+) -> miette::Result<Response> {
+    let secret = match conf.auth {
+        AuthMode::Bearer => Some(conf.secret.clone()),
+        AuthMode::Hmac => None,
+    };
+    let json = serde_json::to_vec(&Request { secret, request }).into_diagnostic()?;
+    let (content_type, body) = if attachments.is_empty() {
+        ("application/json".to_string(), json)
+    } else {
+        attachment::encode(&json, attachments)
+    };
+
+    let mut builder = Client::new()
+        .post(format!("{}/api", conf.addr))
+        .header(CONTENT_TYPE, content_type);
+    if let AuthMode::Hmac = conf.auth {
+        let key_id = conf
+            .key_id
+            .ok_or(miette!("HMAC authentication requires a key_id"))?;
+        let timestamp = Utc::now().timestamp();
+        builder = builder
+            .header(auth::KEY_HEADER, key_id.to_string())
+            .header(auth::TIMESTAMP_HEADER, timestamp.to_string())
+            .header(
+                auth::SIGNATURE_HEADER,
+                auth::sign(&conf.secret, timestamp, &body),
+            );
+    }
+    let builder = builder.body(body);
+
+    let response = builder.send().await.into_diagnostic()?;
+    let status = response.status();
+    // A proxy in front of the server may reject large requests without a JSON answer
+    match response.json().await {
+        Err(_) if status == StatusCode::PAYLOAD_TOO_LARGE => {
+            Err(miette!("The request is too large for the server"))
+        }
+        result => result.into_diagnostic(),
+    }
+}
+
+/// Lists why the server rejected an article
+fn print_invalid(errors: &[ValidationError]) {
+    println!("The article was rejected:");
+    for error in errors {
+        println!("  {}: {}", error.field, error.message);
+    }
+}
+
+/// The one ID starting with the given prefix
+fn unique_id<'a>(prefix: &str, ids: impl Iterator<Item = &'a str>) -> miette::Result<String> {
+    let matches: Vec<_> = ids.filter(|id| id.starts_with(prefix)).collect();
+    match matches.as_slice() {
+        [id] => Ok(id.to_string()),
+        [] => Err(miette!(
+            "No article with an ID starting with {prefix} found"
+        )),
+        _ => Err(miette!(
+            "{prefix} is the start of {} IDs, please type a few more characters",
+            matches.len()
+        )),
+    }
+}
+
+/// Expands a prefix of an article's ID using the list of published articles. Full IDs
+/// are used as they are, so drafts and submissions can still be addressed.
+async fn resolve_id(conf: &ClientConfig, prefix: String) -> miette::Result<String> {
+    if Uuid::parse_str(&prefix).is_ok() {
+        return Ok(prefix);
+    }
+    match send(conf, InnerRequest::ListArticles(ArticleFilter::default())).await? {
+        Response::ArticleMetadata(articles) => {
+            unique_id(&prefix, articles.iter().map(|a| a.id.as_str()))
+        }
+        Response::Error(e) => Err(miette!("An error occured: {e}")),
+        _ => unreachable!(),
+    }
+}
+
+/// How often `publish` tries to reach the server before giving up
+const PUBLISH_ATTEMPTS: u64 = 3;
+
+/// The files in a post folder that the article's images and links refer to by relative
+/// paths. References to files that don't exist there are left as they are, with a warning.
+async fn attachments(dir: &Path, content: &str) -> miette::Result<Vec<Attachment>> {
+    let mut attachments = Vec::new();
+    for path in attachment::relative_references(content) {
+        let file = dir.join(percent_decode_str(&path).decode_utf8_lossy().as_ref());
+        if !attachment::is_inside(&path) || !file.is_file() {
+            println!("Warning: {path} is not a file in the post folder and is published as it is");
+            continue;
+        }
+        attachments.push(Attachment {
+            content_type: content_type(&file).to_string(),
+            data: tokio::fs::read(&file).await.into_diagnostic()?,
+            path,
+        });
+    }
+    Ok(attachments)
+}
+
+/// Publishes a markdown file, or the `index.md` of a post folder, along with the files
+/// it refers to by relative paths
 pub async fn publish(article: Publish, conf: ClientConfig) -> miette::Result<()> {
-    let content = tokio::fs::read_to_string(article.path)
-        .await
-        .into_diagnostic()?;
+    let mut path = PathBuf::from(article.path);
+    if path.is_dir() {
+        path = path.join("index.md");
+    }
+    let source = tokio::fs::read_to_string(&path).await.into_diagnostic()?;
+    let (front_matter, content) = frontmatter::parse(&source)?;
+    let content = content.to_string();
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let attachments = attachments(dir, &content).await?;
 
-    let title = match article.title {
+    let mut fields = front_matter.fields;
+    if !article.tags.is_empty() {
+        fields.tags = Some(article.tags);
+    }
+
+    let title = match article.title.or(front_matter.title) {
         Some(t) => t,
         None => {
             print!("Please enter a title for the post: ");
@@ -25,49 +169,325 @@ pub async fn publish(article: Publish, conf: ClientConfig) -> miette::Result<()>
         }
     };
 
-    let request = Request {
-        secret: conf.secret,
-        request: InnerRequest::CreateArticle { title, content },
+    // Every attempt carries the same key, so retrying after a timeout doesn't publish the
+    // article twice if the first attempt went through after all
+    let request = InnerRequest::CreateArticle {
+        idempotency_key: Some(Uuid::new_v4().to_string()),
+        title,
+        content,
+        force_slug: article.force_slug,
+        fields,
+    };
+    let mut attempt = 1;
+    let response = loop {
+        match send_with_attachments(&conf, request.clone(), &attachments).await {
+            Err(e) if attempt < PUBLISH_ATTEMPTS => {
+                println!("Publishing failed, trying again: {e}");
+                tokio::time::sleep(Duration::from_secs(attempt)).await;
+                attempt += 1;
+            }
+            result => break result?,
+        }
+    };
+    match response {
+        Response::SlugTaken { url, existing } => println!(
+            "The article {existing} already lives at /article/{url}. Change the title or pass --force-slug to publish anyway."
+        ),
+        Response::Invalid(errors) => print_invalid(&errors),
+        Response::Error(err) => println!("An error occured: {err}"),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Pushes queued edits, then fetches the articles that changed since the last sync.
+/// Fails if the server can't be reached, leaving the cache usable offline.
+async fn sync(conf: &ClientConfig, cache: &mut Cache) -> miette::Result<()> {
+    while let Some(request) = cache.pending.first() {
+        match send(conf, request.clone()).await? {
+            Response::Error(e) => println!("A queued edit was rejected: {e}"),
+            Response::Invalid(errors) => print_invalid(&errors),
+            _ => {}
+        }
+        cache.pending.remove(0);
+        cache.save().await?;
+    }
+
+    let request = InnerRequest::ListArticlesSince {
+        timestamp: cache.last_sync,
+    };
+    match send(conf, request).await? {
+        Response::Changes {
+            changed,
+            existing,
+            tags,
+            authors,
+        } => cache.merge(changed, &existing, tags, authors),
+        Response::Error(e) => return Err(miette!("An error occured: {e}")),
+        _ => unreachable!(),
+    }
+    cache.save().await
+}
+
+/// Loads the local cache, bringing it up to date if the server is reachable
+async fn synced_cache(conf: &ClientConfig) -> miette::Result<Cache> {
+    let mut cache = Cache::load().await?;
+    if let Err(e) = sync(conf, &mut cache).await {
+        println!("Working offline, the server could not be reached: {e}");
+    }
+    Ok(cache)
+}
+
+/// Lists the articles passing the filter, which is applied by the server. Offline,
+/// the cached articles are filtered instead.
+pub async fn list(conf: ClientConfig, filter: ArticleFilter) -> miette::Result<()> {
+    let cache = synced_cache(&conf).await?;
+
+    let articles = match send(&conf, InnerRequest::ListArticles(filter.clone())).await {
+        Ok(Response::ArticleMetadata(articles)) => articles,
+        Ok(Response::Error(e)) => return Err(miette!("An error occured: {e}")),
+        Ok(_) => unreachable!(),
+        Err(_) if filter.tag.is_some() => {
+            return Err(miette!("Filtering by tag is not possible offline"))
+        }
+        Err(_) => {
+            let mut articles: Vec<_> = cache
+                .articles
+                .iter()
+                .map(|a| ArticleMetadata {
+                    id: a.id.clone(),
+                    title: a.title.clone(),
+                    published: a.published,
+                    updated: a.updated,
+                    created_by: a.created_by,
+                })
+                .filter(|a| filter.matches(a))
+                .collect();
+            filter.sort.apply(&mut articles);
+            articles
+        }
     };
-    let client = Client::new();
-    let resp = client
-        .post(format!("{}/api", conf.addr))
-        .json(&request)
-        .send()
-        .await
-        .into_diagnostic()?;
 
-    if let Response::Error(err) = resp.json().await.into_diagnostic()? {
-        println!("An error occured: {err}")
+    let mut table = Table::new();
+    table.set_header(Row::from(vec![
+        "ID",
+        "Title",
+        "Publication Date",
+        "Created By",
+    ]));
+    for article in &articles {
+        table.add_row(Row::from(&[
+            &article.id,
+            &article.title,
+            &datetime::display(article.published),
+            &article
+                .created_by
+                .map_or("-".to_string(), |id| format!("secret {id}")),
+        ]));
+    }
+    println!("{table}");
+    if !cache.pending.is_empty() {
+        println!("{} edits are waiting to be sent", cache.pending.len());
     }
 
     Ok(())
 }
 
-pub async fn list(conf: ClientConfig) -> miette::Result<()> {
-    let resp = Client::new()
-        .post(format!("{}/api", conf.addr))
-        .json(&Request {
-            secret: conf.secret,
-            request: InnerRequest::ListArticles,
-        })
-        .send()
+/// Prints an article in the format `publish` reads
+pub async fn get(conf: ClientConfig, id: String) -> miette::Result<()> {
+    let cache = synced_cache(&conf).await?;
+    let id = unique_id(&id, cache.articles.iter().map(|a| a.id.as_str()))?;
+    let article = cache
+        .get(&id)
+        .ok_or(miette!("No article with id {id} found"))?;
+    print!("{}", cache.render(article)?);
+
+    Ok(())
+}
+
+/// Opens an article in `$EDITOR` and sends the changes, or queues them while offline
+pub async fn edit(conf: ClientConfig, id: String) -> miette::Result<()> {
+    let mut cache = synced_cache(&conf).await?;
+    let id = unique_id(&id, cache.articles.iter().map(|a| a.id.as_str()))?;
+    let article = cache
+        .get(&id)
+        .ok_or(miette!("No article with id {id} found"))?;
+
+    let path = std::env::temp_dir().join(format!("thoughtkeeper-{id}.md"));
+    tokio::fs::write(&path, cache.render(article)?)
         .await
         .into_diagnostic()?;
-    let data: Response = resp.json().await.into_diagnostic()?;
-
-    match data {
-        Response::ArticleMetadata(data) => {
-            let mut table = Table::new();
-            table.set_header(Row::from(vec!["ID", "Title", "Publication Date"]));
-            for row in data {
-                table.add_row(Row::from(&[
-                    &row.id,
-                    &row.title,
-                    &row.published.to_string(),
-                ]));
+    let editor = std::env::var("EDITOR").unwrap_or("vi".to_string());
+    let status = tokio::process::Command::new(editor)
+        .arg(&path)
+        .status()
+        .await
+        .into_diagnostic()?;
+    if !status.success() {
+        return Err(miette!(
+            "The editor exited with {status}, discarding the edit"
+        ));
+    }
+    let source = tokio::fs::read_to_string(&path).await.into_diagnostic()?;
+    tokio::fs::remove_file(&path).await.into_diagnostic()?;
+
+    let (front_matter, content) = frontmatter::parse(&source)?;
+    if let Some(tags) = &front_matter.fields.tags {
+        cache.tags.insert(id.clone(), tags.clone());
+    }
+    if let Some(authors) = &front_matter.fields.authors {
+        cache.authors.insert(id.clone(), authors.clone());
+    }
+    let article = cache
+        .get_mut(&id)
+        .ok_or(miette!("No article with id {id} found"))?;
+    if let Some(title) = &front_matter.title {
+        article.title = title.clone();
+    }
+    article.content = content.to_string();
+    article.apply(&front_matter.fields);
+    article.updated = Some(Utc::now().naive_utc());
+
+    let request = InnerRequest::UpdateArticle {
+        id,
+        title: front_matter.title,
+        content: Some(content.to_string()),
+        change_note: None,
+        fields: front_matter.fields,
+    };
+    match send(&conf, request.clone()).await {
+        Ok(Response::Error(e)) => println!("An error occured: {e}"),
+        Ok(Response::Invalid(errors)) => print_invalid(&errors),
+        Ok(_) => {}
+        Err(_) => {
+            println!("The server could not be reached, the edit will be sent with the next sync");
+            cache.pending.push(request);
+        }
+    }
+    cache.save().await
+}
+
+/// Mirrors all articles into a directory as markdown files, only fetching the articles
+/// that changed since the last export into the same directory
+pub async fn export(conf: ClientConfig, dir: PathBuf) -> miette::Result<()> {
+    tokio::fs::create_dir_all(&dir).await.into_diagnostic()?;
+    let marker = dir.join(LAST_SYNC);
+    let timestamp = match tokio::fs::read_to_string(&marker).await {
+        Ok(last) => last.trim().parse().into_diagnostic()?,
+        Err(_) => NaiveDateTime::default(),
+    };
+
+    let (changed, existing, tags, authors) =
+        match send(&conf, InnerRequest::ListArticlesSince { timestamp }).await? {
+            Response::Changes {
+                changed,
+                existing,
+                tags,
+                authors,
+            } => (changed, existing, tags, authors),
+            Response::Error(e) => return Err(miette!("An error occured: {e}")),
+            _ => unreachable!(),
+        };
+
+    // The newest change we received, so the server's clock decides what is new next time
+    let last_sync = changed
+        .iter()
+        .map(Article::last_modified)
+        .max()
+        .unwrap_or(timestamp);
+    for article in &changed {
+        let path = dir.join(format!("{}.md", article.id));
+        let markdown = frontmatter::render(
+            article,
+            tags.get(&article.id).map_or(&[][..], Vec::as_slice),
+            authors.get(&article.id).map_or(&[][..], Vec::as_slice),
+        )?;
+        tokio::fs::write(path, markdown).await.into_diagnostic()?;
+    }
+
+    let mut removed = 0;
+    let mut entries = tokio::fs::read_dir(&dir).await.into_diagnostic()?;
+    while let Some(entry) = entries.next_entry().await.into_diagnostic()? {
+        let path = entry.path();
+        let Some(id) = path
+            .extension()
+            .filter(|ext| *ext == "md")
+            .and(path.file_stem())
+            .and_then(|stem| stem.to_str())
+        else {
+            continue;
+        };
+        if !existing.iter().any(|e| e == id) {
+            tokio::fs::remove_file(&path).await.into_diagnostic()?;
+            removed += 1;
+        }
+    }
+
+    tokio::fs::write(
+        &marker,
+        last_sync.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+    )
+    .await
+    .into_diagnostic()?;
+    println!(
+        "Exported {} changed and removed {removed} yanked articles",
+        changed.len()
+    );
+
+    Ok(())
+}
+
+/// Copies an article, printing the ID of the copy
+pub async fn clone(conf: ClientConfig, id: String, draft: bool) -> miette::Result<()> {
+    let id = resolve_id(&conf, id).await?;
+    match send(&conf, InnerRequest::DuplicateArticle { id, draft }).await? {
+        Response::ArticleId(id) => println!("{id}"),
+        Response::Error(e) => println!("An error occured: {e}"),
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Prints a preview link for an article
+pub async fn preview(conf: ClientConfig, id: String, days: i64) -> miette::Result<()> {
+    let id = resolve_id(&conf, id).await?;
+    match send(&conf, InnerRequest::PreviewArticle { id, days }).await? {
+        Response::ArticleUrl(url) => println!("{}{url}", conf.addr),
+        Response::Error(e) => println!("An error occured: {e}"),
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Prints an HTML page showing the markdown file the way the blog would, so it can be
+/// checked in a browser before publishing
+pub async fn render(conf: ClientConfig, path: PathBuf) -> miette::Result<()> {
+    let path = if path.is_dir() {
+        path.join("index.md")
+    } else {
+        path
+    };
+    let source = tokio::fs::read_to_string(&path).await.into_diagnostic()?;
+    let (front_matter, content) = frontmatter::parse(&source)?;
+    let title = front_matter.title.unwrap_or_else(|| "Preview".to_string());
+    let content = content.to_string();
+
+    match send(&conf, InnerRequest::RenderPreview { content }).await? {
+        Response::Preview { html, stylesheets } => {
+            println!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">");
+            println!("<title>{}</title>", escape_xml(&title));
+            for stylesheet in stylesheets {
+                println!(
+                    "<link rel=\"stylesheet\" href=\"{}{}\" media=\"{}\">",
+                    conf.addr, stylesheet.href, stylesheet.media
+                );
             }
-            println!("{table}");
+            println!("</head>\n<body>\n<main class=\"content\">");
+            println!("<h1>{}</h1>\n{html}", escape_xml(&title));
+            println!("</main>\n</body>\n</html>");
         }
         Response::Error(e) => println!("An error occured: {e}"),
         _ => unreachable!(),
@@ -76,47 +496,162 @@ pub async fn list(conf: ClientConfig) -> miette::Result<()> {
     Ok(())
 }
 
-pub async fn yank(conf: ClientConfig, id: String) -> miette::Result<()> {
-    let resp = Client::new()
-        .post(format!("{}/api", conf.addr))
-        .json(&Request {
-            secret: conf.secret,
-            request: InnerRequest::YankArticle { id },
-        })
-        .send()
-        .await
-        .into_diagnostic()?;
-    let data: Response = resp.json().await.into_diagnostic()?;
-    if let Response::Error(e) = data {
+/// Prints the articles matching the query, including drafts, with their IDs
+pub async fn search(conf: ClientConfig, query: String) -> miette::Result<()> {
+    let results = match send(&conf, InnerRequest::SearchArticles { query }).await? {
+        Response::SearchResults(results) => results,
+        Response::Error(e) => return Err(miette!("An error occured: {e}")),
+        _ => unreachable!(),
+    };
+    if results.is_empty() {
+        println!("No articles found");
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.set_header(Row::from(vec!["ID", "Title", "Match"]));
+    for result in &results {
+        // Snippets are HTML for the search box on the blog
+        let snippet = result.snippet.replace("<mark>", "").replace("</mark>", "");
+        let snippet = quick_xml::escape::unescape(&snippet)
+            .map(|s| s.into_owned())
+            .unwrap_or(snippet);
+        table.add_row(Row::from(&[&result.id, &result.title, &snippet]));
+    }
+    println!("{table}");
+
+    Ok(())
+}
+
+/// Moves an article to a new URL and prints it
+pub async fn set_slug(conf: ClientConfig, id: String, slug: String) -> miette::Result<()> {
+    let id = resolve_id(&conf, id).await?;
+    match send(&conf, InnerRequest::SetSlug { id, slug }).await? {
+        Response::ArticleUrl(url) => println!("{}{url}", conf.addr),
+        Response::Error(e) => println!("An error occured: {e}"),
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Deletes an article once its title has been confirmed, unless `force` is set
+pub async fn yank(conf: ClientConfig, id: String, force: bool) -> miette::Result<()> {
+    let id = resolve_id(&conf, id).await?;
+    if !force {
+        let title = match send(&conf, InnerRequest::GetArticleById { id: id.clone() }).await? {
+            Response::Article(article) => article.title,
+            Response::Error(e) => {
+                println!("An error occured: {e}");
+                return Ok(());
+            }
+            _ => unreachable!(),
+        };
+        print!("Yank \"{title}\" ({id})? [y/N] ");
+        std::io::stdout().flush().into_diagnostic()?;
+        let mut buf = String::new();
+        std::io::stdin().read_line(&mut buf).into_diagnostic()?;
+        if !matches!(buf.trim().to_lowercase().as_str(), "y" | "yes") {
+            println!("Nothing was yanked.");
+            return Ok(());
+        }
+    }
+
+    if let Response::Error(e) = send(&conf, InnerRequest::YankArticle { id }).await? {
         println!("An error occured: {e}");
     }
 
     Ok(())
 }
 
+/// Guesses the content type of common web formats from the file extension
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+pub async fn upload(
+    conf: ClientConfig,
+    path: PathBuf,
+    content_type: Option<String>,
+) -> miette::Result<()> {
+    let data = tokio::fs::read(&path).await.into_diagnostic()?;
+    let request = InnerRequest::UploadMedia {
+        filename: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        content_type: content_type.unwrap_or_else(|| self::content_type(&path).to_string()),
+        data: STANDARD.encode(data),
+    };
+
+    match send(&conf, request).await? {
+        Response::MediaUrl(url) => println!("{url}"),
+        Response::Error(e) => println!("An error occured: {e}"),
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
 pub async fn update(
     conf: ClientConfig,
     id: String,
     title: Option<String>,
     path: Option<String>,
+    tags: Vec<String>,
+    publish: bool,
+    change_note: Option<String>,
 ) -> miette::Result<()> {
-    let content = if let Some(path) = path {
-        Some(tokio::fs::read_to_string(path).await.into_diagnostic()?)
+    let id = resolve_id(&conf, id).await?;
+    let (content, front_matter) = if let Some(path) = path {
+        let source = tokio::fs::read_to_string(path).await.into_diagnostic()?;
+        let (front_matter, content) = frontmatter::parse(&source)?;
+        (Some(content.to_string()), front_matter)
     } else {
-        None
+        (None, FrontMatter::default())
     };
 
-    let resp = Client::new()
-        .post(format!("{}/api", conf.addr))
-        .json(&Request {
-            secret: conf.secret,
-            request: InnerRequest::UpdateArticle { id, title, content },
-        })
-        .send()
-        .await
-        .into_diagnostic()?;
-    let data: Response = resp.json().await.into_diagnostic()?;
-    if let Response::Error(e) = data {
+    let mut fields = front_matter.fields;
+    if !tags.is_empty() {
+        fields.tags = Some(tags);
+    }
+    if publish {
+        fields.draft = Some(false);
+    }
+    let request = InnerRequest::UpdateArticle {
+        id,
+        title: title.or(front_matter.title),
+        content,
+        change_note,
+        fields,
+    };
+    match send(&conf, request).await? {
+        Response::Error(e) => println!("An error occured: {e}"),
+        Response::Invalid(errors) => print_invalid(&errors),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+pub async fn set_maintenance(conf: ClientConfig, enabled: bool) -> miette::Result<()> {
+    if let Response::Error(e) = send(&conf, InnerRequest::SetMaintenance { enabled }).await? {
         println!("An error occured: {e}");
     }
 
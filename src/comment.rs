@@ -1,7 +1,25 @@
+use std::{collections::HashMap, net::IpAddr};
+
 use chrono::{NaiveDateTime, Utc};
+use miette::{miette, IntoDiagnostic};
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    thread_rng, Rng,
+};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Connection, SqliteConnection};
 use uuid::Uuid;
 
+use crate::{
+    article::url_of,
+    auth, datetime,
+    email::{Email, ReplyNotification},
+    jobs::{self, Job},
+    server::connect,
+    CommentConfig,
+};
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Comment {
     pub id: String,
@@ -9,6 +27,11 @@ pub struct Comment {
     pub author: String,
     pub content: String,
     pub published: NaiveDateTime,
+    /// Salted hash of the commenter's address, to spot abuse without storing the address
+    pub ip_hash: Option<String>,
+    /// Reported by enough readers to be taken down until it is reviewed
+    #[serde(default)]
+    pub hidden: bool,
 }
 
 impl Comment {
@@ -19,11 +42,13 @@ impl Comment {
             author: req.author,
             content: req.content,
             published: Utc::now().naive_utc(),
+            ip_hash: None,
+            hidden: false,
         }
     }
 
     pub fn published(&self) -> String {
-        self.published.format("%d.%m.%Y %H:%M").to_string()
+        datetime::display(self.published)
     }
 }
 
@@ -32,4 +57,541 @@ pub struct CommentRequest {
     article: String,
     author: String,
     content: String,
+    /// The proof of work challenge that was solved, and its solution
+    #[serde(default)]
+    challenge: Option<String>,
+    #[serde(default)]
+    nonce: Option<String>,
+    /// Index of the question that was answered, and the answer
+    #[serde(default)]
+    question: Option<usize>,
+    #[serde(default)]
+    answer: Option<String>,
+    /// Where to send notifications about new comments, never shown or stored with the comment
+    #[serde(default)]
+    email: Option<String>,
+    /// Set by the checkbox asking for notifications
+    #[serde(default)]
+    subscribe: Option<String>,
+}
+
+impl CommentRequest {
+    /// The email address given, whether or not notifications were asked for
+    pub fn email(&self) -> Option<&str> {
+        self.email
+            .as_deref()
+            .map(str::trim)
+            .filter(|email| !email.is_empty())
+    }
+
+    /// The address to notify about new comments, if the commenter asked for it
+    pub fn subscription(&self) -> Option<String> {
+        self.subscribe.as_ref()?;
+        self.email
+            .as_deref()
+            .map(str::trim)
+            .filter(|email| email.contains('@'))
+            .map(str::to_string)
+    }
+
+    /// Verifies the configured challenges, returning the reason if one was failed.
+    /// Proof of work challenges must have been signed with `key` by [`Challenge::new`].
+    pub fn check_challenge(&self, config: &CommentConfig, key: &str) -> Result<(), &'static str> {
+        if let Some(difficulty) = config.pow_difficulty {
+            let (Some(challenge), Some(nonce)) = (&self.challenge, &self.nonce) else {
+                return Err("The comment form requires JavaScript to be enabled.");
+            };
+            let issued = challenge
+                .strip_prefix(&format!("{}:", self.article))
+                .and_then(|rest| rest.split_once(':'))
+                .and_then(|(t, signature)| Some((t.parse::<i64>().ok()?, signature)))
+                .filter(|(t, signature)| auth::verify(key, *t, self.article.as_bytes(), signature));
+            let age = issued.map(|(t, _)| Utc::now().timestamp() - t);
+            let fresh = age.is_some_and(|age| (0..CHALLENGE_LIFETIME).contains(&age));
+            if !fresh
+                || leading_zero_bits(&Sha256::digest(format!("{challenge}:{nonce}"))) < difficulty
+            {
+                return Err(
+                    "The comment could not be verified, please reload the page and try again.",
+                );
+            }
+        }
+
+        if !config.questions.is_empty() {
+            let expected = self.question.and_then(|i| config.questions.get(i));
+            let correct = expected
+                .zip(self.answer.as_ref())
+                .is_some_and(|(q, answer)| q.answer.trim().eq_ignore_ascii_case(answer.trim()));
+            if !correct {
+                return Err("The answer to the question was wrong.");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Marks the proof of work challenge as used. Returns false if it already was, so
+    /// every solution is good for a single comment.
+    pub async fn spend_challenge(&self, conn: &mut SqliteConnection) -> miette::Result<bool> {
+        let Some(challenge) = &self.challenge else {
+            return Ok(true);
+        };
+        let now = Utc::now().naive_utc();
+        let expired = now - chrono::Duration::seconds(CHALLENGE_LIFETIME);
+        sqlx::query!("DELETE FROM spent_challenges WHERE spent < ?", expired)
+            .execute(&mut *conn)
+            .await
+            .into_diagnostic()?;
+        let spent = sqlx::query!(
+            "INSERT OR IGNORE INTO spent_challenges ( challenge, spent ) VALUES (?, ?)",
+            challenge,
+            now
+        )
+        .execute(conn)
+        .await
+        .into_diagnostic()?
+        .rows_affected();
+        Ok(spent > 0)
+    }
+}
+
+/// Seconds a proof of work challenge stays valid
+const CHALLENGE_LIFETIME: i64 = 3600;
+
+/// A proof of work challenge: find a nonce so that the SHA-256 hash of
+/// `challenge:nonce` starts with `difficulty` zero bits
+#[derive(Clone)]
+pub struct Work {
+    pub challenge: String,
+    pub difficulty: u32,
+}
+
+/// A question from the config, identified by its index
+#[derive(Clone)]
+pub struct Question {
+    pub index: usize,
+    pub text: String,
+}
+
+/// The challenges shown with the comment form of an article
+#[derive(Clone, Default)]
+pub struct Challenge {
+    pub work: Option<Work>,
+    pub question: Option<Question>,
+}
+
+impl Challenge {
+    /// Challenges for the comment form of an article. The proof of work challenge is
+    /// signed with `key`, so commenters can't make up their own.
+    pub fn new(article: &str, config: &CommentConfig, key: &str) -> Self {
+        let work = config.pow_difficulty.map(|difficulty| {
+            let issued = Utc::now().timestamp();
+            let signature = auth::sign(key, issued, article.as_bytes());
+            Work {
+                challenge: format!("{article}:{issued}:{signature}"),
+                difficulty,
+            }
+        });
+        let question = (!config.questions.is_empty()).then(|| {
+            let index = thread_rng().gen_range(0..config.questions.len());
+            Question {
+                index,
+                text: config.questions[index].question.clone(),
+            }
+        });
+        Self { work, question }
+    }
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// Hashes a value with a salt that is generated once per database, so the
+/// hashes can't be reversed by hashing every possible value
+async fn salted_hash(value: &str, conn: &mut SqliteConnection) -> miette::Result<String> {
+    let salt = Alphanumeric.sample_string(&mut thread_rng(), 32);
+    sqlx::query!(
+        "INSERT OR IGNORE INTO settings ( key, value ) VALUES ('ip_salt', ?)",
+        salt
+    )
+    .execute(&mut *conn)
+    .await
+    .into_diagnostic()?;
+    let salt = sqlx::query!("SELECT value FROM settings WHERE key = 'ip_salt'")
+        .fetch_one(conn)
+        .await
+        .into_diagnostic()?
+        .value;
+
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(value.as_bytes());
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Hashes a commenter's address, see [`salted_hash`]
+pub async fn hash_ip(ip: IpAddr, conn: &mut SqliteConnection) -> miette::Result<String> {
+    salted_hash(&ip.to_string(), conn).await
+}
+
+/// Hashes an email address regardless of its case, see [`salted_hash`]
+pub async fn hash_email(email: &str, conn: &mut SqliteConnection) -> miette::Result<String> {
+    salted_hash(&email.trim().to_lowercase(), conn).await
+}
+
+/// The number of comments on every article that has any, in a single query
+pub async fn counts(conn: &mut SqliteConnection) -> miette::Result<HashMap<String, i64>> {
+    Ok(
+        sqlx::query!(r#"SELECT article, COUNT(*) AS "count!: i64" FROM comments WHERE NOT hidden GROUP BY article"#)
+            .fetch_all(conn)
+            .await
+            .into_diagnostic()?
+            .into_iter()
+            .map(|r| (r.article, r.count))
+            .collect(),
+    )
+}
+
+/// Records a reader's report of a comment and hides the comment once `threshold`
+/// readers reported it. Returns the ID of the comment's article, if it exists.
+pub async fn flag(
+    id: &str,
+    ip_hash: &str,
+    threshold: i64,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Option<String>> {
+    let Some(comment) = sqlx::query!("SELECT article FROM comments WHERE id = ?", id)
+        .fetch_optional(&mut *conn)
+        .await
+        .into_diagnostic()?
+    else {
+        return Ok(None);
+    };
+
+    let now = Utc::now().naive_utc();
+    sqlx::query!(
+        "INSERT OR IGNORE INTO comment_flags ( comment, ip_hash, created ) VALUES (?, ?, ?)",
+        id,
+        ip_hash,
+        now
+    )
+    .execute(&mut *conn)
+    .await
+    .into_diagnostic()?;
+    let flags = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!: i64" FROM comment_flags WHERE comment = ?"#,
+        id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .into_diagnostic()?
+    .count;
+    if flags >= threshold {
+        let hidden = sqlx::query!(
+            "UPDATE comments SET hidden = TRUE WHERE id = ? AND NOT hidden",
+            id
+        )
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?
+        .rows_affected();
+        if hidden > 0 {
+            tracing::info!(comment = %id, flags, "hid a reported comment");
+        }
+    }
+    Ok(Some(comment.article))
+}
+
+/// A reported comment awaiting review
+pub struct FlaggedComment {
+    pub comment: Comment,
+    pub article_title: String,
+    pub article_url: String,
+    pub flags: i64,
+}
+
+/// All reported comments, the most reported first
+pub async fn flagged(conn: &mut SqliteConnection) -> miette::Result<Vec<FlaggedComment>> {
+    let rows = sqlx::query!(
+        r#"SELECT comments.id, comments.article, comments.author, comments.content, comments.published, comments.ip_hash, comments.hidden,
+        articles.title, articles.slug, COUNT(*) AS "flags!: i64"
+        FROM comment_flags
+        JOIN comments ON comments.id = comment_flags.comment
+        JOIN articles ON articles.id = comments.article
+        GROUP BY comments.id
+        ORDER BY COUNT(*) DESC, comments.published DESC"#
+    )
+    .fetch_all(conn)
+    .await
+    .into_diagnostic()?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| FlaggedComment {
+            article_url: url_of(&r.title, r.slug.as_deref()),
+            article_title: r.title,
+            flags: r.flags,
+            comment: Comment {
+                id: r.id,
+                article: r.article,
+                author: r.author,
+                content: r.content,
+                published: r.published,
+                ip_hash: r.ip_hash,
+                hidden: r.hidden,
+            },
+        })
+        .collect())
+}
+
+/// Shows a reported comment again and forgets the reports
+pub async fn keep(id: &str, conn: &mut SqliteConnection) -> miette::Result<()> {
+    sqlx::query!("DELETE FROM comment_flags WHERE comment = ?", id)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    sqlx::query!("UPDATE comments SET hidden = FALSE WHERE id = ?", id)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    Ok(())
+}
+
+/// Deletes a comment along with its reports
+pub async fn delete(id: &str, conn: &mut SqliteConnection) -> miette::Result<()> {
+    sqlx::query!("DELETE FROM comment_flags WHERE comment = ?", id)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    sqlx::query!("DELETE FROM comments WHERE id = ?", id)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    Ok(())
+}
+
+/// "1 comment", "2 comments" and so on
+pub fn count_label(count: i64) -> String {
+    match count {
+        1 => "1 comment".to_string(),
+        count => format!("{count} comments"),
+    }
+}
+
+/// Subscribes the address to new comments on the article, which only takes effect once it
+/// is confirmed. Returns the token to confirm with, or `None` if it already was.
+pub async fn subscribe(
+    article: &str,
+    email: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Option<String>> {
+    let token = Alphanumeric.sample_string(&mut thread_rng(), 32);
+    let now = Utc::now().naive_utc();
+    sqlx::query!(
+        "INSERT OR IGNORE INTO comment_subscriptions ( token, article, email, created ) VALUES (?, ?, ?, ?)",
+        token,
+        article,
+        email,
+        now
+    )
+    .execute(&mut *conn)
+    .await
+    .into_diagnostic()?;
+
+    let subscription = sqlx::query!(
+        "SELECT token, confirmed FROM comment_subscriptions WHERE article = ? AND email = ?",
+        article,
+        email
+    )
+    .fetch_one(conn)
+    .await
+    .into_diagnostic()?;
+    Ok((!subscription.confirmed).then_some(subscription.token))
+}
+
+/// Starts the notifications of a subscription. Returns whether the token was valid.
+pub async fn confirm(token: &str, conn: &mut SqliteConnection) -> miette::Result<bool> {
+    let confirmed = sqlx::query!(
+        "UPDATE comment_subscriptions SET confirmed = TRUE WHERE token = ?",
+        token
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?
+    .rows_affected();
+    Ok(confirmed > 0)
+}
+
+/// Everyone with a confirmed subscription to an article, as their address and unsubscribe token
+pub async fn subscribers(
+    article: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Vec<(String, String)>> {
+    Ok(sqlx::query!(
+        "SELECT email, token FROM comment_subscriptions WHERE article = ? AND confirmed",
+        article
+    )
+    .fetch_all(conn)
+    .await
+    .into_diagnostic()?
+    .into_iter()
+    .map(|r| (r.email, r.token))
+    .collect())
+}
+
+/// Ends a subscription. Returns whether the token was valid.
+pub async fn unsubscribe(token: &str, conn: &mut SqliteConnection) -> miette::Result<bool> {
+    let deleted = sqlx::query!("DELETE FROM comment_subscriptions WHERE token = ?", token)
+        .execute(conn)
+        .await
+        .into_diagnostic()?
+        .rows_affected();
+    Ok(deleted > 0)
+}
+
+/// Emails the subscribers of an article about a comment, unless it was hidden or deleted
+/// since it was posted. `except` is the commenter's own address, and unsubscribe links are
+/// `unsubscribe` followed by the subscription's token.
+pub async fn notify_subscribers(
+    comment: &str,
+    except: Option<&str>,
+    blog_name: &str,
+    link: &str,
+    unsubscribe: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<()> {
+    let Some(comment) = sqlx::query!(
+        "SELECT comments.article, comments.author, comments.content, articles.title FROM comments JOIN articles ON articles.id = comments.article WHERE comments.id = ? AND NOT comments.hidden",
+        comment
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .into_diagnostic()?
+    else {
+        return Ok(());
+    };
+
+    for (email, token) in subscribers(&comment.article, &mut *conn).await? {
+        // Nobody needs to hear about their own comment
+        if except.is_some_and(|own| own.eq_ignore_ascii_case(&email)) {
+            continue;
+        }
+        let unsubscribe = format!("{unsubscribe}{token}");
+        let email = Email::from_template(
+            email,
+            format!("New comment on \"{}\"", comment.title),
+            &ReplyNotification {
+                blog_name,
+                article_title: &comment.title,
+                author: &comment.author,
+                content: &comment.content,
+                link,
+                unsubscribe: &unsubscribe,
+            },
+        )?;
+        jobs::enqueue(&Job::SendEmail { email }, &mut *conn).await?;
+    }
+    Ok(())
+}
+
+/// A request to be notified about new comments on an article
+#[derive(Serialize, Deserialize)]
+pub struct Subscription {
+    pub token: String,
+    pub article: String,
+    pub email: String,
+    pub created: NaiveDateTime,
+    /// Whether the address confirmed it wants the notifications
+    #[serde(default)]
+    pub confirmed: bool,
+}
+
+/// The discussion of an article, as written by `comments export`
+#[derive(Serialize, Deserialize)]
+struct Discussion {
+    article: String,
+    title: String,
+    comments: Vec<Comment>,
+}
+
+/// Writes the comments of an article to stdout as JSON, oldest first
+pub async fn export(article: String) -> miette::Result<()> {
+    let mut conn = connect().await?;
+    let title = sqlx::query!("SELECT title FROM articles WHERE id = ?", article)
+        .fetch_optional(&mut conn)
+        .await
+        .into_diagnostic()?
+        .ok_or(miette!("No article with id {article} found"))?
+        .title;
+    let comments = sqlx::query_as!(
+        Comment,
+        "SELECT * FROM comments WHERE article = ? ORDER BY published",
+        article
+    )
+    .fetch_all(&mut conn)
+    .await
+    .into_diagnostic()?;
+
+    let discussion = Discussion {
+        article,
+        title,
+        comments,
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&discussion).into_diagnostic()?
+    );
+    Ok(())
+}
+
+/// Restores comments written by `comments export`, replacing existing ones with the same IDs
+pub async fn import(path: String) -> miette::Result<()> {
+    let source = tokio::fs::read_to_string(path).await.into_diagnostic()?;
+    let discussion: Discussion = serde_json::from_str(&source).into_diagnostic()?;
+
+    let mut conn = connect().await?;
+    let exists = sqlx::query!("SELECT id FROM articles WHERE id = ?", discussion.article)
+        .fetch_optional(&mut conn)
+        .await
+        .into_diagnostic()?
+        .is_some();
+    if !exists {
+        return Err(miette!(
+            "No article with id {} found, load the article first",
+            discussion.article
+        ));
+    }
+
+    let mut tx = conn.begin().await.into_diagnostic()?;
+    for comment in &discussion.comments {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO comments ( id, article, author, content, published, ip_hash, hidden ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            comment.id,
+            discussion.article,
+            comment.author,
+            comment.content,
+            comment.published,
+            comment.ip_hash,
+            comment.hidden
+        )
+        .execute(&mut *tx)
+        .await
+        .into_diagnostic()?;
+    }
+    tx.commit().await.into_diagnostic()?;
+
+    println!(
+        "Imported {} comments on \"{}\"",
+        discussion.comments.len(),
+        discussion.title
+    );
+    Ok(())
 }
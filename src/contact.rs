@@ -0,0 +1,110 @@
+use askama::Template;
+use chrono::{NaiveDateTime, Utc};
+use comfy_table::{Row, Table};
+use miette::IntoDiagnostic;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{datetime, server::connect, ServerConfig};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Message {
+    pub id: String,
+    pub name: String,
+    pub email: String,
+    pub content: String,
+    pub received: NaiveDateTime,
+}
+
+impl Message {
+    pub fn from_form(form: ContactForm) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name: form.name,
+            email: form.email,
+            content: form.message,
+            received: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ContactForm {
+    pub name: String,
+    pub email: String,
+    pub message: String,
+    /// Hidden from humans, so anything filled in here was filled in by a bot
+    pub website: String,
+}
+
+impl ContactForm {
+    pub fn is_spam(&self) -> bool {
+        !self.website.is_empty()
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.name.trim().is_empty() || self.name.len() > 100 {
+            errors.push("Please enter a name of at most 100 characters.".to_string());
+        }
+        if !self.email.contains('@') || self.email.len() > 254 {
+            errors.push("Please enter a valid email address.".to_string());
+        }
+        if self.message.trim().is_empty() || self.message.len() > 5000 {
+            errors.push("Please enter a message of at most 5000 characters.".to_string());
+        }
+        errors
+    }
+}
+
+#[derive(Template)]
+#[template(path = "contact.html")]
+pub struct ContactPage {
+    pub config: ServerConfig,
+    pub form: ContactForm,
+    pub errors: Vec<String>,
+    pub sent: bool,
+    pub csrf: String,
+}
+
+#[derive(Template)]
+#[template(path = "email/contact.txt")]
+pub struct ContactNotification<'a> {
+    pub blog_name: &'a str,
+    pub message: &'a Message,
+}
+
+pub async fn list_messages() -> miette::Result<()> {
+    let mut conn = connect().await?;
+
+    let messages = sqlx::query_as!(Message, "SELECT * FROM messages ORDER BY received DESC")
+        .fetch_all(&mut conn)
+        .await
+        .into_diagnostic()?;
+
+    let mut table = Table::new();
+    table.set_header(Row::from(vec!["ID", "From", "Received", "Message"]));
+    for message in messages {
+        table.add_row([
+            &message.id,
+            &format!("{} <{}>", message.name, message.email),
+            &datetime::display(message.received),
+            &message.content,
+        ]);
+    }
+    println!("{table}");
+
+    Ok(())
+}
+
+pub async fn delete_message(id: String) -> miette::Result<()> {
+    let mut conn = connect().await?;
+
+    sqlx::query!("DELETE FROM messages WHERE id = ?", id)
+        .execute(&mut conn)
+        .await
+        .into_diagnostic()?;
+
+    Ok(())
+}
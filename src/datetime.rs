@@ -0,0 +1,100 @@
+//! Timestamps are stored as naive UTC and only converted to the configured timezone for display
+
+use std::sync::OnceLock;
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Locale, NaiveDateTime, TimeZone,
+};
+use chrono_tz::Tz;
+use miette::miette;
+
+/// The format used if none is configured
+pub const DEFAULT_FORMAT: &str = "%d.%m.%Y %H:%M";
+
+/// How timestamps are shown to humans
+struct Style {
+    timezone: Tz,
+    format: String,
+    locale: Locale,
+}
+
+static STYLE: OnceLock<Style> = OnceLock::new();
+
+/// Sets the timezone, `strftime` format and locale (for month and day names) timestamps
+/// are displayed in. Only the first call has an effect.
+pub fn configure(timezone: Tz, format: &str, locale: Option<&str>) -> miette::Result<()> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(miette!("invalid date format {format}"));
+    }
+    let locale = match locale {
+        Some(locale) => Locale::try_from(locale).map_err(|_| miette!("unknown locale {locale}"))?,
+        None => Locale::POSIX,
+    };
+    let _ = STYLE.set(Style {
+        timezone,
+        format: format.to_string(),
+        locale,
+    });
+    Ok(())
+}
+
+/// The timestamp in the configured timezone, UTC if none is configured
+pub fn local(timestamp: NaiveDateTime) -> DateTime<Tz> {
+    let timezone = STYLE.get().map_or(Tz::UTC, |style| style.timezone);
+    timezone.from_utc_datetime(&timestamp)
+}
+
+/// Formats a timestamp for humans as configured
+pub fn display(timestamp: NaiveDateTime) -> String {
+    match STYLE.get() {
+        Some(style) => local(timestamp)
+            .format_localized(&style.format, style.locale)
+            .to_string(),
+        None => local(timestamp).format(DEFAULT_FORMAT).to_string(),
+    }
+}
+
+fn parse(timestamp: &str) -> Result<NaiveDateTime, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.naive_utc())
+        .or_else(|_| timestamp.parse())
+}
+
+/// (De)serializes UTC timestamps as RFC 3339. Naive timestamps, which older clients send
+/// and older caches and dumps contain, are still accepted and taken to be in UTC.
+pub mod rfc3339 {
+    use chrono::{NaiveDateTime, TimeZone, Utc};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(timestamp: &NaiveDateTime, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&Utc.from_utc_datetime(timestamp).to_rfc3339())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<NaiveDateTime, D::Error> {
+        super::parse(&String::deserialize(d)?).map_err(D::Error::custom)
+    }
+
+    pub mod option {
+        use chrono::NaiveDateTime;
+        use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            timestamp: &Option<NaiveDateTime>,
+            s: S,
+        ) -> Result<S::Ok, S::Error> {
+            match timestamp {
+                Some(timestamp) => super::serialize(timestamp, s),
+                None => s.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            d: D,
+        ) -> Result<Option<NaiveDateTime>, D::Error> {
+            Option::<String>::deserialize(d)?
+                .map(|t| super::super::parse(&t).map_err(D::Error::custom))
+                .transpose()
+        }
+    }
+}
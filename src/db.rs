@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+use miette::IntoDiagnostic;
+use serde::Deserialize;
+use sqlx::{Pool, Sqlite, SqliteConnection};
+
+use crate::server::connect;
+
+/// How much a WAL checkpoint may wait for and block readers and writers, see
+/// <https://www.sqlite.org/pragma.html#pragma_wal_checkpoint>
+#[derive(Clone, Copy, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckpointMode {
+    /// Copy as much as possible without waiting on anyone
+    #[default]
+    Passive,
+    /// Wait for writers, then copy the whole log
+    Full,
+    /// Like `full`, and wait for readers so the log starts over
+    Restart,
+    /// Like `restart`, and empty the log file
+    Truncate,
+}
+
+impl CheckpointMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Passive => "PASSIVE",
+            Self::Full => "FULL",
+            Self::Restart => "RESTART",
+            Self::Truncate => "TRUNCATE",
+        }
+    }
+}
+
+/// The outcome of a WAL checkpoint
+pub struct Checkpoint {
+    /// Whether the checkpoint couldn't finish because the database was in use
+    pub busy: bool,
+    /// Pages in the log
+    pub log: i64,
+    /// Pages of the log copied into the database
+    pub checkpointed: i64,
+}
+
+/// Copies the write-ahead log into the database
+pub async fn checkpoint(
+    mode: CheckpointMode,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Checkpoint> {
+    // PRAGMA arguments can't be bound, and the macros can't tell the type of the results
+    let (busy, log, checkpointed): (i64, i64, i64) =
+        sqlx::query_as(&format!("PRAGMA wal_checkpoint({})", mode.as_str()))
+            .fetch_one(conn)
+            .await
+            .into_diagnostic()?;
+    Ok(Checkpoint {
+        busy: busy != 0,
+        log,
+        checkpointed,
+    })
+}
+
+/// Runs a checkpoint in the given interval until the server shuts down
+pub async fn run_checkpoints(pool: Pool<Sqlite>, interval: u64, mode: CheckpointMode) {
+    if interval == 0 {
+        return;
+    }
+    loop {
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        let result = match pool.acquire().await {
+            Ok(mut conn) => checkpoint(mode, &mut conn).await,
+            Err(e) => Err(e).into_diagnostic(),
+        };
+        match result {
+            Ok(Checkpoint { busy: true, .. }) => {
+                tracing::warn!("checkpoint did not finish because the database is busy")
+            }
+            Ok(checkpoint) => tracing::debug!(
+                log = checkpoint.log,
+                checkpointed = checkpoint.checkpointed,
+                "checkpointed the write-ahead log"
+            ),
+            Err(e) => tracing::error!("checkpoint failed: {e}"),
+        }
+    }
+}
+
+/// Checks the database for corruption, then updates the statistics the query planner
+/// uses and rebuilds the database file to free unused space. Returns the problems the
+/// integrity check found, which skip the rest.
+pub async fn maintain(conn: &mut SqliteConnection) -> miette::Result<Vec<String>> {
+    // The macros can't tell the type of PRAGMA results
+    let problems: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(&mut *conn)
+        .await
+        .into_diagnostic()?
+        .into_iter()
+        .filter(|line: &String| line != "ok")
+        .collect();
+    if !problems.is_empty() {
+        return Ok(problems);
+    }
+
+    sqlx::query!("ANALYZE")
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    sqlx::query!("VACUUM")
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    Ok(Vec::new())
+}
+
+/// Maintains the database and logs the outcome, for the scheduler
+pub async fn maintain_logged(conn: &mut SqliteConnection) {
+    match maintain(conn).await {
+        Ok(problems) if problems.is_empty() => tracing::info!("database maintenance finished"),
+        Ok(problems) => {
+            for problem in problems {
+                tracing::error!("database integrity check failed: {problem}");
+            }
+        }
+        Err(e) => tracing::error!("database maintenance failed: {e}"),
+    }
+}
+
+/// Maintains the database of the server from the command line
+pub async fn maintain_now() -> miette::Result<()> {
+    let mut conn = connect().await?;
+    let problems = maintain(&mut conn).await?;
+    if problems.is_empty() {
+        println!("The database is intact, analyzed and vacuumed");
+    } else {
+        println!("The integrity check found problems, restore a backup:");
+        for problem in problems {
+            println!("  {problem}");
+        }
+    }
+    Ok(())
+}
+
+/// Checkpoints the database of a running server from the command line
+pub async fn checkpoint_now(mode: CheckpointMode) -> miette::Result<()> {
+    let mut conn = connect().await?;
+    let checkpoint = checkpoint(mode, &mut conn).await?;
+    if checkpoint.busy {
+        println!("The database is busy, try again or use a less strict mode");
+    } else {
+        println!(
+            "Copied {} of {} pages from the write-ahead log",
+            checkpoint.checkpointed, checkpoint.log
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,26 @@
+//! Diagrams written in fenced code blocks, drawn in the browser by Mermaid
+
+/// How comrak renders a `mermaid` code block
+const CODE_BLOCK: &str = r#"<pre><code class="language-mermaid">"#;
+/// The container Mermaid looks for, holding the diagram's source
+const CONTAINER: &str = r#"<pre class="mermaid">"#;
+
+/// Turns the mermaid code blocks into containers Mermaid draws diagrams in. The source
+/// is still shown if the script doesn't load.
+pub fn mermaid(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(CODE_BLOCK) {
+        let source = &rest[start + CODE_BLOCK.len()..];
+        let Some(end) = source.find("</code></pre>") else {
+            break;
+        };
+        output.push_str(&rest[..start]);
+        output.push_str(CONTAINER);
+        output.push_str(&source[..end]);
+        output.push_str("</pre>");
+        rest = &source[end + "</code></pre>".len()..];
+    }
+    output.push_str(rest);
+    output
+}
@@ -0,0 +1,201 @@
+use miette::{miette, IntoDiagnostic};
+use serde::{Deserialize, Serialize};
+use sqlx::{Connection, SqliteConnection};
+
+use crate::{
+    article::Article,
+    comment::{Comment, Subscription},
+    secret::{self, SecretMetadata},
+    server::connect,
+};
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum DumpFormat {
+    Json,
+}
+
+/// Everything needed to recreate a blog, tagged with the schema it was taken from
+#[derive(Serialize, Deserialize)]
+pub struct Dump {
+    /// Version of the newest migration applied to the dumped database
+    schema_version: i64,
+    articles: Vec<Article>,
+    comments: Vec<Comment>,
+    /// Readers notified about new comments
+    #[serde(default)]
+    comment_subscriptions: Vec<Subscription>,
+    tags: Vec<ArticleTag>,
+    authors: Vec<ArticleAuthor>,
+    /// Secrets are dumped without their values, which can't be recovered
+    secrets: Vec<SecretMetadata>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArticleTag {
+    article: String,
+    tag: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArticleAuthor {
+    article: String,
+    author: String,
+    position: i64,
+}
+
+async fn schema_version(conn: &mut SqliteConnection) -> miette::Result<i64> {
+    sqlx::query!(r#"SELECT MAX(version) AS "version!: i64" FROM _sqlx_migrations"#)
+        .fetch_one(conn)
+        .await
+        .into_diagnostic()
+        .map(|r| r.version)
+}
+
+/// Writes the whole dataset to stdout
+pub async fn dump(format: DumpFormat) -> miette::Result<()> {
+    let mut conn = connect().await?;
+
+    let dump = Dump {
+        schema_version: schema_version(&mut conn).await?,
+        articles: sqlx::query_as!(Article, "SELECT * FROM articles ORDER BY published")
+            .fetch_all(&mut conn)
+            .await
+            .into_diagnostic()?,
+        comments: sqlx::query_as!(Comment, "SELECT * FROM comments ORDER BY published")
+            .fetch_all(&mut conn)
+            .await
+            .into_diagnostic()?,
+        comment_subscriptions: sqlx::query_as!(
+            Subscription,
+            "SELECT token, article, email, created, confirmed FROM comment_subscriptions ORDER BY created"
+        )
+        .fetch_all(&mut conn)
+        .await
+        .into_diagnostic()?,
+        tags: sqlx::query_as!(ArticleTag, "SELECT article, tag FROM article_tags")
+            .fetch_all(&mut conn)
+            .await
+            .into_diagnostic()?,
+        authors: sqlx::query_as!(
+            ArticleAuthor,
+            "SELECT article, author, position FROM article_authors"
+        )
+        .fetch_all(&mut conn)
+        .await
+        .into_diagnostic()?,
+        secrets: secret::list(&mut conn).await?,
+    };
+
+    match format {
+        DumpFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&dump).into_diagnostic()?)
+        }
+    }
+    Ok(())
+}
+
+/// Restores a dump taken with the same schema version, replacing existing rows with the same IDs
+pub async fn load(path: String) -> miette::Result<()> {
+    let source = tokio::fs::read_to_string(path).await.into_diagnostic()?;
+    let dump: Dump = serde_json::from_str(&source).into_diagnostic()?;
+
+    let mut conn = connect().await?;
+    let version = schema_version(&mut conn).await?;
+    if dump.schema_version != version {
+        return Err(miette!(
+            "the dump was taken at schema version {}, but the database is at {version}",
+            dump.schema_version
+        ));
+    }
+
+    let mut tx = conn.begin().await.into_diagnostic()?;
+    for article in &dump.articles {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO articles ( id, title, content, published, canonical_url, lang, translation_of, content_warning, license, status, updated, noindex, slug, summary, expires_at, comments_enabled, created_by, html ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+            article.id,
+            article.title,
+            article.content,
+            article.published,
+            article.canonical_url,
+            article.lang,
+            article.translation_of,
+            article.content_warning,
+            article.license,
+            article.status,
+            article.updated,
+            article.noindex,
+            article.slug,
+            article.summary,
+            article.expires_at,
+            article.comments_enabled,
+            article.created_by,
+            article.html
+        )
+        .execute(&mut *tx)
+        .await
+        .into_diagnostic()?;
+    }
+    for comment in &dump.comments {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO comments ( id, article, author, content, published, ip_hash, hidden ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            comment.id,
+            comment.article,
+            comment.author,
+            comment.content,
+            comment.published,
+            comment.ip_hash,
+            comment.hidden
+        )
+        .execute(&mut *tx)
+        .await
+        .into_diagnostic()?;
+    }
+    for subscription in &dump.comment_subscriptions {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO comment_subscriptions ( token, article, email, created, confirmed ) VALUES (?1, ?2, ?3, ?4, ?5)",
+            subscription.token,
+            subscription.article,
+            subscription.email,
+            subscription.created,
+            subscription.confirmed
+        )
+        .execute(&mut *tx)
+        .await
+        .into_diagnostic()?;
+    }
+    for tag in &dump.tags {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO article_tags ( article, tag ) VALUES (?1, ?2)",
+            tag.article,
+            tag.tag
+        )
+        .execute(&mut *tx)
+        .await
+        .into_diagnostic()?;
+    }
+    for author in &dump.authors {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO article_authors ( article, author, position ) VALUES (?1, ?2, ?3)",
+            author.article,
+            author.author,
+            author.position
+        )
+        .execute(&mut *tx)
+        .await
+        .into_diagnostic()?;
+    }
+    tx.commit().await.into_diagnostic()?;
+
+    println!(
+        "Loaded {} articles and {} comments",
+        dump.articles.len(),
+        dump.comments.len()
+    );
+    if !dump.secrets.is_empty() {
+        println!(
+            "{} secrets were not restored because their values are not part of the dump. Create new ones with `secret create`.",
+            dump.secrets.len()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use askama::Template;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use miette::{miette, IntoDiagnostic};
+use serde::{Deserialize, Serialize};
+
+use crate::{EmailConfig, ServerConfig};
+
+/// A plain text email, ready to be sent or queued as a job
+#[derive(Serialize, Deserialize)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Email {
+    pub fn from_template(
+        to: String,
+        subject: String,
+        template: &impl Template,
+    ) -> miette::Result<Self> {
+        Ok(Email {
+            to,
+            subject,
+            body: template.render().into_diagnostic()?,
+        })
+    }
+}
+
+/// Sends email through the SMTP server configured in `[server.email]`
+#[derive(Clone)]
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn new(config: &EmailConfig) -> miette::Result<Self> {
+        let mut builder =
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host).into_diagnostic()?;
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(Mailer {
+            transport: builder.build(),
+            from: config.from.parse().into_diagnostic()?,
+        })
+    }
+
+    pub async fn send(&self, email: &Email) -> miette::Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse().into_diagnostic()?)
+            .subject(&email.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body.clone())
+            .into_diagnostic()?;
+
+        self.transport.send(message).await.into_diagnostic()?;
+        Ok(())
+    }
+}
+
+#[derive(Template)]
+#[template(path = "email/comment.txt")]
+pub struct CommentNotification<'a> {
+    pub blog_name: &'a str,
+    pub article_title: &'a str,
+    pub author: &'a str,
+    pub content: &'a str,
+    pub link: &'a str,
+}
+
+/// Tells a subscriber about a new comment in a discussion they take part in
+#[derive(Template)]
+#[template(path = "email/reply.txt")]
+pub struct ReplyNotification<'a> {
+    pub blog_name: &'a str,
+    pub article_title: &'a str,
+    pub author: &'a str,
+    pub content: &'a str,
+    pub link: &'a str,
+    pub unsubscribe: &'a str,
+}
+
+/// Asks an address to confirm that it wants to be notified about new comments
+#[derive(Template)]
+#[template(path = "email/confirm_subscription.txt")]
+pub struct SubscriptionConfirmation<'a> {
+    pub blog_name: &'a str,
+    pub article_title: &'a str,
+    pub confirm: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "email/test.txt")]
+struct TestEmail<'a> {
+    blog_name: &'a str,
+}
+
+/// Sends a test message directly, bypassing the job queue, to check the email configuration
+pub async fn send_test(config: ServerConfig, to: String) -> miette::Result<()> {
+    let email_config = config
+        .email
+        .as_ref()
+        .ok_or(miette!("no email config found"))?;
+    let mailer = Mailer::new(email_config)?;
+
+    let email = Email::from_template(
+        to,
+        format!("Test message from {}", config.blog_name),
+        &TestEmail {
+            blog_name: &config.blog_name,
+        },
+    )?;
+    mailer.send(&email).await?;
+
+    println!("Test message sent to {}", email.to);
+    Ok(())
+}
@@ -0,0 +1,199 @@
+//! Articles as EPUB books, for readers who want an offline copy of a long post
+
+use std::io::{Cursor, Write};
+
+use miette::IntoDiagnostic;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    article::Article,
+    sitemap::{absolute_url, escape_xml},
+};
+
+/// An uploaded image the article shows, packed into the book so it can be read offline
+pub struct Image {
+    /// The URL the article references the image by
+    pub url: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Where the image is stored within the book
+    fn path(&self, index: usize) -> String {
+        let extension = self.url.rsplit_once('.').map_or("img", |(_, e)| e);
+        format!("images/{index}.{extension}")
+    }
+}
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// The package document listing the book's metadata and files
+fn package(article: &Article, authors: &[String], images: &[Image]) -> String {
+    let mut opf = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">"#,
+        "\n",
+        r#"  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">"#,
+        "\n"
+    ));
+    opf.push_str(&format!(
+        "    <dc:identifier id=\"id\">urn:uuid:{}</dc:identifier>\n",
+        escape_xml(&article.id)
+    ));
+    opf.push_str(&format!(
+        "    <dc:title>{}</dc:title>\n",
+        escape_xml(&article.title)
+    ));
+    opf.push_str(&format!(
+        "    <dc:language>{}</dc:language>\n",
+        escape_xml(article.lang.as_deref().unwrap_or("en"))
+    ));
+    for author in authors {
+        opf.push_str(&format!(
+            "    <dc:creator>{}</dc:creator>\n",
+            escape_xml(author)
+        ));
+    }
+    opf.push_str(&format!(
+        "    <dc:date>{}</dc:date>\n",
+        article.published.format("%Y-%m-%d")
+    ));
+    opf.push_str(&format!(
+        "    <meta property=\"dcterms:modified\">{}</meta>\n",
+        article.last_modified().format("%Y-%m-%dT%H:%M:%SZ")
+    ));
+    opf.push_str("  </metadata>\n  <manifest>\n");
+    opf.push_str(
+        "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+    );
+    opf.push_str(
+        "    <item id=\"article\" href=\"article.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+    );
+    for (index, image) in images.iter().enumerate() {
+        opf.push_str(&format!(
+            "    <item id=\"image{index}\" href=\"{}\" media-type=\"{}\"/>\n",
+            image.path(index),
+            escape_xml(&image.content_type)
+        ));
+    }
+    opf.push_str(
+        "  </manifest>\n  <spine>\n    <itemref idref=\"article\"/>\n  </spine>\n</package>\n",
+    );
+    opf
+}
+
+/// An XHTML document titled like the article
+fn xhtml(article: &Article, body: &str) -> String {
+    let lang = escape_xml(article.lang.as_deref().unwrap_or("en"));
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" lang="{lang}" xml:lang="{lang}">
+<head>
+  <meta charset="utf-8"/>
+  <title>{}</title>
+</head>
+<body>
+{body}
+</body>
+</html>
+"#,
+        escape_xml(&article.title)
+    )
+}
+
+/// The rendered article with the images pointing into the book and other links on the
+/// blog made absolute
+fn chapter(
+    article: &Article,
+    authors: &[String],
+    content: &str,
+    images: &[Image],
+    domain: Option<&str>,
+) -> String {
+    let mut content = content.to_string();
+    for (index, image) in images.iter().enumerate() {
+        content = content.replace(
+            &format!("src=\"{}\"", escape_xml(&image.url)),
+            &format!("src=\"{}\"", image.path(index)),
+        );
+    }
+    if let Some(domain) = domain {
+        let root = absolute_url(domain, "/");
+        content = content
+            .replace("href=\"/", &format!("href=\"{root}"))
+            .replace("src=\"/", &format!("src=\"{root}"));
+    }
+
+    let byline = format!(
+        "{} | {}",
+        authors.join(", "),
+        article.published.format("%Y-%m-%d")
+    );
+    let source = domain.map_or(String::new(), |domain| {
+        let url = absolute_url(domain, &format!("/article/{}", article.url()));
+        format!(
+            "<p>From <a href=\"{0}\">{0}</a></p>\n",
+            escape_xml(article.canonical_url.as_ref().unwrap_or(&url))
+        )
+    });
+    let body = format!(
+        "<h1>{}</h1>\n<p>{}</p>\n{content}\n{source}",
+        escape_xml(&article.title),
+        escape_xml(&byline)
+    );
+    xhtml(article, &body)
+}
+
+/// Packs the rendered article and its images into an EPUB 3 book
+pub fn book(
+    article: &Article,
+    authors: &[String],
+    content: &str,
+    images: &[Image],
+    domain: Option<&str>,
+) -> miette::Result<Vec<u8>> {
+    let nav = xhtml(
+        article,
+        &format!(
+            "<nav epub:type=\"toc\">\n<ol>\n<li><a href=\"article.xhtml\">{}</a></li>\n</ol>\n</nav>",
+            escape_xml(&article.title)
+        ),
+    );
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // Readers recognize the format by the uncompressed `mimetype` file at the start
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default();
+    zip.start_file("mimetype", stored).into_diagnostic()?;
+    zip.write_all(b"application/epub+zip").into_diagnostic()?;
+
+    let files = [
+        ("META-INF/container.xml", CONTAINER.to_string()),
+        ("OEBPS/content.opf", package(article, authors, images)),
+        ("OEBPS/nav.xhtml", nav),
+        (
+            "OEBPS/article.xhtml",
+            chapter(article, authors, content, images, domain),
+        ),
+    ];
+    for (path, contents) in files {
+        zip.start_file(path, deflated).into_diagnostic()?;
+        zip.write_all(contents.as_bytes()).into_diagnostic()?;
+    }
+    for (index, image) in images.iter().enumerate() {
+        zip.start_file(format!("OEBPS/{}", image.path(index)), deflated)
+            .into_diagnostic()?;
+        zip.write_all(&image.data).into_diagnostic()?;
+    }
+
+    Ok(zip.finish().into_diagnostic()?.into_inner())
+}
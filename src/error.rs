@@ -1,15 +1,73 @@
+use std::{collections::HashMap, path::Path};
+
+use askama::Template;
 use askama_axum::IntoResponse;
-use axum::http::StatusCode;
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Request as HttpRequest, StatusCode},
+    middleware::Next,
+    response::{Html, Response as AxumResponse},
+    Json,
+};
+use miette::IntoDiagnostic;
+use uuid::Uuid;
+
+use crate::{request::Response, ServerConfig};
+
+pub enum TkError {
+    /// Something went wrong while handling the request
+    Internal(miette::Error),
+    /// No database connection became available in time
+    Unavailable,
+}
 
-pub struct TkError(miette::Error);
+/// What a client is told about a [`TkError`], kept on its response until [`themed_errors`]
+/// turns it into a page or JSON
+#[derive(Clone)]
+struct Failure {
+    message: String,
+    /// Identifies the details in the server's log
+    error_id: Option<String>,
+}
+
+/// Logs an error with all its details and returns an ID to find them by. Only the ID is
+/// meant for clients, the details may contain paths or SQL.
+pub fn report(err: &miette::Error) -> String {
+    let error_id = Uuid::new_v4().to_string();
+    tracing::error!(%error_id, "{err:?}");
+    error_id
+}
 
 impl IntoResponse for TkError {
     fn into_response(self) -> askama_axum::Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Internal Server Error: {}", self.0),
-        )
-            .into_response()
+        let (status, failure) = match self {
+            TkError::Internal(err) => {
+                let error_id = report(&err);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Failure {
+                        message: format!("Internal Server Error (error ID {error_id})"),
+                        error_id: Some(error_id),
+                    },
+                )
+            }
+            TkError::Unavailable => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Failure {
+                    message: "Service Unavailable: the server is too busy right now".to_string(),
+                    error_id: None,
+                },
+            ),
+        };
+        let mut response = (status, failure.message.clone()).into_response();
+        if status == StatusCode::SERVICE_UNAVAILABLE {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, header::HeaderValue::from_static("5"));
+        }
+        response.extensions_mut().insert(failure);
+        response
     }
 }
 
@@ -18,6 +76,113 @@ where
     E: Into<miette::Error>,
 {
     fn from(value: E) -> Self {
-        Self(value.into())
+        Self::Internal(value.into())
+    }
+}
+
+#[derive(Template)]
+#[template(path = "404.html")]
+struct NotFoundPage {
+    config: ServerConfig,
+}
+
+#[derive(Template)]
+#[template(path = "500.html")]
+struct InternalErrorPage {
+    config: ServerConfig,
+    error_id: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "503.html")]
+struct UnavailablePage {
+    config: ServerConfig,
+    /// Whether the blog is down on purpose rather than overloaded
+    maintenance: bool,
+}
+
+/// Error pages from the theme directory, which replace the built-in ones
+#[derive(Default)]
+pub struct ErrorPages(HashMap<StatusCode, String>);
+
+impl ErrorPages {
+    /// Reads `404.html`, `500.html` and `503.html` from the theme directory, if present
+    pub fn load(theme: Option<&Path>) -> miette::Result<Self> {
+        let mut pages = HashMap::new();
+        let Some(theme) = theme else {
+            return Ok(Self(pages));
+        };
+        for status in [
+            StatusCode::NOT_FOUND,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::SERVICE_UNAVAILABLE,
+        ] {
+            let path = theme.join(format!("{}.html", status.as_u16()));
+            if path.exists() {
+                pages.insert(status, std::fs::read_to_string(&path).into_diagnostic()?);
+                tracing::info!("using {} as the {status} page", path.display());
+            }
+        }
+        Ok(Self(pages))
+    }
+}
+
+fn render(
+    config: &ServerConfig,
+    status: StatusCode,
+    maintenance: bool,
+    error_id: Option<String>,
+) -> AxumResponse {
+    if let Some(page) = config.error_pages.0.get(&status) {
+        return (status, Html(page.clone())).into_response();
+    }
+    let config = config.clone();
+    match status {
+        StatusCode::NOT_FOUND => (status, NotFoundPage { config }).into_response(),
+        StatusCode::SERVICE_UNAVAILABLE => (
+            status,
+            UnavailablePage {
+                config,
+                maintenance,
+            },
+        )
+            .into_response(),
+        _ => (status, InternalErrorPage { config, error_id }).into_response(),
     }
 }
+
+/// The themed page for an error status
+pub fn page(config: &ServerConfig, status: StatusCode) -> AxumResponse {
+    render(config, status, false, None)
+}
+
+/// The themed page shown while maintenance mode is enabled
+pub fn maintenance_page(config: &ServerConfig) -> AxumResponse {
+    render(config, StatusCode::SERVICE_UNAVAILABLE, true, None)
+}
+
+/// Answers failed API requests with a JSON error like any other API error, and all
+/// other failed requests with the themed error page
+pub async fn themed_errors(
+    State(config): State<ServerConfig>,
+    request: HttpRequest<Body>,
+    next: Next,
+) -> AxumResponse {
+    let is_api = request.uri().path() == "/api";
+    let response = next.run(request).await;
+    let Some(failure) = response.extensions().get::<Failure>().cloned() else {
+        return response;
+    };
+
+    let (mut parts, _) = response.into_parts();
+    let mut themed = if is_api {
+        (parts.status, Json(Response::Error(failure.message))).into_response()
+    } else {
+        render(&config, parts.status, false, failure.error_id)
+    };
+    // Keep headers like Retry-After and the session cookie
+    parts.headers.remove(header::CONTENT_TYPE);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    themed.headers_mut().extend(parts.headers);
+    themed
+}
@@ -0,0 +1,56 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use chrono::NaiveDateTime;
+use miette::IntoDiagnostic;
+use sqlx::SqliteConnection;
+
+/// Changes whenever an article, its tags or its authors are written to
+#[derive(Clone, Copy, PartialEq)]
+pub struct FeedVersion {
+    /// Published articles in the feed
+    pub count: i64,
+    /// Counts up with every change to any article
+    pub version: i64,
+    /// When the last change was made
+    pub changed: NaiveDateTime,
+}
+
+/// The version of the feed of all articles, or of those in a language. Much cheaper
+/// than generating the feed.
+pub async fn version(
+    lang: Option<&str>,
+    conn: &mut SqliteConnection,
+) -> miette::Result<FeedVersion> {
+    let row = sqlx::query!(
+        r#"SELECT (SELECT COUNT(*) FROM articles WHERE status = 'published' AND (?1 IS NULL OR lang = ?1)) AS "count!: i64", version, changed AS "changed: NaiveDateTime" FROM content_version"#,
+        lang
+    )
+    .fetch_one(conn)
+    .await
+    .into_diagnostic()?;
+    Ok(FeedVersion {
+        count: row.count,
+        version: row.version,
+        changed: row.changed,
+    })
+}
+
+/// Generated RSS feeds by language, so feed readers polling every few minutes don't
+/// have every article rendered again
+#[derive(Default)]
+pub struct FeedCache {
+    feeds: RwLock<HashMap<Option<String>, (FeedVersion, String)>>,
+}
+
+impl FeedCache {
+    /// The cached feed, if it is still of the given version
+    pub fn get(&self, lang: Option<&str>, version: FeedVersion) -> Option<String> {
+        let feeds = self.feeds.read().unwrap();
+        let (cached, xml) = feeds.get(&lang.map(str::to_string))?;
+        (*cached == version).then(|| xml.clone())
+    }
+
+    pub fn insert(&self, lang: Option<String>, version: FeedVersion, xml: String) {
+        self.feeds.write().unwrap().insert(lang, (version, xml));
+    }
+}
@@ -0,0 +1,64 @@
+use miette::{miette, IntoDiagnostic};
+use serde::{Deserialize, Serialize};
+
+use crate::{article::Article, html::HtmlPolicy, request::ArticleFields};
+
+/// Delimits the TOML front matter at the start of an article
+const DELIMITER: &str = "+++";
+
+/// Metadata that can be given at the top of an article's markdown file
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    #[serde(flatten)]
+    pub fields: ArticleFields,
+}
+
+/// Renders an article with its tags and explicitly set authors as markdown with front
+/// matter, the format `publish` reads
+pub fn render(article: &Article, tags: &[String], authors: &[String]) -> miette::Result<String> {
+    let front_matter = FrontMatter {
+        title: Some(article.title.clone()),
+        fields: ArticleFields {
+            canonical_url: article.canonical_url.clone(),
+            lang: article.lang.clone(),
+            translation_of: article.translation_of.clone(),
+            content_warning: article.content_warning.clone(),
+            license: article.license.clone(),
+            summary: article.summary.clone(),
+            expires_at: article.expires_at,
+            comments_enabled: (!article.comments_enabled).then_some(false),
+            html: article.html.as_deref().and_then(HtmlPolicy::parse),
+            tags: (!tags.is_empty()).then(|| tags.to_vec()),
+            authors: (!authors.is_empty()).then(|| authors.to_vec()),
+            ..Default::default()
+        },
+    };
+    let front_matter = toml::to_string(&front_matter).into_diagnostic()?;
+    Ok(format!(
+        "{DELIMITER}\n{front_matter}{DELIMITER}\n\n{}",
+        article.content
+    ))
+}
+
+/// Splits the front matter off the article, if it has any
+pub fn parse(source: &str) -> miette::Result<(FrontMatter, &str)> {
+    let mut lines = source.split_inclusive('\n');
+    let Some(first) = lines.next().filter(|l| l.trim() == DELIMITER) else {
+        return Ok((FrontMatter::default(), source));
+    };
+
+    let start = first.len();
+    let mut end = start;
+    for line in lines {
+        if line.trim() == DELIMITER {
+            let front_matter = toml::from_str(&source[start..end]).into_diagnostic()?;
+            let content = source[end + line.len()..].trim_start_matches(['\r', '\n']);
+            return Ok((front_matter, content));
+        }
+        end += line.len();
+    }
+
+    Err(miette!("front matter is not terminated by {DELIMITER}"))
+}
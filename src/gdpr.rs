@@ -0,0 +1,118 @@
+use chrono::NaiveDateTime;
+use miette::IntoDiagnostic;
+use serde::Serialize;
+use sqlx::SqliteConnection;
+
+use crate::{contact::Message, server::connect};
+
+/// Everything stored about a person, found by their email address. Comments only
+/// carry a name and can't be attributed to an address.
+#[derive(Serialize)]
+struct PersonalData {
+    email: String,
+    messages: Vec<Message>,
+    /// Articles whose new comments are sent to the address
+    comment_subscriptions: Vec<Subscription>,
+    /// Queued or sent emails mentioning the address
+    jobs: Vec<StoredJob>,
+}
+
+#[derive(Serialize)]
+struct Subscription {
+    article: String,
+    created: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+struct StoredJob {
+    id: i64,
+    status: String,
+    created: NaiveDateTime,
+    payload: serde_json::Value,
+}
+
+async fn collect(email: &str, conn: &mut SqliteConnection) -> miette::Result<PersonalData> {
+    let messages = sqlx::query_as!(
+        Message,
+        "SELECT * FROM messages WHERE lower(email) = lower(?) ORDER BY received",
+        email
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .into_diagnostic()?;
+
+    let comment_subscriptions = sqlx::query_as!(
+        Subscription,
+        "SELECT article, created FROM comment_subscriptions WHERE lower(email) = lower(?) ORDER BY created",
+        email
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .into_diagnostic()?;
+
+    let jobs = sqlx::query!(
+        "SELECT id, status, created, payload FROM jobs WHERE instr(lower(payload), lower(?)) > 0 ORDER BY id",
+        email
+    )
+    .fetch_all(conn)
+    .await
+    .into_diagnostic()?
+    .into_iter()
+    .map(|r| {
+        Ok(StoredJob {
+            id: r.id,
+            status: r.status,
+            created: r.created,
+            payload: serde_json::from_str(&r.payload).into_diagnostic()?,
+        })
+    })
+    .collect::<miette::Result<_>>()?;
+
+    Ok(PersonalData {
+        email: email.to_string(),
+        messages,
+        comment_subscriptions,
+        jobs,
+    })
+}
+
+/// Writes all data tied to an email address to stdout as JSON
+pub async fn export(email: String) -> miette::Result<()> {
+    let mut conn = connect().await?;
+    let data = collect(&email, &mut conn).await?;
+    println!("{}", serde_json::to_string_pretty(&data).into_diagnostic()?);
+
+    Ok(())
+}
+
+/// Deletes all data tied to an email address
+pub async fn delete(email: String) -> miette::Result<()> {
+    let mut conn = connect().await?;
+
+    let messages = sqlx::query!("DELETE FROM messages WHERE lower(email) = lower(?)", email)
+        .execute(&mut conn)
+        .await
+        .into_diagnostic()?
+        .rows_affected();
+    let subscriptions = sqlx::query!(
+        "DELETE FROM comment_subscriptions WHERE lower(email) = lower(?)",
+        email
+    )
+    .execute(&mut conn)
+    .await
+    .into_diagnostic()?
+    .rows_affected();
+    let jobs = sqlx::query!(
+        "DELETE FROM jobs WHERE instr(lower(payload), lower(?)) > 0",
+        email
+    )
+    .execute(&mut conn)
+    .await
+    .into_diagnostic()?
+    .rows_affected();
+    println!(
+        "Deleted {messages} messages, {subscriptions} comment subscriptions and {jobs} jobs mentioning {email}"
+    );
+
+    Ok(())
+}
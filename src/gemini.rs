@@ -0,0 +1,224 @@
+use std::{fs::File, io::BufReader, sync::Arc};
+
+use comrak::{
+    nodes::{AstNode, NodeValue},
+    Arena, Options,
+};
+use miette::{miette, IntoDiagnostic};
+use sqlx::{Pool, Sqlite};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader as AsyncBufReader},
+    net::TcpListener,
+};
+use tokio_rustls::{rustls, TlsAcceptor};
+
+use crate::{article::Article, datetime, server::find_article_by_url, GeminiConfig, ServerConfig};
+
+/// Requests are a URL of at most 1024 bytes followed by CRLF
+const MAX_REQUEST: u64 = 1026;
+
+/// Serves the blog over the Gemini protocol from the same database as the web server
+pub async fn serve(
+    pool: Pool<Sqlite>,
+    config: ServerConfig,
+    gemini: GeminiConfig,
+) -> miette::Result<()> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(
+        File::open(&gemini.cert).into_diagnostic()?,
+    ))
+    .collect::<Result<Vec<_>, _>>()
+    .into_diagnostic()?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(
+        File::open(&gemini.key).into_diagnostic()?,
+    ))
+    .into_diagnostic()?
+    .ok_or(miette!("no private key found in {}", gemini.key))?;
+    let tls = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .into_diagnostic()?;
+    let acceptor = TlsAcceptor::from(Arc::new(tls));
+
+    let listener = TcpListener::bind(&gemini.addr).await.into_diagnostic()?;
+    tracing::info!(
+        "serving {} over gemini on {}",
+        config.blog_name,
+        gemini.addr
+    );
+    loop {
+        let (stream, peer) = listener.accept().await.into_diagnostic()?;
+        let acceptor = acceptor.clone();
+        let pool = pool.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            let result = async {
+                let mut stream = acceptor.accept(stream).await.into_diagnostic()?;
+                let mut line = String::new();
+                AsyncBufReader::new(&mut stream)
+                    .take(MAX_REQUEST)
+                    .read_line(&mut line)
+                    .await
+                    .into_diagnostic()?;
+                let response = respond(line.trim_end(), &pool, &config).await;
+                stream
+                    .write_all(response.as_bytes())
+                    .await
+                    .into_diagnostic()?;
+                stream.shutdown().await.into_diagnostic()
+            }
+            .await;
+            if let Err(e) = result {
+                tracing::warn!(client = %peer, "gemini request failed: {e}");
+            }
+        });
+    }
+}
+
+/// Builds the full response, including the status line, for a request URL
+async fn respond(url: &str, pool: &Pool<Sqlite>, config: &ServerConfig) -> String {
+    let Some(path) = url
+        .strip_prefix("gemini://")
+        .map(|rest| rest.find('/').map_or("/", |i| &rest[i..]))
+    else {
+        return "59 Only gemini:// URLs are served here\r\n".to_string();
+    };
+
+    let page = match path.split_once('?').map_or(path, |(path, _)| path) {
+        "/" | "" => index(pool, config).await,
+        path => match path.strip_prefix("/article/") {
+            Some(url) => article(url, pool, config).await,
+            None => Ok(None),
+        },
+    };
+    match page {
+        Ok(Some(body)) => format!("20 text/gemini; charset=utf-8\r\n{body}"),
+        Ok(None) => "51 Not found\r\n".to_string(),
+        Err(e) => {
+            tracing::error!("failed to render gemini page: {e}");
+            "40 Temporary failure\r\n".to_string()
+        }
+    }
+}
+
+async fn index(pool: &Pool<Sqlite>, config: &ServerConfig) -> miette::Result<Option<String>> {
+    let articles = sqlx::query_as!(
+        Article,
+        "SELECT * FROM articles WHERE status = 'published' ORDER BY published DESC"
+    )
+    .fetch_all(pool)
+    .await
+    .into_diagnostic()?;
+
+    let mut page = format!("# {}\n\n{}\n\n", config.blog_name, config.description);
+    for article in articles {
+        page.push_str(&format!(
+            "=> /article/{} {} {}\n",
+            article.url(),
+            datetime::local(article.published).format("%Y-%m-%d"),
+            article.title
+        ));
+    }
+    Ok(Some(page))
+}
+
+async fn article(
+    url: &str,
+    pool: &Pool<Sqlite>,
+    config: &ServerConfig,
+) -> miette::Result<Option<String>> {
+    let url = percent_encoding::percent_decode_str(url)
+        .decode_utf8()
+        .into_diagnostic()?;
+    let mut conn = pool.acquire().await.into_diagnostic()?;
+    let Some(article) = find_article_by_url(&url, &mut conn).await? else {
+        return Ok(None);
+    };
+
+    Ok(Some(format!(
+        "# {}\n\n{} | {}\n\n{}\n=> / Back to {}\n",
+        article.title,
+        config.author,
+        article.published(),
+        to_gemtext(&article.content),
+        config.blog_name
+    )))
+}
+
+/// Converts markdown to gemtext. Gemtext has no inline markup, so links are
+/// listed on their own lines after the block they appear in.
+pub fn to_gemtext(markdown: &str) -> String {
+    let mut options = Options::default();
+    options.extension.footnotes = true;
+    options.extension.table = true;
+    let arena = Arena::new();
+    let root = comrak::parse_document(&arena, markdown, &options);
+
+    let mut gemtext = String::new();
+    for block in root.children() {
+        block_to_gemtext(block, &mut gemtext);
+    }
+    gemtext
+}
+
+fn block_to_gemtext<'a>(node: &'a AstNode<'a>, out: &mut String) {
+    let mut links = Vec::new();
+    match &node.data.borrow().value {
+        NodeValue::Heading(heading) => {
+            let level = "#".repeat(heading.level.min(3) as usize);
+            out.push_str(&format!("{level} {}\n", inline_text(node, &mut links)));
+        }
+        NodeValue::CodeBlock(code) => {
+            out.push_str(&format!("```\n{}```\n", code.literal));
+        }
+        NodeValue::BlockQuote => {
+            for child in node.children() {
+                out.push_str(&format!("> {}\n", inline_text(child, &mut links)));
+            }
+        }
+        NodeValue::List(_) => {
+            for item in node.children() {
+                out.push_str(&format!("* {}\n", inline_text(item, &mut links)));
+            }
+        }
+        NodeValue::Table(_) => {
+            out.push_str("```\n");
+            for row in node.children() {
+                let cells = row
+                    .children()
+                    .map(|cell| inline_text(cell, &mut links))
+                    .collect::<Vec<_>>();
+                out.push_str(&format!("{}\n", cells.join(" | ")));
+            }
+            out.push_str("```\n");
+        }
+        NodeValue::HtmlBlock(_) | NodeValue::ThematicBreak => return,
+        _ => out.push_str(&format!("{}\n", inline_text(node, &mut links))),
+    }
+    for (url, text) in links {
+        out.push_str(&format!("=> {url} {text}\n"));
+    }
+    out.push('\n');
+}
+
+/// The plain text of a node, collecting the links it contains
+fn inline_text<'a>(node: &'a AstNode<'a>, links: &mut Vec<(String, String)>) -> String {
+    let mut text = String::new();
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::Text(t) => text.push_str(t),
+            NodeValue::Code(code) => text.push_str(&code.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => text.push(' '),
+            NodeValue::Link(link) | NodeValue::Image(link) => {
+                let label = inline_text(child, links);
+                links.push((link.url.clone(), label.clone()));
+                text.push_str(&label);
+            }
+            NodeValue::Paragraph if !text.is_empty() => {
+                text.push(' ');
+                text.push_str(&inline_text(child, links));
+            }
+            _ => text.push_str(&inline_text(child, links)),
+        }
+    }
+    text
+}
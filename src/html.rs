@@ -0,0 +1,91 @@
+use comrak::Options;
+use serde::{Deserialize, Serialize};
+
+use crate::{article::Article, HtmlConfig};
+
+/// What happens to HTML written into an article's markdown
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum HtmlPolicy {
+    /// Shown as text
+    #[default]
+    Escape,
+    /// Kept, minus anything that could run scripts or break the page
+    Sanitize,
+    /// Kept as written, for authors who are trusted with scripts
+    Allow,
+}
+
+impl HtmlPolicy {
+    pub fn parse(policy: &str) -> Option<Self> {
+        match policy {
+            "escape" => Some(Self::Escape),
+            "sanitize" => Some(Self::Sanitize),
+            "allow" => Some(Self::Allow),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Escape => "escape",
+            Self::Sanitize => "sanitize",
+            Self::Allow => "allow",
+        }
+    }
+}
+
+/// Attributes comrak puts on the HTML it renders, which sanitizing has to keep
+const RENDERED_ATTRIBUTES: &[&str] = &[
+    "id",
+    "class",
+    "aria-hidden",
+    "aria-label",
+    "data-footnotes",
+    "data-footnote-ref",
+    "data-footnote-backref",
+    "data-footnote-backref-idx",
+];
+
+impl HtmlConfig {
+    /// The policy for an article, which may override the blog's
+    pub fn policy(&self, article: &Article) -> HtmlPolicy {
+        article
+            .html
+            .as_deref()
+            .and_then(HtmlPolicy::parse)
+            .unwrap_or(self.policy)
+    }
+
+    /// Renders an article's markdown with the given options, treating HTML in it as its
+    /// policy says
+    pub fn render(&self, article: &Article, mut options: Options) -> String {
+        let policy = self.policy(article);
+        self.configure(policy, &mut options);
+        let html = comrak::markdown_to_html(&article.content, &options);
+        match policy {
+            HtmlPolicy::Sanitize => self.sanitize(&html),
+            _ => html,
+        }
+    }
+
+    /// Sets up comrak to escape, keep or keep and filter HTML
+    fn configure(&self, policy: HtmlPolicy, options: &mut Options) {
+        options.render.escape = policy == HtmlPolicy::Escape;
+        options.render.unsafe_ = policy != HtmlPolicy::Escape;
+        // GitHub's filter for `<script>` and the like only gets in the way of trusted authors
+        options.extension.tagfilter = policy != HtmlPolicy::Allow;
+    }
+
+    /// Removes everything but the allowed tags and harmless attributes from rendered HTML
+    fn sanitize(&self, html: &str) -> String {
+        ammonia::Builder::default()
+            .add_tags(["section"])
+            .add_tags(self.allowed_tags.iter().map(String::as_str))
+            .add_generic_attributes(RENDERED_ATTRIBUTES)
+            // Links get their `rel` when external links are marked
+            .link_rel(None)
+            .clean(html)
+            .to_string()
+    }
+}
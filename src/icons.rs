@@ -0,0 +1,162 @@
+use std::{collections::HashMap, path::Path};
+
+use miette::{miette, IntoDiagnostic};
+use resvg::{
+    tiny_skia::{FilterQuality, Pixmap, PixmapPaint, Transform},
+    usvg::{self, fontdb},
+};
+use serde_json::json;
+
+use crate::ServerConfig;
+
+/// Sizes of the generated touch icons: Apple's, and the two Android asks for in the manifest
+const TOUCH_SIZES: [u32; 3] = [180, 192, 512];
+/// Sizes bundled into `favicon.ico`
+const FAVICON_SIZES: [u32; 2] = [16, 32];
+
+/// The favicon and touch icons, generated from the configured icon when the server starts
+#[derive(Default)]
+pub struct Icons {
+    favicon: Option<Vec<u8>>,
+    /// PNGs by their width
+    touch: HashMap<u32, Vec<u8>>,
+}
+
+enum Source {
+    Svg(usvg::Tree),
+    Png(Pixmap),
+}
+
+impl Source {
+    fn load(path: &Path) -> miette::Result<Self> {
+        let data = std::fs::read(path).into_diagnostic()?;
+        if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("svg"))
+        {
+            let tree =
+                usvg::Tree::from_data(&data, &usvg::Options::default(), &fontdb::Database::new())
+                    .into_diagnostic()?;
+            Ok(Self::Svg(tree))
+        } else {
+            Ok(Self::Png(Pixmap::decode_png(&data).into_diagnostic()?))
+        }
+    }
+
+    /// Renders the icon as a square PNG, centering it if it isn't square itself
+    fn render(&self, size: u32) -> miette::Result<Vec<u8>> {
+        let (width, height) = match self {
+            Source::Svg(tree) => (tree.size().width(), tree.size().height()),
+            Source::Png(pixmap) => (pixmap.width() as f32, pixmap.height() as f32),
+        };
+        let scale = size as f32 / width.max(height);
+        let transform = Transform::from_row(
+            scale,
+            0.0,
+            0.0,
+            scale,
+            (size as f32 - width * scale) / 2.0,
+            (size as f32 - height * scale) / 2.0,
+        );
+
+        let mut pixmap = Pixmap::new(size, size).ok_or(miette!("could not allocate the icon"))?;
+        match self {
+            Source::Svg(tree) => resvg::render(tree, transform, &mut pixmap.as_mut()),
+            Source::Png(source) => pixmap.draw_pixmap(
+                0,
+                0,
+                source.as_ref(),
+                &PixmapPaint {
+                    quality: FilterQuality::Bicubic,
+                    ..PixmapPaint::default()
+                },
+                transform,
+                None,
+            ),
+        }
+        pixmap.encode_png().into_diagnostic()
+    }
+}
+
+/// Bundles PNGs into an ICO file, which may contain them as they are
+fn ico(images: &[(u32, Vec<u8>)]) -> Vec<u8> {
+    let mut ico = vec![0, 0, 1, 0];
+    ico.extend((images.len() as u16).to_le_bytes());
+    let mut offset = 6 + 16 * images.len() as u32;
+    for (size, png) in images {
+        // 0 means 256 pixels
+        let size = if *size >= 256 { 0 } else { *size as u8 };
+        ico.extend([size, size, 0, 0]);
+        ico.extend(1u16.to_le_bytes());
+        ico.extend(32u16.to_le_bytes());
+        ico.extend((png.len() as u32).to_le_bytes());
+        ico.extend(offset.to_le_bytes());
+        offset += png.len() as u32;
+    }
+    for (_, png) in images {
+        ico.extend(png);
+    }
+    ico
+}
+
+impl Icons {
+    /// Generates all icons from a PNG or SVG file
+    pub fn load(path: Option<&Path>) -> miette::Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let source = Source::load(path)
+            .map_err(|e| miette!("could not load the icon {}: {e}", path.display()))?;
+
+        let favicon = FAVICON_SIZES
+            .into_iter()
+            .map(|size| Ok((size, source.render(size)?)))
+            .collect::<miette::Result<Vec<_>>>()?;
+        let touch = TOUCH_SIZES
+            .into_iter()
+            .map(|size| Ok((size, source.render(size)?)))
+            .collect::<miette::Result<_>>()?;
+
+        Ok(Self {
+            favicon: Some(ico(&favicon)),
+            touch,
+        })
+    }
+
+    /// Whether an icon is configured, so pages can link to it
+    pub fn is_configured(&self) -> bool {
+        self.favicon.is_some()
+    }
+
+    pub fn favicon(&self) -> Option<&[u8]> {
+        self.favicon.as_deref()
+    }
+
+    /// The touch icon of the given width
+    pub fn touch(&self, size: u32) -> Option<&[u8]> {
+        self.touch.get(&size).map(Vec::as_slice)
+    }
+
+    /// The web app manifest, which lets browsers install the blog on the home screen
+    pub fn manifest(&self, config: &ServerConfig) -> serde_json::Value {
+        let icons: Vec<_> = TOUCH_SIZES[1..]
+            .iter()
+            .filter(|size| self.touch.contains_key(size))
+            .map(|size| {
+                json!({
+                    "src": format!("/icons/{size}.png"),
+                    "sizes": format!("{size}x{size}"),
+                    "type": "image/png",
+                })
+            })
+            .collect();
+        json!({
+            "name": config.blog_name,
+            "short_name": config.blog_name,
+            "description": config.description,
+            "start_url": "/",
+            "display": "browser",
+            "icons": icons,
+        })
+    }
+}
@@ -0,0 +1,166 @@
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use miette::{miette, IntoDiagnostic};
+use reqwest::Client;
+use sqlx::{Connection, Pool, Sqlite, SqliteConnection};
+
+use crate::{
+    article::{Article, DRAFT},
+    server::insert_article,
+    tag, IngestConfig,
+};
+
+/// An entry of an RSS or Atom feed
+struct Item {
+    /// Identifies the item within its feed
+    guid: String,
+    title: String,
+    /// The item's HTML content, or its summary if the feed has no content
+    html: String,
+    link: Option<String>,
+    published: Option<NaiveDateTime>,
+    categories: Vec<String>,
+}
+
+fn parse_rss(body: &[u8]) -> Option<Vec<Item>> {
+    let channel = rss::Channel::read_from(body).ok()?;
+    let items = channel
+        .items()
+        .iter()
+        .filter_map(|item| {
+            let guid = item
+                .guid()
+                .map(|g| g.value().to_string())
+                .or(item.link().map(str::to_string))
+                .or(item.title().map(str::to_string))?;
+            Some(Item {
+                guid,
+                title: item.title().unwrap_or("Untitled").to_string(),
+                html: item
+                    .content()
+                    .or(item.description())
+                    .unwrap_or_default()
+                    .to_string(),
+                link: item.link().map(str::to_string),
+                published: item
+                    .pub_date()
+                    .and_then(|d| DateTime::parse_from_rfc2822(d).ok())
+                    .map(|d| d.naive_utc()),
+                categories: item
+                    .categories()
+                    .iter()
+                    .map(|c| c.name().to_string())
+                    .collect(),
+            })
+        })
+        .collect();
+    Some(items)
+}
+
+fn parse_atom(body: &[u8]) -> Option<Vec<Item>> {
+    let feed = atom_syndication::Feed::read_from(body).ok()?;
+    let items = feed
+        .entries()
+        .iter()
+        .map(|entry| Item {
+            guid: entry.id().to_string(),
+            title: entry.title().value.clone(),
+            html: entry
+                .content()
+                .and_then(|c| c.value())
+                .map(str::to_string)
+                .or(entry.summary().map(|s| s.value.clone()))
+                .unwrap_or_default(),
+            link: entry.links().first().map(|l| l.href().to_string()),
+            published: Some(entry.published().unwrap_or(entry.updated()).naive_utc()),
+            categories: entry
+                .categories()
+                .iter()
+                .map(|c| c.term().to_string())
+                .collect(),
+        })
+        .collect();
+    Some(items)
+}
+
+/// Creates a draft for every item of the feed that wasn't ingested before, returning
+/// how many there were
+pub async fn ingest(feed: &str, body: &[u8], conn: &mut SqliteConnection) -> miette::Result<u64> {
+    let items = parse_rss(body)
+        .or_else(|| parse_atom(body))
+        .ok_or(miette!("{feed} is neither an RSS nor an Atom feed"))?;
+
+    let mut tx = conn.begin().await.into_diagnostic()?;
+    let mut created = 0;
+    for item in items {
+        let seen = sqlx::query!(
+            "SELECT article FROM ingested_items WHERE feed = ? AND guid = ?",
+            feed,
+            item.guid
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .into_diagnostic()?;
+        if seen.is_some() {
+            continue;
+        }
+
+        let mut article = Article::new(item.title, html2md::parse_html(&item.html));
+        article.status = DRAFT.to_string();
+        article.canonical_url = item.link;
+        if let Some(published) = item.published {
+            article.published = published;
+        }
+        insert_article(&article, &mut tx).await?;
+        tag::set_tags(&article.id, &item.categories, &mut tx).await?;
+
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "INSERT INTO ingested_items ( feed, guid, article, created ) VALUES (?1, ?2, ?3, ?4)",
+            feed,
+            item.guid,
+            article.id,
+            now
+        )
+        .execute(&mut *tx)
+        .await
+        .into_diagnostic()?;
+        tracing::info!(id = %article.id, title = %article.title, "created a draft from {feed}");
+        created += 1;
+    }
+    tx.commit().await.into_diagnostic()?;
+    Ok(created)
+}
+
+/// Fetches the feed and ingests its new items
+async fn poll(client: &Client, feed: &str, pool: &Pool<Sqlite>) -> miette::Result<u64> {
+    let body = client
+        .get(feed)
+        .send()
+        .await
+        .into_diagnostic()?
+        .error_for_status()
+        .into_diagnostic()?
+        .bytes()
+        .await
+        .into_diagnostic()?;
+    let mut conn = pool.acquire().await.into_diagnostic()?;
+    ingest(feed, &body, &mut conn).await
+}
+
+/// Polls the configured feeds in the configured interval until the server shuts down
+pub async fn run(pool: Pool<Sqlite>, config: IngestConfig) {
+    if config.feeds.is_empty() {
+        return;
+    }
+    let client = Client::new();
+    loop {
+        for feed in &config.feeds {
+            if let Err(e) = poll(&client, feed, &pool).await {
+                tracing::error!(%feed, "ingesting the feed failed: {e}");
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(config.interval)).await;
+    }
+}
@@ -0,0 +1,270 @@
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use comfy_table::{Row, Table};
+use miette::{miette, IntoDiagnostic};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite, SqliteConnection};
+
+use crate::{
+    archive, comment, datetime,
+    email::{Email, Mailer},
+    server::connect,
+};
+
+/// How often a failing job is attempted before it is given up on
+const MAX_ATTEMPTS: i64 = 5;
+/// How long the worker sleeps when there is nothing to do
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Work that is done in the background, outside of any request
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Job {
+    /// Send a JSON payload to a webhook
+    Webhook {
+        url: String,
+        payload: serde_json::Value,
+    },
+    /// Send an email through the configured SMTP server
+    SendEmail { email: Email },
+    /// Have the Wayback Machine capture a published article
+    ArchiveSnapshot { article: String, url: String },
+    /// Email the subscribers of an article about a comment that is still visible
+    NotifySubscribers {
+        comment: String,
+        /// The commenter's own address, which isn't notified
+        except: Option<String>,
+        blog_name: String,
+        link: String,
+        /// Unsubscribe links are this followed by the subscription's token
+        unsubscribe: String,
+    },
+}
+
+/// What jobs need to do their work
+pub struct JobContext {
+    pub client: Client,
+    pub mailer: Option<Mailer>,
+    pub pool: Pool<Sqlite>,
+}
+
+impl Job {
+    fn describe(&self) -> String {
+        match self {
+            Job::Webhook { url, .. } => format!("webhook to {url}"),
+            Job::SendEmail { email } => format!("email to {}", email.to),
+            Job::ArchiveSnapshot { url, .. } => format!("snapshot of {url}"),
+            Job::NotifySubscribers { comment, .. } => {
+                format!("notifications about comment {comment}")
+            }
+        }
+    }
+
+    pub async fn run(&self, ctx: &JobContext) -> miette::Result<()> {
+        match self {
+            Job::Webhook { url, payload } => {
+                ctx.client
+                    .post(url)
+                    .json(payload)
+                    .send()
+                    .await
+                    .into_diagnostic()?
+                    .error_for_status()
+                    .into_diagnostic()?;
+            }
+            Job::SendEmail { email } => {
+                ctx.mailer
+                    .as_ref()
+                    .ok_or(miette!("email is not configured"))?
+                    .send(email)
+                    .await?;
+            }
+            Job::ArchiveSnapshot { article, url } => {
+                let snapshot = archive::snapshot(&ctx.client, url).await?;
+                let mut conn = ctx.pool.acquire().await.into_diagnostic()?;
+                archive::record(article, &snapshot, &mut conn).await?;
+            }
+            Job::NotifySubscribers {
+                comment,
+                except,
+                blog_name,
+                link,
+                unsubscribe,
+            } => {
+                let mut conn = ctx.pool.acquire().await.into_diagnostic()?;
+                comment::notify_subscribers(
+                    comment,
+                    except.as_deref(),
+                    blog_name,
+                    link,
+                    unsubscribe,
+                    &mut conn,
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Adds a job to the queue, to be picked up by the worker as soon as possible
+pub async fn enqueue(job: &Job, conn: &mut SqliteConnection) -> miette::Result<()> {
+    enqueue_at(job, Utc::now().naive_utc(), conn).await
+}
+
+/// Adds a job to the queue that is not run before `run_at`
+pub async fn enqueue_at(
+    job: &Job,
+    run_at: NaiveDateTime,
+    conn: &mut SqliteConnection,
+) -> miette::Result<()> {
+    let payload = serde_json::to_string(job).into_diagnostic()?;
+    let now = Utc::now().naive_utc();
+
+    sqlx::query!(
+        "INSERT INTO jobs ( payload, run_at, created ) VALUES (?1, ?2, ?3)",
+        payload,
+        run_at,
+        now
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?;
+
+    Ok(())
+}
+
+/// Runs due jobs one after another until the server shuts down.
+/// Failed jobs are retried with exponential backoff.
+pub async fn worker(pool: Pool<Sqlite>, ctx: JobContext) {
+    loop {
+        match run_next(&pool, &ctx).await {
+            Ok(true) => (),
+            Ok(false) => tokio::time::sleep(POLL_INTERVAL).await,
+            Err(e) => {
+                tracing::error!("job worker failed: {e}");
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+/// Runs the next due job, if any. Returns whether there was one.
+async fn run_next(pool: &Pool<Sqlite>, ctx: &JobContext) -> miette::Result<bool> {
+    let now = Utc::now().naive_utc();
+    let Some(row) = sqlx::query!(
+        "SELECT id, payload, attempts FROM jobs WHERE status = 'pending' AND run_at <= ? ORDER BY run_at LIMIT 1",
+        now
+    )
+    .fetch_optional(pool)
+    .await
+    .into_diagnostic()?
+    else {
+        return Ok(false);
+    };
+
+    let result = match serde_json::from_str::<Job>(&row.payload) {
+        Ok(job) => {
+            tracing::info!("running job {}: {}", row.id, job.describe());
+            job.run(ctx).await
+        }
+        Err(e) => Err(miette!("could not decode job: {e}")),
+    };
+
+    match result {
+        Ok(()) => {
+            sqlx::query!(
+                "UPDATE jobs SET status = 'done', attempts = attempts + 1, last_error = NULL WHERE id = ?",
+                row.id
+            )
+            .execute(pool)
+            .await
+            .into_diagnostic()?;
+        }
+        Err(e) => {
+            let attempts = row.attempts + 1;
+            let status = if attempts >= MAX_ATTEMPTS {
+                "failed"
+            } else {
+                "pending"
+            };
+            let run_at = Utc::now().naive_utc() + chrono::Duration::seconds(30 << attempts);
+            let error = e.to_string();
+            tracing::warn!("job {} failed (attempt {attempts}): {error}", row.id);
+
+            sqlx::query!(
+                "UPDATE jobs SET status = ?, attempts = ?, last_error = ?, run_at = ? WHERE id = ?",
+                status,
+                attempts,
+                error,
+                run_at,
+                row.id
+            )
+            .execute(pool)
+            .await
+            .into_diagnostic()?;
+        }
+    }
+
+    Ok(true)
+}
+
+pub async fn list_jobs() -> miette::Result<()> {
+    let mut conn = connect().await?;
+
+    let jobs = sqlx::query!(
+        "SELECT id, payload, status, attempts, last_error, run_at FROM jobs ORDER BY id DESC LIMIT 50"
+    )
+    .fetch_all(&mut conn)
+    .await
+    .into_diagnostic()?;
+
+    let mut table = Table::new();
+    table.set_header(Row::from(vec![
+        "ID",
+        "Job",
+        "Status",
+        "Attempts",
+        "Next Run",
+        "Last Error",
+    ]));
+    for row in jobs {
+        let job = serde_json::from_str::<Job>(&row.payload)
+            .map(|job| job.describe())
+            .unwrap_or(row.payload);
+        table.add_row([
+            &row.id.to_string(),
+            &job,
+            &row.status,
+            &row.attempts.to_string(),
+            &datetime::display(row.run_at),
+            &row.last_error.unwrap_or("-".to_string()),
+        ]);
+    }
+    println!("{table}");
+
+    Ok(())
+}
+
+/// Puts a job back into the queue, e.g. after it has failed too often
+pub async fn retry_job(id: i64) -> miette::Result<()> {
+    let mut conn = connect().await?;
+    let now = Utc::now().naive_utc();
+
+    let result = sqlx::query!(
+        "UPDATE jobs SET status = 'pending', attempts = 0, run_at = ? WHERE id = ?",
+        now,
+        id
+    )
+    .execute(&mut conn)
+    .await
+    .into_diagnostic()?;
+
+    if result.rows_affected() == 0 {
+        println!("There is no job with ID {id}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Allows each client address a fixed number of actions within a sliding time window
+pub struct RateLimiter {
+    hits: Mutex<HashMap<IpAddr, Vec<Instant>>>,
+    max: usize,
+    window: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(max: usize, window: Duration) -> Self {
+        Self {
+            hits: Mutex::default(),
+            max,
+            window,
+        }
+    }
+
+    /// Records an action by the given address. Returns `false` if it exceeds the limit.
+    pub fn check(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().unwrap();
+        hits.retain(|_, times| {
+            times.retain(|t| now.duration_since(*t) < self.window);
+            !times.is_empty()
+        });
+
+        let times = hits.entry(ip).or_default();
+        if times.len() >= self.max {
+            return false;
+        }
+        times.push(now);
+        true
+    }
+}
@@ -0,0 +1,61 @@
+use reqwest::Url;
+
+use crate::{sitemap::escape_xml, LinksConfig};
+
+/// Whether a link leads away from the blog
+fn is_external(href: &str, domain: Option<&str>) -> bool {
+    let Ok(url) = Url::parse(href) else {
+        return false;
+    };
+    matches!(url.scheme(), "http" | "https")
+        && url.host_str().is_some_and(|host| Some(host) != domain)
+}
+
+/// The `href` of an opening `<a>` tag, still escaped
+fn href(tag: &str) -> Option<&str> {
+    let start = tag.find("href=\"")? + "href=\"".len();
+    tag[start..].split('"').next()
+}
+
+impl LinksConfig {
+    /// Marks the links to other sites in rendered HTML, so they don't learn where the
+    /// reader came from and, if configured, open in a new tab and end with an icon
+    pub fn mark_external(&self, html: &str, domain: Option<&str>) -> String {
+        let mut output = String::with_capacity(html.len());
+        let mut rest = html;
+        while let Some(start) = rest.find("<a ") {
+            let Some(tag_end) = rest[start..].find('>').map(|end| start + end) else {
+                break;
+            };
+            let tag = &rest[start..tag_end];
+            let external = href(tag)
+                .map(|href| href.replace("&amp;", "&"))
+                .is_some_and(|href| is_external(&href, domain));
+            output.push_str(&rest[..start]);
+            rest = &rest[tag_end..];
+            if !external {
+                output.push_str(tag);
+                continue;
+            }
+
+            output.push_str("<a rel=\"noopener noreferrer\"");
+            if self.new_tab {
+                output.push_str(" target=\"_blank\"");
+            }
+            output.push_str(&tag["<a".len()..]);
+            if !self.icon.is_empty() {
+                let Some(end) = rest.find("</a>") else {
+                    continue;
+                };
+                output.push_str(&rest[..end]);
+                output.push_str(&format!(
+                    "<span class=\"external-link\" aria-hidden=\"true\">{}</span>",
+                    escape_xml(&self.icon)
+                ));
+                rest = &rest[end..];
+            }
+        }
+        output.push_str(rest);
+        output
+    }
+}
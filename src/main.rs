@@ -1,19 +1,90 @@
+mod accesslog;
+mod alias;
+mod anchors;
+mod appearance;
+mod archive;
 mod article;
+mod assets;
+mod attachment;
+mod auth;
+mod author;
+mod backup;
+mod ban;
+mod cache;
+mod changes;
 mod client;
 mod comment;
+mod contact;
+mod datetime;
+mod db;
+mod diagram;
+mod dump;
+mod email;
+mod epub;
 mod error;
+mod feed;
+mod frontmatter;
+mod gdpr;
+mod gemini;
+mod html;
+mod icons;
+mod ingest;
+mod jobs;
+mod limit;
+mod links;
+mod manual;
+mod media;
+mod mention;
+mod net;
+mod oembed;
+mod og;
+mod plugin;
+mod preview;
 mod request;
+mod retention;
+mod revision;
+mod scheduler;
+mod search;
+mod secret;
+mod seed;
 mod server;
+mod session;
+mod site;
+mod sitemap;
+mod stats;
+mod tag;
+#[cfg(test)]
+mod testing;
+mod totp;
+mod validation;
 
-use std::{collections::HashMap, net::SocketAddr};
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf, sync::Arc};
 
+use accesslog::AccessLog;
+use appearance::ColorScheme;
+use assets::Assets;
+use auth::Scope;
+use chrono::{NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
 use clap::{Args, Parser, Subcommand};
+use clap_complete::Shell;
+use db::CheckpointMode;
+use dump::DumpFormat;
+use error::ErrorPages;
 use figment::{
     providers::{Format, Toml},
     Figment,
 };
+use html::HtmlPolicy;
+use icons::Icons;
+use ipnet::IpNet;
 use miette::{miette, IntoDiagnostic};
-use serde::Deserialize;
+use net::ProxyHeader;
+use plugin::Plugins;
+use request::{ArticleFilter, ArticleSort};
+use serde::{de::Error as _, Deserialize, Deserializer};
+use tag::TagOrder;
+use tracing_subscriber::EnvFilter;
 
 #[derive(Parser)]
 #[command(author, version, about)]
@@ -22,10 +93,71 @@ pub enum Command {
     Serve,
     /// Publish an article to a blog
     Publish(Publish),
-    /// List all published articles
-    List,
-    /// Yank (delete) the article with the given ID
-    Yank { id: String },
+    /// List all published articles. Falls back to the local cache when offline.
+    List {
+        /// Only list articles published or edited after this time, e.g. 2024-03-01T12:00:00
+        #[arg(long)]
+        since: Option<NaiveDateTime>,
+        /// Only list articles published before this time
+        #[arg(long)]
+        until: Option<NaiveDateTime>,
+        /// Only list articles whose title contains this, ignoring case
+        #[arg(long)]
+        title_contains: Option<String>,
+        /// Only list articles with this tag. Needs a connection to the server.
+        #[arg(long)]
+        tag: Option<String>,
+        /// Only list articles created with the secret with this ID
+        #[arg(long)]
+        created_by: Option<i64>,
+        #[arg(long, value_enum, default_value = "published")]
+        sort: ArticleSort,
+    },
+    /// Search the titles and content of all articles, including drafts
+    Search { query: String },
+    /// Mirror all articles into a directory, fetching only what changed since the last export
+    Export { dir: PathBuf },
+    /// Print the article with the given ID, from the local cache when offline
+    Get { id: String },
+    /// Edit the article with the given ID in $EDITOR. Edits made offline are sent later.
+    Edit { id: String },
+    /// Copy the title and content of an article into a new one, e.g. for recurring formats
+    Clone {
+        id: String,
+        /// Keep the copy as a draft, to be published later with `update --publish`
+        #[arg(long)]
+        as_draft: bool,
+    },
+    /// Yank (delete) the article with the given ID after asking for confirmation
+    Yank {
+        id: String,
+        /// Don't ask for confirmation
+        #[arg(long, short, visible_alias = "yes", short_alias = 'y')]
+        force: bool,
+    },
+    /// Print a link anyone can read the article at, even while it is a draft
+    Preview {
+        id: String,
+        /// How many days the link works for
+        #[arg(long, default_value_t = 7)]
+        days: i64,
+    },
+    /// Print a markdown file as an HTML page rendered and styled exactly like the blog
+    /// would show it, e.g. `thoughtkeeper render post.md > post.html`
+    Render {
+        /// A markdown file, or a folder with an `index.md`
+        path: PathBuf,
+    },
+    /// Change the URL of an article
+    #[command(subcommand)]
+    Slug(SlugOperation),
+    /// Upload a file and print the URL articles can link it at
+    Upload {
+        path: PathBuf,
+        /// Defaults to a guess based on the file extension
+        #[arg(long)]
+        content_type: Option<String>,
+    },
     /// Update the title or content of an existing article
     Update {
         /// The article to update
@@ -36,16 +168,72 @@ pub enum Command {
         #[arg(short, long)]
         /// The path of the updated content
         path: Option<String>,
+        /// Replace the article's tags (can be given multiple times)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Publish the article if it is a draft
+        #[arg(long)]
+        publish: bool,
+        /// Announce the update with this note in the changes feed
+        #[arg(long)]
+        note: Option<String>,
     },
     /// Manage server-side secrets
     #[command(subcommand)]
     Secret(SecretOperation),
+    /// Check the email configuration
+    #[command(subcommand)]
+    Email(EmailOperation),
+    /// Read messages sent through the contact form
+    #[command(subcommand)]
+    Messages(MessageOperation),
+    /// Export or import the comments of an article
+    #[command(subcommand)]
+    Comments(CommentOperation),
+    /// Inspect and retry background jobs
+    #[command(subcommand)]
+    Jobs(JobOperation),
+    /// Export or delete everything stored about an email address
+    #[command(subcommand)]
+    Gdpr(GdprOperation),
+    /// Review articles submitted by guests
+    #[command(subcommand)]
+    Submissions(SubmissionOperation),
+    /// Fill the database with sample articles, tags and comments
+    Seed,
+    /// Maintain the database of the server
+    #[command(subcommand)]
+    Db(DbOperation),
+    /// Write all articles, comments, tags and secret metadata to stdout
+    Dump {
+        #[arg(long, value_enum, default_value = "json")]
+        format: DumpFormat,
+    },
+    /// Restore a dump into the database
+    Load { path: String },
+    /// Toggle maintenance mode, which shows a maintenance page on all public routes
+    #[command(subcommand)]
+    Maintenance(MaintenanceOperation),
+    /// Manage two-factor authentication for the admin panel
+    #[command(subcommand)]
+    TwoFactor(TwoFactorOperation),
+    /// Print the completion script for a shell, e.g. `thoughtkeeper completions bash`
+    Completions { shell: Shell },
+    /// Write man pages for all commands into a directory
+    Manpages { dir: PathBuf },
 }
 
 #[derive(Args)]
 pub struct Publish {
+    /// A markdown file, or a folder with an `index.md` and the images it uses
     path: String,
     title: Option<String>,
+    /// Tag the article (can be given multiple times)
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+    /// If another article already has the title's URL, append a number instead of failing
+    #[arg(long)]
+    force_slug: bool,
 }
 
 #[derive(Subcommand)]
@@ -54,6 +242,15 @@ pub enum SecretOperation {
     Create {
         #[arg(short, long)]
         description: Option<String>,
+        /// Require requests to be signed with the secret instead of sending it along
+        #[arg(long)]
+        hmac: bool,
+        /// What the secret may be used for. Guests should get `submit`.
+        #[arg(long, value_enum, default_value = "publish")]
+        scope: Scope,
+        /// Reject the secret from this day on, e.g. 2024-12-31
+        #[arg(long)]
+        expires: Option<NaiveDate>,
     },
     /// List the existing secrets by ID. Does not actually show the secrets.
     List,
@@ -61,11 +258,126 @@ pub enum SecretOperation {
     Revoke {
         #[arg(short, long)]
         id: i64,
+        /// Also yank every article created with the secret
+        #[arg(long)]
+        yank_articles: bool,
+    },
+    /// Revokes every secret and prints a new `publish` scoped one, e.g. after a leak
+    Purge {
+        /// Confirm that all clients lose access until they get the new secret
+        #[arg(long)]
+        confirm: bool,
     },
 }
 
+#[derive(Subcommand)]
+pub enum EmailOperation {
+    /// Send a test message to the given address
+    Test { to: String },
+}
+
+#[derive(Subcommand)]
+pub enum MessageOperation {
+    /// List all received messages
+    List,
+    /// Delete the message with the given ID
+    Delete { id: String },
+}
+
+#[derive(Subcommand)]
+pub enum CommentOperation {
+    /// Print the comments of an article as JSON
+    Export {
+        #[arg(long)]
+        article: String,
+    },
+    /// Restore comments printed by `export`
+    Import { path: String },
+    /// Discard the comments of a commenter from now on
+    Ban {
+        kind: ban::BanKind,
+        /// An IP address, an email address, or a name pattern with `*` and `?`
+        value: String,
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// List the banned commenters
+    Bans,
+    /// Lift the ban with the given ID
+    Unban { id: i64 },
+}
+
+#[derive(Subcommand)]
+pub enum JobOperation {
+    /// List the most recent background jobs
+    List,
+    /// Queue the job with the given ID to run again
+    Retry { id: i64 },
+}
+
+#[derive(Subcommand)]
+pub enum GdprOperation {
+    /// Print the messages and emails tied to the address as JSON
+    Export {
+        #[arg(long)]
+        email: String,
+    },
+    /// Delete the messages and emails tied to the address
+    Delete {
+        #[arg(long)]
+        email: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SubmissionOperation {
+    /// List submissions awaiting approval
+    List,
+    /// Publish the submission with the given ID
+    Approve { id: String },
+    /// Delete the submission with the given ID
+    Reject { id: String },
+}
+
+#[derive(Subcommand)]
+pub enum SlugOperation {
+    /// Serve the article at /article/<slug> from now on and redirect its old URL there
+    Set { id: String, slug: String },
+}
+
+#[derive(Subcommand)]
+pub enum TwoFactorOperation {
+    /// Turn two-factor authentication off, e.g. after losing the authenticator app
+    Disable,
+}
+
+#[derive(Subcommand)]
+pub enum DbOperation {
+    /// Copy the write-ahead log into the database
+    Checkpoint {
+        #[arg(long, value_enum, default_value = "passive")]
+        mode: CheckpointMode,
+    },
+    /// Check the integrity of the database, then run `ANALYZE` and `VACUUM`
+    Maintain,
+}
+
+#[derive(Subcommand)]
+pub enum MaintenanceOperation {
+    /// Enable maintenance mode
+    On,
+    /// Disable maintenance mode
+    Off,
+}
+
 #[derive(Deserialize)]
 pub struct Config {
+    /// Timezone timestamps are shown in, e.g. `Europe/Berlin`. They are stored in UTC.
+    timezone: Option<Tz>,
+    /// How timestamps are shown, in `strftime` syntax
+    date_format: Option<String>,
+    /// Language of month and day names in dates, e.g. `de_DE`
+    locale: Option<String>,
     server: Option<ServerConfig>,
     client: Option<ClientConfig>,
 }
@@ -77,23 +389,681 @@ pub struct ServerConfig {
     description: String,
     footer_links: HashMap<String, String>,
     addr: SocketAddr,
+    /// Serve the API and admin panel only on this address, e.g. `127.0.0.1:4445`, instead
+    /// of on `addr`
+    admin_addr: Option<SocketAddr>,
     domain: Option<String>,
+    /// URL or path of the blog's logo, used as the feed image
+    logo: Option<String>,
+    /// Square PNG or SVG the favicon and touch icons are generated from
+    icon: Option<PathBuf>,
+    /// License of all articles that don't specify their own
+    license: Option<String>,
+    #[serde(default)]
+    trust_proxy: bool,
+    /// Which header of the proxy to read the client address from if `trust_proxy` is on
+    #[serde(default)]
+    proxy_header: ProxyHeader,
+    #[serde(default)]
+    access: AccessConfig,
+    #[serde(default)]
+    api: ApiConfig,
+    #[serde(default)]
+    limits: LimitsConfig,
+    #[serde(default)]
+    database: DatabaseConfig,
+    #[serde(default)]
+    auth: AuthConfig,
+    /// URLs that are notified with a JSON payload whenever an article is published
+    #[serde(default)]
+    webhooks: Vec<String>,
+    /// Submit articles to the Wayback Machine when they are published. Requires `domain`.
+    #[serde(default)]
+    archive: bool,
+    /// Show the summaries of edits under articles, e.g. "Updated on March 1: Fixed a typo"
+    #[serde(default)]
+    edit_notes: bool,
+    /// Let anyone see the earlier versions of articles at `/article/<url>/history`. The
+    /// admin panel always shows them.
+    #[serde(default)]
+    public_history: bool,
+    email: Option<EmailConfig>,
+    #[serde(default)]
+    contact: ContactConfig,
+    #[serde(default)]
+    comments: CommentConfig,
+    #[serde(default)]
+    tags: TagConfig,
+    /// Buttons under the byline of articles
+    #[serde(default)]
+    actions: ActionsConfig,
+    /// How links to other sites in articles behave
+    #[serde(default)]
+    links: LinksConfig,
+    #[serde(default)]
+    popular: PopularConfig,
+    #[serde(default)]
+    retention: RetentionConfig,
+    /// Regular copies of the database, kept in a directory
+    backup: Option<BackupConfig>,
+    /// Feeds from other platforms that drafts are created from
+    #[serde(default)]
+    ingest: IngestConfig,
+    #[serde(default)]
+    robots: RobotsConfig,
+    #[serde(default)]
+    appearance: AppearanceConfig,
+    #[serde(default)]
+    session: SessionConfig,
+    /// Draw diagrams from `mermaid` code blocks in the browser
+    mermaid: Option<MermaidConfig>,
+    /// Also serve the blog over Gemini
+    gemini: Option<GeminiConfig>,
+    /// Obtain TLS certificates for `domain` from Let's Encrypt and serve HTTPS on `addr`
+    acme: Option<AcmeConfig>,
+    #[serde(default)]
+    plugins: PluginConfig,
+    /// Checks articles have to pass before they are stored
+    #[serde(default)]
+    validation: ValidationConfig,
+    /// What happens to HTML in articles
+    #[serde(default)]
+    html: HtmlConfig,
+    /// Directory with pages replacing the built-in error pages: `404.html`, `500.html` and
+    /// `503.html`
+    theme: Option<PathBuf>,
+    /// Log requests in the combined log format, apart from the application's log
+    access_log: Option<AccessLogConfig>,
+    /// Fingerprinted static files, hashed when the server starts
+    #[serde(skip)]
+    assets: Arc<Assets>,
+    /// The plugins, compiled when the server starts
+    #[serde(skip)]
+    plugin_runtime: Arc<Plugins>,
+    /// The access log, opened when the server starts
+    #[serde(skip)]
+    access_logger: Arc<AccessLog>,
+    /// The error pages from `theme`, read when the server starts
+    #[serde(skip)]
+    error_pages: Arc<ErrorPages>,
+    /// The icons generated from `icon` when the server starts
+    #[serde(skip)]
+    icons: Arc<Icons>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AccessLogConfig {
+    /// The file requests are logged to
+    path: PathBuf,
+    /// Size in bytes at which the log is rotated
+    #[serde(default = "AccessLogConfig::default_max_size")]
+    max_size: u64,
+    /// Number of rotated logs to keep, as `<path>.1` (the newest) to `<path>.<keep>`
+    #[serde(default = "AccessLogConfig::default_keep")]
+    keep: usize,
+}
+
+impl AccessLogConfig {
+    fn default_max_size() -> u64 {
+        10 * 1024 * 1024
+    }
+
+    fn default_keep() -> usize {
+        5
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct MermaidConfig {
+    /// The Mermaid ES module, e.g. a copy in `static` to not depend on a CDN
+    #[serde(default = "MermaidConfig::default_script")]
+    script: String,
+}
+
+impl MermaidConfig {
+    fn default_script() -> String {
+        "https://cdn.jsdelivr.net/npm/mermaid@10/dist/mermaid.esm.min.mjs".to_string()
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AcmeConfig {
+    /// Email addresses Let's Encrypt may contact about the certificates
+    #[serde(default)]
+    contact: Vec<String>,
+    /// Where certificates and the account key are stored between restarts
+    #[serde(default = "AcmeConfig::default_cache_dir")]
+    cache_dir: String,
+    /// Use the staging environment, which has generous rate limits but untrusted certificates
+    #[serde(default)]
+    staging: bool,
+}
+
+impl AcmeConfig {
+    fn default_cache_dir() -> String {
+        "acme".to_string()
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct GeminiConfig {
+    /// Address to serve Gemini on, usually port 1965
+    addr: SocketAddr,
+    /// PEM file with the TLS certificate chain
+    cert: String,
+    /// PEM file with the TLS private key
+    key: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct EmailConfig {
+    /// The SMTP server to send email through
+    host: String,
+    port: Option<u16>,
+    username: Option<String>,
+    password: Option<String>,
+    /// The sender address, e.g. `Blog <blog@example.com>`
+    from: String,
+    /// Address that is notified about new comments
+    notify: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct AccessConfig {
+    /// Networks that may use the API. If empty, the API is reachable from anywhere.
+    #[serde(default)]
+    api_allow: Vec<IpNet>,
+    /// Networks that are denied access to the whole site
+    #[serde(default)]
+    deny: Vec<IpNet>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Largest API request in bytes. Larger ones are answered with 413 before they are read.
+    max_body_size: usize,
+    /// Days a preview link may be valid for at most
+    max_preview_days: i64,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            max_body_size: 16 * 1024 * 1024,
+            max_preview_days: 90,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct LimitsConfig {
+    /// Seconds a request to the site or admin panel may take before it is answered with 408
+    request_timeout: u64,
+    /// Seconds an API request may take, including uploading its body
+    api_timeout: u64,
+    /// Largest body of a request to the site or admin panel in bytes, e.g. a comment
+    max_form_size: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout: 30,
+            api_timeout: 300,
+            max_form_size: 256 * 1024,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    /// Maximum number of connections in the pool
+    max_connections: u32,
+    /// Seconds to wait for a free connection before answering with 503
+    acquire_timeout: u64,
+    /// SQLite journal mode, e.g. `wal` or `delete`
+    journal_mode: String,
+    /// Milliseconds SQLite waits on a locked database before giving up
+    busy_timeout: u64,
+    /// SQLite synchronous level, e.g. `normal` or `full`
+    synchronous: String,
+    /// Log size in pages at which SQLite checkpoints on its own. 0 leaves checkpoints to
+    /// `checkpoint_interval` or a replication tool like Litestream.
+    wal_autocheckpoint: u32,
+    /// Seconds between checkpoints by the server, 0 for none
+    checkpoint_interval: u64,
+    checkpoint_mode: CheckpointMode,
+    /// Seconds between integrity checks, `ANALYZE` and `VACUUM`, 0 for none
+    maintain_interval: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            acquire_timeout: 5,
+            journal_mode: "wal".to_string(),
+            busy_timeout: 5000,
+            synchronous: "normal".to_string(),
+            wal_autocheckpoint: 1000,
+            checkpoint_interval: 0,
+            checkpoint_mode: CheckpointMode::Passive,
+            maintain_interval: 0,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Failed API authentication attempts before a client is locked out
+    max_failures: u32,
+    /// Seconds after a client's first failed attempt in which further failures count towards it
+    window: u64,
+    /// Seconds a client stays locked out
+    lockout: u64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            window: 900,
+            lockout: 900,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ContactConfig {
+    /// Messages a client may send through the contact form per window
+    max_messages: usize,
+    /// Length of the rate limiting window in seconds
+    window: u64,
+}
+
+impl Default for ContactConfig {
+    fn default() -> Self {
+        Self {
+            max_messages: 3,
+            window: 3600,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct CommentConfig {
+    /// Whether readers may comment at all. Existing comments are hidden while disabled.
+    enabled: bool,
+    /// Comments a client may post per window
+    max_comments: i64,
+    /// Length of the rate limiting window in seconds
+    window: u64,
+    /// Require commenters' browsers to solve a proof of work with this many leading zero bits
+    pow_difficulty: Option<u32>,
+    /// Questions commenters have to answer, one of which is picked at random
+    questions: Vec<CommentQuestion>,
+    /// Comments reported by this many readers are hidden until they are reviewed in the
+    /// admin panel. 0 turns off reporting.
+    flag_threshold: i64,
+    /// Seconds to wait before emailing subscribers about a new comment, so that comments
+    /// hidden in the meantime are never sent out
+    notify_delay: u64,
+}
+
+impl Default for CommentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_comments: 5,
+            window: 3600,
+            pow_difficulty: None,
+            questions: Vec::new(),
+            flag_threshold: 3,
+            notify_delay: 900,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct AppearanceConfig {
+    /// Stylesheet in `static` with the colors of the light scheme
+    light: String,
+    /// Stylesheet in `static` with the colors of the dark scheme
+    dark: String,
+    /// The scheme for visitors who haven't picked one, `auto` follows their system setting
+    default: ColorScheme,
+    /// Let visitors pick a scheme, which is remembered in a cookie
+    toggle: bool,
+    /// Symbol of the link next to headings in articles, for linking to a section
+    heading_link: String,
+    /// Symbol of the link from a footnote back to its reference, empty to leave it out
+    footnote_backlink: String,
+}
+
+impl Default for AppearanceConfig {
+    fn default() -> Self {
+        Self {
+            light: "light.css".to_string(),
+            dark: "dark.css".to_string(),
+            default: ColorScheme::Auto,
+            toggle: true,
+            heading_link: "#".to_string(),
+            footnote_backlink: "↩".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Seconds a browser session, including an admin login, stays valid
+    lifetime: u64,
+    /// Only send the session cookie over HTTPS
+    secure: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            lifetime: 7 * 24 * 3600,
+            secure: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct CommentQuestion {
+    question: String,
+    /// Compared ignoring case and surrounding whitespace
+    answer: String,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct TagConfig {
+    /// Whether to show the tag cloud on the index page
+    show_cloud: bool,
+    order: TagOrder,
+    /// Tags used by fewer articles are left out of the tag cloud
+    min_count: i64,
+}
+
+impl Default for TagConfig {
+    fn default() -> Self {
+        Self {
+            show_cloud: true,
+            order: TagOrder::Name,
+            min_count: 1,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ActionsConfig {
+    /// Offer the article's markdown at `/article/<url>/markdown`
+    markdown: bool,
+    /// Copy the article's canonical URL to the clipboard. Needs JavaScript.
+    copy_link: bool,
+}
+
+impl Default for ActionsConfig {
+    fn default() -> Self {
+        Self {
+            markdown: true,
+            copy_link: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct HtmlConfig {
+    /// `escape` shows HTML as text, `sanitize` keeps harmless tags and `allow` keeps
+    /// everything. Articles may override it.
+    policy: HtmlPolicy,
+    /// Tags `sanitize` keeps in addition to the usual formatting, e.g. `iframe`
+    allowed_tags: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct LinksConfig {
+    /// Open links to other sites in a new tab
+    new_tab: bool,
+    /// Shown after links to other sites, e.g. "↗". Empty to show nothing.
+    icon: String,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct PopularConfig {
+    /// Whether to show the most-read articles on the index page
+    enabled: bool,
+    /// How many articles to show
+    count: i64,
+    /// Only views within this many days are counted
+    window_days: i64,
+    /// Seconds the ranking is cached for
+    cache_secs: u64,
+}
+
+impl Default for PopularConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            count: 5,
+            window_days: 30,
+            cache_secs: 600,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct PluginConfig {
+    /// WebAssembly modules implementing any of the `on_publish`, `on_render` and `on_comment` hooks
+    modules: Vec<PathBuf>,
+    /// Instructions a plugin may execute per hook call before it is stopped
+    fuel: u64,
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        Self {
+            modules: Vec::new(),
+            fuel: 10_000_000,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct IngestConfig {
+    /// URLs of RSS or Atom feeds. Every new item becomes a draft.
+    feeds: Vec<String>,
+    /// Seconds between polling the feeds
+    interval: u64,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            feeds: Vec::new(),
+            interval: 3600,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Page views older than this many days are deleted. 0 keeps them forever.
+    views_days: i64,
+    /// Finished and failed jobs older than this many days are deleted. 0 keeps them forever.
+    jobs_days: i64,
+    /// Seconds between pruning runs
+    interval: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            views_days: 365,
+            jobs_days: 30,
+            interval: 3600,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BackupConfig {
+    /// Where the backups are written, e.g. a mounted network drive or a directory
+    /// synced to object storage
+    dir: PathBuf,
+    /// Seconds between backups, at least a minute
+    #[serde(
+        default = "BackupConfig::default_interval",
+        deserialize_with = "BackupConfig::interval"
+    )]
+    interval: u64,
+    /// How many backups are kept before the oldest ones are deleted, at least one
+    #[serde(
+        default = "BackupConfig::default_keep",
+        deserialize_with = "BackupConfig::keep"
+    )]
+    keep: usize,
+}
+
+impl BackupConfig {
+    fn default_interval() -> u64 {
+        86400
+    }
+
+    fn default_keep() -> usize {
+        7
+    }
+
+    fn interval<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let interval = u64::deserialize(deserializer)?;
+        if interval < 60 {
+            return Err(D::Error::custom(
+                "backups can be made once a minute at most",
+            ));
+        }
+        Ok(interval)
+    }
+
+    /// Keeping none would delete every backup right after it was made
+    fn keep<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+        let keep = usize::deserialize(deserializer)?;
+        if keep == 0 {
+            return Err(D::Error::custom("at least one backup has to be kept"));
+        }
+        Ok(keep)
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct RobotsConfig {
+    /// Paths all crawlers are asked to stay out of
+    disallow: Vec<String>,
+    /// User agents that are asked to stay away from the whole blog
+    blocked_agents: Vec<String>,
+}
+
+impl Default for RobotsConfig {
+    fn default() -> Self {
+        Self {
+            disallow: vec![
+                "/api".to_string(),
+                "/admin".to_string(),
+                "/xmlrpc".to_string(),
+            ],
+            blocked_agents: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ValidationConfig {
+    enabled: bool,
+    /// Longest allowed title in characters
+    max_title_length: usize,
+    /// Largest allowed content in bytes
+    max_content_size: usize,
+    /// Raw HTML tags articles may not contain
+    forbidden_tags: Vec<String>,
+    /// Require alt text on every image
+    require_alt_text: bool,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_title_length: 200,
+            max_content_size: 1024 * 1024,
+            forbidden_tags: ["script", "style", "iframe", "object", "embed", "form"]
+                .map(String::from)
+                .to_vec(),
+            require_alt_text: true,
+        }
+    }
 }
 
 #[derive(Deserialize)]
 pub struct ClientConfig {
     addr: String,
     secret: String,
+    #[serde(default)]
+    auth: AuthMode,
+    /// ID of the secret, needed to sign requests with HMAC
+    key_id: Option<i64>,
+}
+
+/// How the client authenticates API requests
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    /// Send the secret in the request body
+    #[default]
+    Bearer,
+    /// Sign the request body and a timestamp with the secret
+    Hmac,
 }
 
 #[tokio::main]
 async fn main() -> miette::Result<()> {
     let command = Command::parse();
 
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
     let config: Config = Figment::new()
         .merge(Toml::file("blog.toml"))
         .extract()
         .into_diagnostic()?;
+    datetime::configure(
+        config.timezone.unwrap_or(Tz::UTC),
+        config
+            .date_format
+            .as_deref()
+            .unwrap_or(datetime::DEFAULT_FORMAT),
+        config.locale.as_deref(),
+    )?;
 
     match command {
         Command::Serve => {
@@ -106,26 +1076,174 @@ async fn main() -> miette::Result<()> {
             )
             .await?
         }
-        Command::List => {
-            client::list(config.client.ok_or(miette!("no client config found"))?).await?
+        Command::List {
+            since,
+            until,
+            title_contains,
+            tag,
+            created_by,
+            sort,
+        } => {
+            let filter = ArticleFilter {
+                since,
+                until,
+                title_contains,
+                tag,
+                created_by,
+                sort,
+            };
+            client::list(
+                config.client.ok_or(miette!("no client config found"))?,
+                filter,
+            )
+            .await?
+        }
+        Command::Search { query } => {
+            client::search(
+                config.client.ok_or(miette!("no client config found"))?,
+                query,
+            )
+            .await?
+        }
+        Command::Export { dir } => {
+            client::export(config.client.ok_or(miette!("no client config found"))?, dir).await?
         }
-        Command::Yank { id } => {
-            client::yank(config.client.ok_or(miette!("no client config found"))?, id).await?
+        Command::Get { id } => {
+            client::get(config.client.ok_or(miette!("no client config found"))?, id).await?
+        }
+        Command::Edit { id } => {
+            client::edit(config.client.ok_or(miette!("no client config found"))?, id).await?
+        }
+        Command::Yank { id, force } => {
+            client::yank(
+                config.client.ok_or(miette!("no client config found"))?,
+                id,
+                force,
+            )
+            .await?
+        }
+        Command::Upload { path, content_type } => {
+            client::upload(
+                config.client.ok_or(miette!("no client config found"))?,
+                path,
+                content_type,
+            )
+            .await?
         }
-        Command::Update { id, title, path } => {
+        Command::Update {
+            id,
+            title,
+            path,
+            tags,
+            publish,
+            note,
+        } => {
             client::update(
                 config.client.ok_or(miette!("no client config found"))?,
                 id,
                 title,
                 path,
+                tags,
+                publish,
+                note,
+            )
+            .await?
+        }
+        Command::Preview { id, days } => {
+            client::preview(
+                config.client.ok_or(miette!("no client config found"))?,
+                id,
+                days,
+            )
+            .await?
+        }
+        Command::Render { path } => {
+            client::render(
+                config.client.ok_or(miette!("no client config found"))?,
+                path,
+            )
+            .await?
+        }
+        Command::Slug(SlugOperation::Set { id, slug }) => {
+            client::set_slug(
+                config.client.ok_or(miette!("no client config found"))?,
+                id,
+                slug,
+            )
+            .await?
+        }
+        Command::Clone { id, as_draft } => {
+            client::clone(
+                config.client.ok_or(miette!("no client config found"))?,
+                id,
+                as_draft,
             )
             .await?
         }
         Command::Secret(operation) => match operation {
-            SecretOperation::Create { description } => server::create_secret(description).await?,
+            SecretOperation::Create {
+                description,
+                hmac,
+                scope,
+                expires,
+            } => server::create_secret(description, hmac, scope, expires).await?,
             SecretOperation::List => server::list_secrets().await?,
-            SecretOperation::Revoke { id } => server::revoke_secret(id).await?,
+            SecretOperation::Revoke { id, yank_articles } => {
+                server::revoke_secret(id, yank_articles).await?
+            }
+            SecretOperation::Purge { confirm } => server::purge_secrets(confirm).await?,
+        },
+        Command::Email(EmailOperation::Test { to }) => {
+            email::send_test(config.server.ok_or(miette!("no server config found"))?, to).await?
+        }
+        Command::Messages(operation) => match operation {
+            MessageOperation::List => contact::list_messages().await?,
+            MessageOperation::Delete { id } => contact::delete_message(id).await?,
+        },
+        Command::Comments(operation) => match operation {
+            CommentOperation::Export { article } => comment::export(article).await?,
+            CommentOperation::Import { path } => comment::import(path).await?,
+            CommentOperation::Ban {
+                kind,
+                value,
+                reason,
+            } => ban::ban(kind, value, reason).await?,
+            CommentOperation::Bans => ban::list_bans().await?,
+            CommentOperation::Unban { id } => ban::unban(id).await?,
         },
+        Command::Submissions(operation) => match operation {
+            SubmissionOperation::List => server::list_submissions().await?,
+            SubmissionOperation::Approve { id } => {
+                let server = config.server.ok_or(miette!("no server config found"))?;
+                server::approve_submission(id, &server).await?
+            }
+            SubmissionOperation::Reject { id } => server::reject_submission(id).await?,
+        },
+        Command::Jobs(operation) => match operation {
+            JobOperation::List => jobs::list_jobs().await?,
+            JobOperation::Retry { id } => jobs::retry_job(id).await?,
+        },
+        Command::Gdpr(operation) => match operation {
+            GdprOperation::Export { email } => gdpr::export(email).await?,
+            GdprOperation::Delete { email } => gdpr::delete(email).await?,
+        },
+        Command::Seed => seed::seed().await?,
+        Command::Db(operation) => match operation {
+            DbOperation::Checkpoint { mode } => db::checkpoint_now(mode).await?,
+            DbOperation::Maintain => db::maintain_now().await?,
+        },
+        Command::Dump { format } => dump::dump(format).await?,
+        Command::Load { path } => dump::load(path).await?,
+        Command::TwoFactor(TwoFactorOperation::Disable) => totp::reset().await?,
+        Command::Completions { shell } => manual::completions(shell),
+        Command::Manpages { dir } => manual::manpages(&dir)?,
+        Command::Maintenance(operation) => {
+            client::set_maintenance(
+                config.client.ok_or(miette!("no client config found"))?,
+                matches!(operation, MaintenanceOperation::On),
+            )
+            .await?
+        }
     }
 
     Ok(())
@@ -0,0 +1,37 @@
+use std::{fs, io, path::Path};
+
+use clap::CommandFactory;
+use clap_complete::Shell;
+use clap_mangen::Man;
+use miette::IntoDiagnostic;
+
+use crate::Command;
+
+/// Prints the completion script for the shell
+pub fn completions(shell: Shell) {
+    let mut command = Command::command();
+    clap_complete::generate(shell, &mut command, "thoughtkeeper", &mut io::stdout());
+}
+
+/// Writes a man page for thoughtkeeper and one for each of its subcommands into the
+/// directory, e.g. `thoughtkeeper-publish.1`
+pub fn manpages(dir: &Path) -> miette::Result<()> {
+    fs::create_dir_all(dir).into_diagnostic()?;
+    write_manpages(&Command::command(), "thoughtkeeper", dir)
+}
+
+fn write_manpages(command: &clap::Command, name: &str, dir: &Path) -> miette::Result<()> {
+    let command = command.clone().display_name(name);
+    let mut page = Vec::new();
+    Man::new(command.clone())
+        .title(name)
+        .render(&mut page)
+        .into_diagnostic()?;
+    fs::write(dir.join(format!("{name}.1")), page).into_diagnostic()?;
+
+    for subcommand in command.get_subcommands().filter(|c| !c.is_hide_set()) {
+        let name = format!("{name}-{}", subcommand.get_name());
+        write_manpages(subcommand, &name, dir)?;
+    }
+    Ok(())
+}
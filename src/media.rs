@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{NaiveDateTime, Utc};
+use miette::IntoDiagnostic;
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+use uuid::Uuid;
+
+use crate::{article::url_of, datetime};
+
+/// An uploaded file, without its contents
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Media {
+    pub id: String,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    #[serde(with = "datetime::rfc3339")]
+    pub uploaded: NaiveDateTime,
+    /// The articles linking to the file
+    #[serde(default)]
+    pub used_by: Vec<MediaUsage>,
+}
+
+/// An article linking to an uploaded file
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MediaUsage {
+    pub title: String,
+    pub url: String,
+}
+
+impl Media {
+    /// Where the file is served
+    pub fn url(&self) -> String {
+        format!("/media/{}/{}", self.id, self.filename)
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.content_type.starts_with("image/")
+    }
+
+    pub fn uploaded(&self) -> String {
+        datetime::display(self.uploaded)
+    }
+
+    /// The size in a human readable unit
+    pub fn size(&self) -> String {
+        match self.size {
+            size if size < 1024 => format!("{size} B"),
+            size if size < 1024 * 1024 => format!("{:.1} KiB", size as f64 / 1024.0),
+            size => format!("{:.1} MiB", size as f64 / (1024.0 * 1024.0)),
+        }
+    }
+}
+
+/// The width and height of an image in pixels
+pub type Dimensions = (i64, i64);
+
+/// The ID of the upload a URL on the blog points to
+pub fn id_of(url: &str) -> Option<&str> {
+    url.strip_prefix("/media/")?.split('/').next()
+}
+
+/// The dimensions of an image, if its format is known
+fn measure(content_type: &str, data: &[u8]) -> Option<Dimensions> {
+    if !content_type.starts_with("image/") {
+        return None;
+    }
+    let size = imagesize::blob_size(data).ok()?;
+    Some((size.width as i64, size.height as i64))
+}
+
+/// Keeps only the last path component and characters that are safe in a URL
+fn sanitize_filename(filename: &str) -> String {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.trim_matches('.').is_empty() {
+        "file".to_string()
+    } else {
+        name
+    }
+}
+
+/// Stores a base64 encoded upload and returns it
+pub async fn upload(
+    filename: &str,
+    content_type: &str,
+    data: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Media> {
+    let data = STANDARD.decode(data).into_diagnostic()?;
+    store(filename, content_type, data, conn).await
+}
+
+/// Stores an upload and returns it
+pub async fn store(
+    filename: &str,
+    content_type: &str,
+    data: Vec<u8>,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Media> {
+    let media = Media {
+        id: Uuid::new_v4().to_string(),
+        filename: sanitize_filename(filename),
+        content_type: content_type.to_string(),
+        size: data.len() as i64,
+        uploaded: Utc::now().naive_utc(),
+        used_by: Vec::new(),
+    };
+
+    let (width, height) = measure(content_type, &data).unzip();
+    sqlx::query!(
+        "INSERT INTO media ( id, filename, content_type, size, data, uploaded, width, height ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        media.id,
+        media.filename,
+        media.content_type,
+        media.size,
+        data,
+        media.uploaded,
+        width,
+        height
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?;
+
+    Ok(media)
+}
+
+/// All uploads, newest first, along with the articles using them
+pub async fn list(conn: &mut SqliteConnection) -> miette::Result<Vec<Media>> {
+    let rows = sqlx::query!(
+        "SELECT id, filename, content_type, size, uploaded FROM media ORDER BY uploaded DESC"
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .into_diagnostic()?;
+
+    let mut media = Vec::with_capacity(rows.len());
+    for row in rows {
+        let pattern = format!("%/media/{}%", row.id);
+        let used_by = sqlx::query!(
+            "SELECT title, slug FROM articles WHERE content LIKE ? ORDER BY published",
+            pattern
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .into_diagnostic()?
+        .into_iter()
+        .map(|r| MediaUsage {
+            url: url_of(&r.title, r.slug.as_deref()),
+            title: r.title,
+        })
+        .collect();
+
+        media.push(Media {
+            id: row.id,
+            filename: row.filename,
+            content_type: row.content_type,
+            size: row.size,
+            uploaded: row.uploaded,
+            used_by,
+        });
+    }
+    Ok(media)
+}
+
+/// The content type and contents of an upload
+pub async fn contents(
+    id: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Option<(String, Vec<u8>)>> {
+    Ok(
+        sqlx::query!("SELECT content_type, data FROM media WHERE id = ?", id)
+            .fetch_optional(conn)
+            .await
+            .into_diagnostic()?
+            .map(|r| (r.content_type, r.data)),
+    )
+}
+
+/// The dimensions of the uploaded images among the URLs, by upload ID. Images uploaded
+/// before dimensions were recorded are measured now.
+pub async fn dimensions(
+    urls: &[String],
+    conn: &mut SqliteConnection,
+) -> miette::Result<HashMap<String, Dimensions>> {
+    let mut dimensions = HashMap::new();
+    for id in urls.iter().filter_map(|url| id_of(url)) {
+        let Some(row) = sqlx::query!(
+            "SELECT content_type, width, height FROM media WHERE id = ?",
+            id
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .into_diagnostic()?
+        else {
+            continue;
+        };
+        let measured = match (row.width, row.height) {
+            (Some(width), Some(height)) => Some((width, height)),
+            _ => {
+                let Some((content_type, data)) = contents(id, conn).await? else {
+                    continue;
+                };
+                let measured = measure(&content_type, &data);
+                if let Some((width, height)) = measured {
+                    sqlx::query!(
+                        "UPDATE media SET width = ?, height = ? WHERE id = ?",
+                        width,
+                        height,
+                        id
+                    )
+                    .execute(&mut *conn)
+                    .await
+                    .into_diagnostic()?;
+                }
+                measured
+            }
+        };
+        if let Some(measured) = measured {
+            dimensions.insert(id.to_string(), measured);
+        }
+    }
+    Ok(dimensions)
+}
+
+/// Lets browsers load the images in rendered HTML once they are about to be seen, and
+/// reserve the space of those with known dimensions so the page doesn't jump
+pub fn size_images(html: &str, dimensions: &HashMap<String, Dimensions>) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("<img ") {
+        output.push_str(&rest[..start]);
+        output.push_str("<img loading=\"lazy\"");
+        let tag = &rest[start..];
+        let size = tag
+            .find("src=\"")
+            .and_then(|src| tag[src + "src=\"".len()..].split('"').next())
+            .and_then(id_of)
+            .and_then(|id| dimensions.get(id));
+        if let Some((width, height)) = size {
+            output.push_str(&format!(" width=\"{width}\" height=\"{height}\""));
+        }
+        rest = &tag["<img".len()..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Deletes an upload. Returns whether it existed.
+pub async fn delete(id: &str, conn: &mut SqliteConnection) -> miette::Result<bool> {
+    let deleted = sqlx::query!("DELETE FROM media WHERE id = ?", id)
+        .execute(conn)
+        .await
+        .into_diagnostic()?
+        .rows_affected();
+    Ok(deleted > 0)
+}
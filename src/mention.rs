@@ -0,0 +1,223 @@
+use std::{net::SocketAddr, time::Duration};
+
+use chrono::{NaiveDateTime, Utc};
+use percent_encoding::percent_decode_str;
+use quick_xml::{events::Event, Reader};
+use reqwest::{header, redirect::Policy, Client, Url};
+use serde::{Deserialize, Serialize};
+use tokio::net::lookup_host;
+use url::Host;
+use uuid::Uuid;
+
+use crate::{net, sitemap::escape_xml};
+
+/// A link to one of the articles from another site
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Mention {
+    pub id: String,
+    pub article: String,
+    pub source: String,
+    pub kind: String,
+    pub received: NaiveDateTime,
+}
+
+impl Mention {
+    pub fn new(article: String, source: String, kind: &str) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            article,
+            source,
+            kind: kind.to_string(),
+            received: Utc::now().naive_utc(),
+        }
+    }
+}
+
+/// The fault codes defined by the pingback specification
+pub enum Fault {
+    Generic,
+    SourceNotFound,
+    NoLinkToTarget,
+    TargetNotFound,
+    TargetInvalid,
+    AlreadyRegistered,
+    UpstreamError,
+}
+
+impl Fault {
+    fn code(&self) -> u32 {
+        match self {
+            Fault::Generic => 0,
+            Fault::SourceNotFound => 0x10,
+            Fault::NoLinkToTarget => 0x11,
+            Fault::TargetNotFound => 0x20,
+            Fault::TargetInvalid => 0x21,
+            Fault::AlreadyRegistered => 0x30,
+            Fault::UpstreamError => 0x32,
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match self {
+            Fault::Generic => "Malformed request",
+            Fault::SourceNotFound => "The source URI does not exist",
+            Fault::NoLinkToTarget => "The source URI does not contain a link to the target URI",
+            Fault::TargetNotFound => "The target URI does not exist",
+            Fault::TargetInvalid => "The target URI cannot be used as a target",
+            Fault::AlreadyRegistered => "The pingback has already been registered",
+            Fault::UpstreamError => "The source URI could not be retrieved",
+        }
+    }
+
+    pub fn to_xml(&self) -> String {
+        format!(
+            concat!(
+                r#"<?xml version="1.0"?><methodResponse><fault><value><struct>"#,
+                "<member><name>faultCode</name><value><int>{}</int></value></member>",
+                "<member><name>faultString</name><value><string>{}</string></value></member>",
+                "</struct></value></fault></methodResponse>"
+            ),
+            self.code(),
+            escape_xml(self.message())
+        )
+    }
+}
+
+pub fn success_xml() -> String {
+    concat!(
+        r#"<?xml version="1.0"?><methodResponse><params><param>"#,
+        "<value><string>Pingback registered</string></value>",
+        "</param></params></methodResponse>"
+    )
+    .to_string()
+}
+
+/// Extracts source and target URI from a `pingback.ping` XML-RPC call
+pub fn parse_ping(body: &str) -> Result<(String, String), Fault> {
+    let mut reader = Reader::from_str(body);
+    reader.trim_text(true);
+
+    let mut stack = Vec::new();
+    let mut method = None;
+    let mut params = Vec::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(tag)) => stack.push(tag.name().as_ref().to_vec()),
+            Ok(Event::End(_)) => {
+                stack.pop();
+            }
+            Ok(Event::Text(text)) => {
+                let text = text.unescape().map_err(|_| Fault::Generic)?.into_owned();
+                match stack.last().map(Vec::as_slice) {
+                    Some(b"methodName") => method = Some(text),
+                    // Values without a type are strings in XML-RPC
+                    Some(b"string") | Some(b"value") => params.push(text),
+                    _ => (),
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => return Err(Fault::Generic),
+            _ => (),
+        }
+    }
+
+    match (method.as_deref(), params.as_slice()) {
+        (Some("pingback.ping"), [source, target]) => Ok((source.clone(), target.clone())),
+        _ => Err(Fault::Generic),
+    }
+}
+
+/// Returns the article URL the target URI points at, if it is one of ours
+pub fn article_url(target: &str, domain: Option<&str>) -> Result<String, Fault> {
+    let target = Url::parse(target).map_err(|_| Fault::TargetInvalid)?;
+    if let Some(domain) = domain {
+        if target.host_str() != Some(domain) {
+            return Err(Fault::TargetInvalid);
+        }
+    }
+
+    let url = target
+        .path()
+        .strip_prefix("/article/")
+        .ok_or(Fault::TargetInvalid)?;
+    Ok(percent_decode_str(url).decode_utf8_lossy().into_owned())
+}
+
+/// How many redirects are followed when fetching a source page
+const MAX_REDIRECTS: usize = 5;
+/// How much of a source page is searched for the link
+const MAX_SOURCE_SIZE: usize = 1024 * 1024;
+
+/// Resolves the host of a URL, refusing hosts that lead into a private network. Every
+/// address is checked, so a name can't smuggle a local one in next to a public one.
+async fn public_address(url: &Url) -> Result<SocketAddr, Fault> {
+    let port = url.port_or_known_default().ok_or(Fault::SourceNotFound)?;
+    let addresses: Vec<SocketAddr> = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        Some(Host::Domain(domain)) => lookup_host((domain, port))
+            .await
+            .map_err(|_| Fault::SourceNotFound)?
+            .collect(),
+        None => vec![],
+    };
+    match addresses.first() {
+        Some(address) if addresses.iter().all(|a| net::is_global(a.ip())) => Ok(*address),
+        _ => Err(Fault::SourceNotFound),
+    }
+}
+
+/// Fetches the source page and checks that it actually links to the target.
+/// The source is chosen by whoever sends the ping, so only public addresses are
+/// contacted, also after redirects, and only the start of the page is read.
+pub async fn verify_source(source: &str, target: &str) -> Result<(), Fault> {
+    let mut url = Url::parse(source).map_err(|_| Fault::SourceNotFound)?;
+    for _ in 0..=MAX_REDIRECTS {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(Fault::SourceNotFound);
+        }
+        let address = public_address(&url).await?;
+        let mut client = Client::builder()
+            .timeout(Duration::from_secs(5))
+            .redirect(Policy::none());
+        // Connect to the address that was checked, not whatever a second lookup returns
+        if let Some(Host::Domain(domain)) = url.host() {
+            client = client.resolve(domain, address);
+        }
+        let mut response = client
+            .build()
+            .map_err(|_| Fault::UpstreamError)?
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|_| Fault::UpstreamError)?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .ok_or(Fault::SourceNotFound)?;
+            url = url.join(location).map_err(|_| Fault::SourceNotFound)?;
+            continue;
+        }
+        if !response.status().is_success() {
+            return Err(Fault::SourceNotFound);
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|_| Fault::UpstreamError)? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= MAX_SOURCE_SIZE {
+                body.truncate(MAX_SOURCE_SIZE);
+                break;
+            }
+        }
+        return if String::from_utf8_lossy(&body).contains(target) {
+            Ok(())
+        } else {
+            Err(Fault::NoLinkToTarget)
+        };
+    }
+    Err(Fault::SourceNotFound)
+}
@@ -0,0 +1,178 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use askama_axum::IntoResponse;
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, Request as HttpRequest, StatusCode},
+    middleware::Next,
+    response::Response as AxumResponse,
+    Extension, Json,
+};
+
+use serde::Deserialize;
+
+use crate::{request::Response, ServerConfig};
+
+/// The address of the client that made a request, as determined by [`resolve_client_ip`]
+#[derive(Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+/// The header the reverse proxy adds the client's address to. Proxies pass the other one
+/// on as the client sent it, so it is never read.
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProxyHeader {
+    #[default]
+    XForwardedFor,
+    Forwarded,
+}
+
+/// Determine the address of the client, taking the given header of a trusted reverse
+/// proxy into account.
+///
+/// Only the rightmost entry is used: it was appended by the proxy, while everything
+/// before it is whatever the client chose to send.
+pub fn client_ip(headers: &HeaderMap, peer: SocketAddr, proxy: Option<ProxyHeader>) -> IpAddr {
+    let forwarded = match proxy {
+        Some(ProxyHeader::XForwardedFor) => x_forwarded_for(headers),
+        Some(ProxyHeader::Forwarded) => forwarded_for(headers),
+        None => None,
+    };
+    forwarded.unwrap_or(peer.ip())
+}
+
+/// The last entry of a header that may be repeated or hold a comma separated list
+fn last_entry<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .last()?
+        .to_str()
+        .ok()?
+        .rsplit(',')
+        .next()
+}
+
+/// Parses the `for=` parameter of the last element of an RFC 7239 `Forwarded` header
+fn forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
+    let last = last_entry(headers, "forwarded")?;
+    let node = last.split(';').find_map(|pair| {
+        let (key, value) = pair.trim().split_once('=')?;
+        key.eq_ignore_ascii_case("for").then_some(value)
+    })?;
+    let node = node.trim_matches('"');
+
+    if let Some(v6) = node.strip_prefix('[') {
+        return v6.split(']').next()?.parse().ok();
+    }
+    node.parse()
+        .ok()
+        .or_else(|| node.split(':').next()?.parse().ok())
+}
+
+/// Parses the rightmost entry of `X-Forwarded-For`, the address the proxy saw
+fn x_forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
+    last_entry(headers, "x-forwarded-for")?.trim().parse().ok()
+}
+
+/// Whether an address is reachable on the public internet, so the server may connect to
+/// it on behalf of others without exposing itself or its local network
+pub fn is_global(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_global_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_global_v4(v4),
+            None => is_global_v6(ip),
+        },
+    }
+}
+
+fn is_global_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "This network", carrier-grade NAT, IETF protocol assignments, benchmarking
+        // and reserved
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_global_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    // NAT64 leads to whatever IPv4 address is embedded
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let v4 = (u32::from(segments[6]) << 16) | u32::from(segments[7]);
+        return is_global_v4(Ipv4Addr::from(v4));
+    }
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, link local and documentation
+        || (segments[0] & 0xfe00) == 0xfc00
+        || (segments[0] & 0xffc0) == 0xfe80
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
+
+/// Determines the client address of every request, makes it available to
+/// handlers as an [`Extension<ClientIp>`] and logs the request.
+pub async fn resolve_client_ip(
+    State(config): State<ServerConfig>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    mut request: HttpRequest<Body>,
+    next: Next,
+) -> AxumResponse {
+    let proxy = config.trust_proxy.then_some(config.proxy_header);
+    let ip = client_ip(request.headers(), peer, proxy);
+    request.extensions_mut().insert(ClientIp(ip));
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    tracing::info!(client = %ip, %method, %path, status = response.status().as_u16());
+
+    response
+}
+
+/// Rejects requests from any network on the site-wide deny list
+pub async fn deny_list(
+    State(config): State<ServerConfig>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    request: HttpRequest<Body>,
+    next: Next,
+) -> AxumResponse {
+    if config.access.deny.iter().any(|net| net.contains(&ip)) {
+        return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Only lets requests from networks on the API allow list through.
+/// An empty allow list permits everyone.
+pub async fn api_allow_list(
+    State(config): State<ServerConfig>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    request: HttpRequest<Body>,
+    next: Next,
+) -> AxumResponse {
+    let allow = &config.access.api_allow;
+
+    if !allow.is_empty() && !allow.iter().any(|net| net.contains(&ip)) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(Response::Error(format!("{ip} may not use the API"))),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    article::Article,
+    sitemap::{absolute_url, escape_xml},
+    ServerConfig,
+};
+
+/// Width of the embed if the consumer doesn't ask for a smaller one
+const DEFAULT_WIDTH: u32 = 600;
+/// The card is a fixed height of title, byline and teaser
+const HEIGHT: u32 = 240;
+
+#[derive(Deserialize)]
+pub struct OEmbedQuery {
+    pub url: String,
+    pub format: Option<String>,
+    pub maxwidth: Option<u32>,
+}
+
+/// A `rich` oEmbed response, see <https://oembed.com/>
+#[derive(Serialize)]
+pub struct OEmbed {
+    version: &'static str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: String,
+    author_name: String,
+    provider_name: String,
+    provider_url: String,
+    html: String,
+    width: u32,
+    height: u32,
+}
+
+/// Extracts the article URL from a link to one of the blog's articles
+pub fn article_url<'a>(url: &'a str, config: &ServerConfig) -> Option<&'a str> {
+    let path = match url.split_once("://") {
+        Some((_, rest)) => {
+            let (host, path) = rest.split_once('/')?;
+            if config
+                .domain
+                .as_deref()
+                .is_some_and(|domain| domain != host)
+            {
+                return None;
+            }
+            path
+        }
+        None => url.trim_start_matches('/'),
+    };
+    let path = path.split(['?', '#']).next()?;
+    path.strip_prefix("article/").filter(|url| !url.is_empty())
+}
+
+impl OEmbed {
+    pub fn new(
+        article: &Article,
+        authors: &[String],
+        maxwidth: Option<u32>,
+        config: &ServerConfig,
+    ) -> Self {
+        let domain = config.domain.as_deref().unwrap_or_default();
+        let link = absolute_url(domain, &format!("/article/{}", article.url()));
+        let author_name = authors.join(", ");
+        let html = format!(
+            r#"<blockquote class="thoughtkeeper-embed"><p><a href="{}">{}</a></p><p>{} | {}</p><p>{}</p></blockquote>"#,
+            escape_xml(&link),
+            escape_xml(&article.title),
+            escape_xml(&author_name),
+            escape_xml(&config.blog_name),
+            escape_xml(&article.teaser()),
+        );
+
+        OEmbed {
+            version: "1.0",
+            kind: "rich",
+            title: article.title.clone(),
+            author_name,
+            provider_name: config.blog_name.clone(),
+            provider_url: absolute_url(domain, "/"),
+            html,
+            width: maxwidth.map_or(DEFAULT_WIDTH, |max| max.min(DEFAULT_WIDTH)),
+            height: HEIGHT,
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use askama::Template;
+use axum::body::Bytes;
+use chrono::NaiveDateTime;
+use miette::{miette, IntoDiagnostic};
+use resvg::{
+    tiny_skia::{Pixmap, Transform},
+    usvg::{self, fontdb},
+};
+
+/// Width of the preview image, as recommended for OpenGraph
+pub const WIDTH: u32 = 1200;
+/// Height of the preview image
+pub const HEIGHT: u32 = 630;
+/// Characters that fit on one line of the title at its font size
+const LINE_LENGTH: usize = 28;
+/// Longer titles are cut off so they don't run into the blog name
+const MAX_LINES: usize = 4;
+/// Images kept in memory at most, a few dozen kilobytes each
+const MAX_CACHED: usize = 256;
+
+/// The social preview of an article, rendered from an SVG template
+#[derive(Template)]
+#[template(path = "og.svg", escape = "html")]
+struct OgImage<'a> {
+    lines: Vec<String>,
+    blog_name: &'a str,
+}
+
+impl OgImage<'_> {
+    /// Centers the title block vertically above the blog name
+    fn title_top(&self) -> usize {
+        280 - (self.lines.len().saturating_sub(1)) * 40
+    }
+}
+
+/// Breaks a title into lines at word boundaries
+fn wrap(title: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in title.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + word.chars().count() < LINE_LENGTH => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    if lines.len() > MAX_LINES {
+        lines.truncate(MAX_LINES);
+        if let Some(last) = lines.last_mut() {
+            last.push('…');
+        }
+    }
+    lines
+}
+
+/// Renders the preview image of an article as PNG
+pub fn render(title: &str, blog_name: &str, fonts: &fontdb::Database) -> miette::Result<Vec<u8>> {
+    let svg = OgImage {
+        lines: wrap(title),
+        blog_name,
+    }
+    .render()
+    .into_diagnostic()?;
+
+    let tree = usvg::Tree::from_str(&svg, &usvg::Options::default(), fonts).into_diagnostic()?;
+    let mut pixmap =
+        Pixmap::new(WIDTH, HEIGHT).ok_or(miette!("could not allocate the preview image"))?;
+    resvg::render(&tree, Transform::default(), &mut pixmap.as_mut());
+    pixmap.encode_png().into_diagnostic()
+}
+
+/// Rendered preview images by article ID, so crawlers fetching them again don't have them
+/// rendered every time
+#[derive(Default)]
+pub struct OgCache {
+    images: RwLock<HashMap<String, (NaiveDateTime, Bytes)>>,
+}
+
+impl OgCache {
+    /// The cached image of an article, if the article wasn't changed since
+    pub fn get(&self, article: &str, last_modified: NaiveDateTime) -> Option<Bytes> {
+        let images = self.images.read().unwrap();
+        let (cached, png) = images.get(article)?;
+        (*cached == last_modified).then(|| png.clone())
+    }
+
+    pub fn insert(&self, article: String, last_modified: NaiveDateTime, png: Bytes) {
+        let mut images = self.images.write().unwrap();
+        if images.len() >= MAX_CACHED && !images.contains_key(&article) {
+            // Any image will do, it is rendered again when it is asked for
+            if let Some(evicted) = images.keys().next().cloned() {
+                images.remove(&evicted);
+            }
+        }
+        images.insert(article, (last_modified, png));
+    }
+}
@@ -0,0 +1,218 @@
+use miette::{miette, IntoDiagnostic};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use wasmtime::{Engine, Instance, Module, Store};
+
+use crate::{article::Article, comment::Comment, jobs::Job, PluginConfig};
+
+/// A compiled WebAssembly module implementing some of the hooks
+struct Plugin {
+    name: String,
+    module: Module,
+}
+
+/// The configured plugins. They are compiled once and get a fresh instance for every
+/// hook call, so they can't keep state between calls.
+#[derive(Default)]
+pub struct Plugins {
+    engine: Engine,
+    plugins: Vec<Plugin>,
+    /// Instructions a single hook call may execute
+    fuel: u64,
+}
+
+#[derive(Serialize)]
+struct PublishEvent<'a> {
+    id: &'a str,
+    title: &'a str,
+    url: String,
+    content: &'a str,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct PublishResult {
+    /// Additional notifications to send, through the job queue like the configured webhooks
+    webhooks: Vec<WebhookCall>,
+}
+
+#[derive(Deserialize)]
+struct WebhookCall {
+    url: String,
+    payload: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct RenderEvent<'a> {
+    id: &'a str,
+    title: &'a str,
+    html: &'a str,
+}
+
+#[derive(Deserialize)]
+struct RenderResult {
+    html: String,
+}
+
+#[derive(Serialize)]
+struct CommentEvent<'a> {
+    article: &'a str,
+    author: &'a str,
+    content: &'a str,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct CommentResult {
+    /// Replaces the content of the comment
+    content: Option<String>,
+    /// Rejects the comment with the given reason
+    reject: Option<String>,
+}
+
+impl Plugins {
+    pub fn load(config: &PluginConfig) -> miette::Result<Self> {
+        let mut wasm = wasmtime::Config::new();
+        wasm.consume_fuel(true);
+        let engine = Engine::new(&wasm).map_err(|e| miette!("{e:#}"))?;
+
+        let plugins = config
+            .modules
+            .iter()
+            .map(|path| {
+                let module = Module::from_file(&engine, path)
+                    .map_err(|e| miette!("could not load plugin {}: {e:#}", path.display()))?;
+                tracing::info!("loaded plugin {}", path.display());
+                Ok(Plugin {
+                    name: path
+                        .file_stem()
+                        .map(|s| s.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    module,
+                })
+            })
+            .collect::<miette::Result<_>>()?;
+
+        Ok(Self {
+            engine,
+            plugins,
+            fuel: config.fuel,
+        })
+    }
+
+    /// Runs a hook of one plugin. Plugins that don't export the hook or return 0 have
+    /// nothing to say.
+    fn call<O: DeserializeOwned>(
+        &self,
+        plugin: &Plugin,
+        hook: &str,
+        input: &impl Serialize,
+    ) -> miette::Result<Option<O>> {
+        if plugin.module.get_export(hook).is_none() {
+            return Ok(None);
+        }
+        let input = serde_json::to_vec(input).into_diagnostic()?;
+        let output = self
+            .run(plugin, hook, &input)
+            .map_err(|e| miette!("plugin {} failed in {hook}: {e:#}", plugin.name))?;
+        output
+            .map(|output| serde_json::from_slice(&output))
+            .transpose()
+            .map_err(|e| {
+                miette!(
+                    "plugin {} returned invalid JSON from {hook}: {e}",
+                    plugin.name
+                )
+            })
+    }
+
+    /// Passes the input to the hook through the plugin's memory, see the README for the ABI
+    fn run(&self, plugin: &Plugin, hook: &str, input: &[u8]) -> wasmtime::Result<Option<Vec<u8>>> {
+        let mut store = Store::new(&self.engine, ());
+        store.set_fuel(self.fuel)?;
+        let instance = Instance::new(&mut store, &plugin.module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or(wasmtime::Error::msg("the plugin exports no memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let hook = instance.get_typed_func::<(i32, i32), i64>(&mut store, hook)?;
+
+        let len = i32::try_from(input.len())?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, ptr as u32 as usize, input)?;
+
+        let result = hook.call(&mut store, (ptr, len))?;
+        if result == 0 {
+            return Ok(None);
+        }
+        let (ptr, len) = ((result >> 32) as u32 as usize, result as u32 as usize);
+        let mut output = vec![0; len];
+        memory.read(&store, ptr, &mut output)?;
+        Ok(Some(output))
+    }
+
+    /// Asks the plugins what to send out when an article is published
+    pub fn on_publish(&self, article: &Article) -> Vec<Job> {
+        let event = PublishEvent {
+            id: &article.id,
+            title: &article.title,
+            url: format!("/article/{}", article.url()),
+            content: &article.content,
+        };
+        let mut jobs = Vec::new();
+        for plugin in &self.plugins {
+            match self.call::<PublishResult>(plugin, "on_publish", &event) {
+                Ok(result) => {
+                    jobs.extend(result.unwrap_or_default().webhooks.into_iter().map(|call| {
+                        Job::Webhook {
+                            url: call.url,
+                            payload: call.payload,
+                        }
+                    }))
+                }
+                Err(e) => tracing::warn!("{e}"),
+            }
+        }
+        jobs
+    }
+
+    /// Lets the plugins rewrite the HTML of an article, one after another
+    pub fn on_render(&self, article: &Article, mut html: String) -> String {
+        for plugin in &self.plugins {
+            let event = RenderEvent {
+                id: &article.id,
+                title: &article.title,
+                html: &html,
+            };
+            match self.call::<RenderResult>(plugin, "on_render", &event) {
+                Ok(Some(result)) => html = result.html,
+                Ok(None) => (),
+                Err(e) => tracing::warn!("{e}"),
+            }
+        }
+        html
+    }
+
+    /// Lets the plugins edit or reject a comment before it is stored
+    pub fn on_comment(&self, comment: &mut Comment) -> Result<(), String> {
+        for plugin in &self.plugins {
+            let event = CommentEvent {
+                article: &comment.article,
+                author: &comment.author,
+                content: &comment.content,
+            };
+            match self.call::<CommentResult>(plugin, "on_comment", &event) {
+                Ok(Some(result)) => {
+                    if let Some(reason) = result.reject {
+                        return Err(reason);
+                    }
+                    if let Some(content) = result.content {
+                        comment.content = content;
+                    }
+                }
+                Ok(None) => (),
+                Err(e) => tracing::warn!("{e}"),
+            }
+        }
+        Ok(())
+    }
+}
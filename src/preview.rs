@@ -0,0 +1,39 @@
+use askama::Template;
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use crate::{article::Article, auth, ServerConfig};
+
+#[derive(Template)]
+#[template(path = "preview.html")]
+pub struct PreviewPage {
+    pub config: ServerConfig,
+    pub article: Article,
+    /// The rendered content
+    pub content: String,
+    /// When the link stops working
+    pub expires: String,
+}
+
+/// A token granting access to an article until the given Unix timestamp, signed with
+/// the session key so it can't be forged or extended
+pub fn token(id: &str, expires: i64, key: &str) -> String {
+    let signature = auth::sign(key, expires, format!("preview:{id}").as_bytes());
+    format!("{id}.{expires}.{signature}")
+}
+
+/// The ID of the article a token grants access to and when it expires, if the token is
+/// genuine and still valid
+pub fn verify(token: &str, key: &str) -> Option<(String, NaiveDateTime)> {
+    let (id, rest) = token.split_once('.')?;
+    let (expires, signature) = rest.split_once('.')?;
+    let expires = expires.parse().ok()?;
+    if expires < Utc::now().timestamp()
+        || !auth::verify(key, expires, format!("preview:{id}").as_bytes(), signature)
+    {
+        return None;
+    }
+    Some((
+        id.to_string(),
+        DateTime::from_timestamp(expires, 0)?.naive_utc(),
+    ))
+}
@@ -1,47 +1,265 @@
+use std::collections::HashMap;
+
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
-use crate::article::Article;
+use crate::{
+    appearance::Stylesheet, article::Article, datetime, html::HtmlPolicy, media::Media,
+    search::SearchResult, validation::ValidationError,
+};
 
 #[derive(Serialize, Deserialize)]
 pub struct Request {
-    pub secret: String,
+    /// The bearer secret. Omitted when the request is signed with HMAC instead.
+    #[serde(default)]
+    pub secret: Option<String>,
     pub request: InnerRequest,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum InnerRequest {
     CreateArticle {
         title: String,
         content: String,
+        /// Retrying a request with the same key returns the article created the first time
+        #[serde(default)]
+        idempotency_key: Option<String>,
+        /// Append a number to the URL if another article already has it, instead of failing
+        #[serde(default)]
+        force_slug: bool,
+        #[serde(flatten)]
+        fields: ArticleFields,
     },
+    /// Creates all articles in a single transaction, answered with one result per article
+    CreateArticles(Vec<NewArticle>),
     GetArticle {
         url: String,
     },
+    /// Like `GetArticle`, but also finds drafts and submissions
+    GetArticleById {
+        id: String,
+    },
     YankArticle {
         id: String,
     },
+    /// Creates a link anyone can read the article at for the given number of days,
+    /// even while it is a draft. Answered with the URL.
+    PreviewArticle {
+        id: String,
+        days: i64,
+    },
     UpdateArticle {
         id: String,
         title: Option<String>,
         content: Option<String>,
+        /// Announces the update in the changes feed if the article is published
+        #[serde(default)]
+        change_note: Option<String>,
+        #[serde(flatten)]
+        fields: ArticleFields,
+    },
+    /// Copies the title and content of an article into a new one, answered with its ID
+    DuplicateArticle {
+        id: String,
+        /// Keep the copy as a draft instead of publishing it
+        #[serde(default)]
+        draft: bool,
+    },
+    /// Moves an article to a new URL, redirecting the old one. Answered with the new URL.
+    SetSlug {
+        id: String,
+        slug: String,
+    },
+    ListArticles(ArticleFilter),
+    /// Lists the articles published or edited after the given time
+    ListArticlesSince {
+        #[serde(with = "datetime::rfc3339")]
+        timestamp: NaiveDateTime,
+    },
+    SetMaintenance {
+        enabled: bool,
+    },
+    /// Stores a file that articles can link to, answered with its URL
+    UploadMedia {
+        filename: String,
+        content_type: String,
+        /// The file's contents, base64 encoded
+        data: String,
+    },
+    ListMedia,
+    DeleteMedia {
+        id: String,
+    },
+    /// Renders markdown exactly like the blog renders articles, answered with the HTML
+    /// and the stylesheets of the blog's theme
+    RenderPreview {
+        content: String,
+    },
+    /// Searches the titles and content of all articles, including drafts
+    SearchArticles {
+        query: String,
     },
-    ListArticles,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NewArticle {
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+    #[serde(default)]
+    pub force_slug: bool,
+    #[serde(flatten)]
+    pub fields: ArticleFields,
+}
+
+/// Optional properties of an article. When updating an article, only the given ones
+/// are changed, and an empty string removes a property.
+#[derive(Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ArticleFields {
+    /// Replaces all tags of the article
+    pub tags: Option<Vec<String>>,
+    /// Replaces the authors of the article, in byline order
+    pub authors: Option<Vec<String>>,
+    /// Replaces the old URLs or paths that redirect to the article
+    pub aliases: Option<Vec<String>>,
+    pub canonical_url: Option<String>,
+    /// Language code of the article, e.g. `en` or `de`
+    pub lang: Option<String>,
+    /// ID of the article this one is a translation of
+    pub translation_of: Option<String>,
+    /// Collapses the article behind a click-through showing this warning
+    pub content_warning: Option<String>,
+    /// Overrides the blog's default license, e.g. `CC-BY-4.0`
+    pub license: Option<String>,
+    /// Keeps the article out of search engines and the sitemap
+    pub noindex: Option<bool>,
+    /// Closes the discussion when `false`, existing comments stay visible
+    pub comments_enabled: Option<bool>,
+    /// Shown in article lists instead of an excerpt
+    pub summary: Option<String>,
+    /// Unpublishes the article at this time, e.g. `2024-06-01T00:00:00`
+    #[serde(with = "datetime::rfc3339::option")]
+    pub expires_at: Option<NaiveDateTime>,
+    /// Hides the article until it is published by setting this to `false`
+    pub draft: Option<bool>,
+    /// Overrides the blog's policy for HTML in the article: `escape`, `sanitize` or
+    /// `allow`. Ignored for guest submissions, and can't be removed once set.
+    pub html: Option<HtmlPolicy>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ArticleMetadata {
     pub id: String,
     pub title: String,
+    #[serde(with = "datetime::rfc3339")]
     pub published: NaiveDateTime,
+    #[serde(default, with = "datetime::rfc3339::option")]
+    pub updated: Option<NaiveDateTime>,
+    /// The ID of the secret the article was created with
+    #[serde(default)]
+    pub created_by: Option<i64>,
+}
+
+/// Narrows down and orders the articles listed by [`InnerRequest::ListArticles`]
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArticleFilter {
+    /// Only articles published or edited after this time
+    #[serde(with = "datetime::rfc3339::option")]
+    pub since: Option<NaiveDateTime>,
+    /// Only articles published before this time
+    #[serde(with = "datetime::rfc3339::option")]
+    pub until: Option<NaiveDateTime>,
+    /// Only articles whose title contains this, ignoring case
+    pub title_contains: Option<String>,
+    pub tag: Option<String>,
+    /// Only articles created with the secret with this ID
+    pub created_by: Option<i64>,
+    pub sort: ArticleSort,
+}
+
+impl ArticleFilter {
+    /// Whether an article passes the filter. Tags are not known outside the server and
+    /// are not checked here.
+    pub fn matches(&self, article: &ArticleMetadata) -> bool {
+        let last_modified = article.updated.unwrap_or(article.published);
+        self.since.map_or(true, |since| last_modified > since)
+            && self.until.map_or(true, |until| article.published < until)
+            && self.title_contains.as_ref().map_or(true, |part| {
+                article.title.to_lowercase().contains(&part.to_lowercase())
+            })
+            && self
+                .created_by
+                .map_or(true, |id| article.created_by == Some(id))
+    }
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ArticleSort {
+    /// Newest first
+    #[default]
+    Published,
+    /// Alphabetically
+    Title,
+    /// Most recently edited first
+    Updated,
+}
+
+impl ArticleSort {
+    pub fn apply(self, articles: &mut [ArticleMetadata]) {
+        match self {
+            ArticleSort::Published => articles.sort_by(|a, b| b.published.cmp(&a.published)),
+            ArticleSort::Title => articles.sort_by_key(|a| a.title.to_lowercase()),
+            ArticleSort::Updated => articles.sort_by(|a, b| {
+                b.updated
+                    .unwrap_or(b.published)
+                    .cmp(&a.updated.unwrap_or(a.published))
+            }),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub enum Response {
     Article(Article),
     ArticleId(String),
+    ArticleUrl(String),
+    /// The title of a new article maps to the URL of an existing one
+    SlugTaken {
+        url: String,
+        existing: String,
+    },
     ArticleMetadata(Vec<ArticleMetadata>),
-    Untyped { kind: String, content: String },
+    /// The article did not pass the server's validation
+    Invalid(Vec<ValidationError>),
+    Untyped {
+        kind: String,
+        content: String,
+    },
+    /// Articles changed since a given time, along with the IDs of all existing
+    /// articles so clients can drop the ones that were yanked
+    Changes {
+        changed: Vec<Article>,
+        existing: Vec<String>,
+        /// Tags and explicitly set authors of the changed articles, by article ID
+        #[serde(default)]
+        tags: HashMap<String, Vec<String>>,
+        #[serde(default)]
+        authors: HashMap<String, Vec<String>>,
+    },
+    Media(Vec<Media>),
+    MediaUrl(String),
+    SearchResults(Vec<SearchResult>),
+    Preview {
+        html: String,
+        /// In the order the blog's pages link them, with URLs relative to the blog
+        stylesheets: Vec<Stylesheet>,
+    },
+    /// Results of a batch request, in the order of its items
+    Results(Vec<Response>),
     Ok,
     Error(String),
 }
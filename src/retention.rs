@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use miette::IntoDiagnostic;
+use sqlx::{Pool, Sqlite, SqliteConnection};
+
+use crate::RetentionConfig;
+
+/// Days a comment subscription is kept without being confirmed
+const PENDING_SUBSCRIPTION_DAYS: i64 = 7;
+
+/// Deletes page views and finished jobs older than the configured retention periods, and
+/// comment subscriptions that were never confirmed. Returns how many rows were deleted.
+pub async fn prune(config: &RetentionConfig, conn: &mut SqliteConnection) -> miette::Result<u64> {
+    let now = Utc::now().naive_utc();
+    let mut deleted = 0;
+
+    if config.views_days > 0 {
+        let cutoff = now - chrono::Duration::days(config.views_days);
+        deleted += sqlx::query!("DELETE FROM views WHERE viewed < ?", cutoff)
+            .execute(&mut *conn)
+            .await
+            .into_diagnostic()?
+            .rows_affected();
+    }
+    if config.jobs_days > 0 {
+        let cutoff = now - chrono::Duration::days(config.jobs_days);
+        deleted += sqlx::query!(
+            "DELETE FROM jobs WHERE status IN ('done', 'failed') AND created < ?",
+            cutoff
+        )
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?
+        .rows_affected();
+    }
+    let cutoff = now - chrono::Duration::days(PENDING_SUBSCRIPTION_DAYS);
+    deleted += sqlx::query!(
+        "DELETE FROM comment_subscriptions WHERE NOT confirmed AND created < ?",
+        cutoff
+    )
+    .execute(&mut *conn)
+    .await
+    .into_diagnostic()?
+    .rows_affected();
+
+    Ok(deleted)
+}
+
+/// Prunes old rows in the configured interval until the server shuts down
+pub async fn run(pool: Pool<Sqlite>, config: RetentionConfig) {
+    loop {
+        let result = match pool.acquire().await {
+            Ok(mut conn) => prune(&config, &mut conn).await,
+            Err(e) => Err(e).into_diagnostic(),
+        };
+        match result {
+            Ok(0) => (),
+            Ok(deleted) => tracing::info!(deleted, "pruned old statistics and jobs"),
+            Err(e) => tracing::error!("pruning failed: {e}"),
+        }
+        tokio::time::sleep(Duration::from_secs(config.interval)).await;
+    }
+}
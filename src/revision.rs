@@ -0,0 +1,148 @@
+use askama::Template;
+use chrono::{NaiveDateTime, Utc};
+use miette::IntoDiagnostic;
+use similar::{ChangeTag, TextDiff};
+use sqlx::SqliteConnection;
+
+use crate::{article::Article, datetime, ServerConfig};
+
+/// An edit summarized by its author, shown under the article if enabled
+pub struct EditNote {
+    pub summary: String,
+    pub created: NaiveDateTime,
+}
+
+impl EditNote {
+    pub fn date(&self) -> String {
+        datetime::display(self.created)
+    }
+}
+
+/// Stores the article's current title and content as a new revision
+pub async fn record(
+    article: &Article,
+    summary: Option<&str>,
+    conn: &mut SqliteConnection,
+) -> miette::Result<()> {
+    let now = Utc::now().naive_utc();
+    sqlx::query!(
+        "INSERT INTO article_revisions ( article, title, content, summary, created ) VALUES (?1, ?2, ?3, ?4, ?5)",
+        article.id,
+        article.title,
+        article.content,
+        summary,
+        now
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?;
+    Ok(())
+}
+
+/// The summarized edits of an article, newest first
+pub async fn edit_notes(
+    article: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Vec<EditNote>> {
+    sqlx::query_as!(
+        EditNote,
+        r#"SELECT summary AS "summary!: String", created FROM article_revisions WHERE article = ? AND summary IS NOT NULL ORDER BY created DESC"#,
+        article
+    )
+    .fetch_all(conn)
+    .await
+    .into_diagnostic()
+}
+
+struct Revision {
+    title: String,
+    content: String,
+    summary: Option<String>,
+    created: NaiveDateTime,
+}
+
+/// A run of words that was kept, added or removed
+pub struct DiffPart {
+    /// `same`, `added` or `removed`
+    pub kind: &'static str,
+    pub text: String,
+}
+
+/// The words changed between two texts
+fn diff(old: &str, new: &str) -> Vec<DiffPart> {
+    let mut parts: Vec<DiffPart> = Vec::new();
+    for change in TextDiff::from_words(old, new).iter_all_changes() {
+        let kind = match change.tag() {
+            ChangeTag::Equal => "same",
+            ChangeTag::Insert => "added",
+            ChangeTag::Delete => "removed",
+        };
+        match parts.last_mut() {
+            Some(last) if last.kind == kind => last.text.push_str(change.value()),
+            _ => parts.push(DiffPart {
+                kind,
+                text: change.value().to_string(),
+            }),
+        }
+    }
+    parts
+}
+
+/// A revision and what changed compared to the one before
+pub struct RevisionDiff {
+    pub summary: Option<String>,
+    pub created: NaiveDateTime,
+    /// Empty if the title stayed the same
+    pub title: Vec<DiffPart>,
+    pub content: Vec<DiffPart>,
+}
+
+impl RevisionDiff {
+    pub fn date(&self) -> String {
+        datetime::display(self.created)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "history.html")]
+pub struct HistoryPage {
+    pub config: ServerConfig,
+    pub article: Article,
+    /// Newest first. The oldest revision is compared to nothing, so it is all added.
+    pub revisions: Vec<RevisionDiff>,
+    /// Shows the admin navigation when the page is part of the admin panel
+    pub csrf: Option<String>,
+}
+
+/// The revisions of an article, newest first, with the changes each one made
+pub async fn history(
+    article: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Vec<RevisionDiff>> {
+    let revisions = sqlx::query_as!(
+        Revision,
+        "SELECT title, content, summary, created FROM article_revisions WHERE article = ? ORDER BY id",
+        article
+    )
+    .fetch_all(conn)
+    .await
+    .into_diagnostic()?;
+
+    let mut diffs = Vec::with_capacity(revisions.len());
+    let (mut title, mut content) = (String::new(), String::new());
+    for revision in revisions {
+        diffs.push(RevisionDiff {
+            summary: revision.summary,
+            created: revision.created,
+            title: if revision.title == title {
+                Vec::new()
+            } else {
+                diff(&title, &revision.title)
+            },
+            content: diff(&content, &revision.content),
+        });
+        (title, content) = (revision.title, revision.content);
+    }
+    diffs.reverse();
+    Ok(diffs)
+}
@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use miette::IntoDiagnostic;
+use sqlx::{Pool, Sqlite, SqliteConnection};
+
+use crate::db;
+
+/// How often the scheduler looks for work
+const INTERVAL: Duration = Duration::from_secs(60);
+
+/// Unpublishes the articles whose expiry date has passed, returning how many there were
+pub async fn expire_articles(conn: &mut SqliteConnection) -> miette::Result<u64> {
+    let now = Utc::now().naive_utc();
+    Ok(sqlx::query!(
+        "UPDATE articles SET status = 'expired' WHERE status = 'published' AND expires_at <= ?",
+        now
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?
+    .rows_affected())
+}
+
+/// Runs time-based changes to articles, and database maintenance every
+/// `maintain_interval` seconds if it isn't 0, until the server shuts down
+pub async fn run(pool: Pool<Sqlite>, maintain_interval: u64) {
+    let mut last_maintenance = Instant::now();
+    loop {
+        let result = match pool.acquire().await {
+            Ok(mut conn) => expire_articles(&mut conn).await,
+            Err(e) => Err(e).into_diagnostic(),
+        };
+        match result {
+            Ok(0) => (),
+            Ok(expired) => tracing::info!(expired, "unpublished expired articles"),
+            Err(e) => tracing::error!("scheduler failed: {e}"),
+        }
+
+        if maintain_interval > 0
+            && last_maintenance.elapsed() >= Duration::from_secs(maintain_interval)
+        {
+            last_maintenance = Instant::now();
+            match pool.acquire().await {
+                Ok(mut conn) => db::maintain_logged(&mut conn).await,
+                Err(e) => tracing::error!("scheduler failed: {e}"),
+            }
+        }
+        tokio::time::sleep(INTERVAL).await;
+    }
+}
@@ -0,0 +1,75 @@
+use itertools::Itertools;
+use miette::IntoDiagnostic;
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use crate::{article::url_of, sitemap::escape_xml};
+
+/// Results returned for a single query
+const LIMIT: i64 = 10;
+/// Longer queries are cut off
+const MAX_QUERY_LENGTH: usize = 200;
+/// Mark matches in snippets with control characters, so they survive escaping
+const MATCH_START: &str = "\u{2}";
+const MATCH_END: &str = "\u{3}";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    /// Escaped HTML excerpt with the matching terms in `<mark>`
+    pub snippet: String,
+}
+
+/// Turns user input into an FTS query matching every word as a prefix,
+/// so results show up while the last word is still being typed
+fn fts_query(query: &str) -> Option<String> {
+    let query: String = query.chars().take(MAX_QUERY_LENGTH).collect();
+    let words = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .join(" ");
+    (!words.is_empty()).then_some(words)
+}
+
+/// Finds published articles matching the query, or all articles with `everything`, for
+/// their authors
+pub async fn search(
+    query: &str,
+    everything: bool,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Vec<SearchResult>> {
+    let Some(query) = fts_query(query) else {
+        return Ok(Vec::new());
+    };
+
+    let rows = sqlx::query!(
+        r#"SELECT articles.id, articles.title AS "title!: String", articles.slug,
+        snippet(articles_fts, 1, ?2, ?3, '…', 16) AS "snippet!: String"
+        FROM articles_fts JOIN articles ON articles.rowid = articles_fts.rowid
+        WHERE articles_fts MATCH ?1
+        AND (?5 OR (articles.status = 'published' AND NOT articles.noindex))
+        ORDER BY rank LIMIT ?4"#,
+        query,
+        MATCH_START,
+        MATCH_END,
+        LIMIT,
+        everything
+    )
+    .fetch_all(conn)
+    .await
+    .into_diagnostic()?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| SearchResult {
+            id: row.id,
+            url: url_of(&row.title, row.slug.as_deref()),
+            snippet: escape_xml(&row.snippet)
+                .replace(MATCH_START, "<mark>")
+                .replace(MATCH_END, "</mark>"),
+            title: row.title,
+        })
+        .collect())
+}
@@ -0,0 +1,120 @@
+use chrono::{NaiveDateTime, Utc};
+use miette::IntoDiagnostic;
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    thread_rng,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use crate::{auth::Scope, datetime};
+
+/// Everything about a secret except the secret itself
+#[derive(Serialize, Deserialize)]
+pub struct SecretMetadata {
+    pub id: i64,
+    pub description: Option<String>,
+    pub auth_mode: String,
+    pub scope: String,
+    pub last_used_at: Option<NaiveDateTime>,
+    /// The secret is rejected from this time on
+    #[serde(default)]
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+impl SecretMetadata {
+    pub fn last_used(&self) -> String {
+        self.last_used_at
+            .map(datetime::display)
+            .unwrap_or("never".to_string())
+    }
+
+    /// The expiry date in the format of a date input, empty if the secret never expires
+    pub fn expires(&self) -> String {
+        self.expires_at
+            .map(|t| t.format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|t| t <= Utc::now().naive_utc())
+    }
+}
+
+/// Generates and stores a new secret, returning its ID and value
+pub async fn create(
+    description: Option<String>,
+    hmac: bool,
+    scope: Scope,
+    expires_at: Option<NaiveDateTime>,
+    conn: &mut SqliteConnection,
+) -> miette::Result<(i64, String)> {
+    let secret = Alphanumeric.sample_string(&mut thread_rng(), 64);
+    let auth_mode = if hmac { "hmac" } else { "bearer" };
+    let scope = scope.as_str();
+
+    let id = sqlx::query!(
+        "INSERT INTO secrets (secret, description, auth_mode, scope, expires_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        secret,
+        description,
+        auth_mode,
+        scope,
+        expires_at
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?
+    .last_insert_rowid();
+
+    Ok((id, secret))
+}
+
+pub async fn list(conn: &mut SqliteConnection) -> miette::Result<Vec<SecretMetadata>> {
+    sqlx::query_as!(
+        SecretMetadata,
+        "SELECT id, description, auth_mode, scope, last_used_at, expires_at FROM secrets ORDER BY id"
+    )
+    .fetch_all(conn)
+    .await
+    .into_diagnostic()
+}
+
+/// Changes what a secret may do and how long it is valid. Returns whether it exists.
+pub async fn update(
+    id: i64,
+    scope: Scope,
+    expires_at: Option<NaiveDateTime>,
+    conn: &mut SqliteConnection,
+) -> miette::Result<bool> {
+    let scope = scope.as_str();
+    let updated = sqlx::query!(
+        "UPDATE secrets SET scope = ?, expires_at = ? WHERE id = ?",
+        scope,
+        expires_at,
+        id
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?
+    .rows_affected();
+    Ok(updated > 0)
+}
+
+/// Deletes a secret. Returns whether it existed.
+pub async fn revoke(id: i64, conn: &mut SqliteConnection) -> miette::Result<bool> {
+    let deleted = sqlx::query!("DELETE FROM secrets WHERE id = ?", id)
+        .execute(conn)
+        .await
+        .into_diagnostic()?
+        .rows_affected();
+    Ok(deleted > 0)
+}
+
+/// Deletes every secret. Returns how many there were.
+pub async fn revoke_all(conn: &mut SqliteConnection) -> miette::Result<u64> {
+    Ok(sqlx::query!("DELETE FROM secrets")
+        .execute(conn)
+        .await
+        .into_diagnostic()?
+        .rows_affected())
+}
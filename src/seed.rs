@@ -0,0 +1,130 @@
+use chrono::{Duration, Utc};
+use miette::IntoDiagnostic;
+use uuid::Uuid;
+
+use crate::{
+    article::Article,
+    comment::Comment,
+    server::{connect, insert_article},
+    tag,
+};
+
+/// Sample articles as title, tags and content
+const ARTICLES: &[(&str, &[&str], &str)] = &[
+    (
+        "Lorem Ipsum Dolor",
+        &["demo", "writing"],
+        r#"Lorem ipsum dolor sit amet, consectetur adipiscing elit. Sed do eiusmod tempor
+incididunt ut labore et dolore magna aliqua[^1]. Ut enim ad minim veniam, quis nostrud
+exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat.
+
+## Duis aute irure
+
+Duis aute irure dolor in *reprehenderit* in voluptate velit esse cillum dolore eu fugiat
+nulla pariatur. Excepteur sint occaecat cupidatat non proident, sunt in culpa qui officia
+deserunt mollit anim id est **laborum**.
+
+> Sed ut perspiciatis unde omnis iste natus error sit voluptatem accusantium doloremque.
+
+[^1]: Nemo enim ipsam voluptatem quia voluptas sit aspernatur aut odit aut fugit.
+"#,
+    ),
+    (
+        "Code Samples",
+        &["demo", "rust"],
+        r#"Neque porro quisquam est, qui dolorem ipsum quia dolor sit amet. Here is some `inline code`
+followed by a block:
+
+```rust
+fn main() {
+    let greeting = "Hello, world!";
+    println!("{greeting}");
+}
+```
+
+And a shell session:
+
+```
+$ thoughtkeeper publish post.md
+```
+
+Quis autem vel eum iure reprehenderit qui in ea voluptate velit esse quam nihil molestiae.
+"#,
+    ),
+    (
+        "Tables and Lists",
+        &["demo"],
+        r#"At vero eos et accusamus et iusto odio dignissimos ducimus qui blanditiis praesentium.
+
+| Name    | Kind   | Score |
+|---------|--------|------:|
+| Lorem   | ipsum  |    42 |
+| Dolor   | sit    |     7 |
+| Amet    | tempor |   100 |
+
+- Temporibus autem quibusdam
+- Et aut officiis debitis
+  1. Aut rerum necessitatibus
+  2. Saepe eveniet ut et voluptates
+
+~~Itaque earum rerum~~ hic tenetur a sapiente delectus[^note].
+
+[^note]: Ut aut reiciendis voluptatibus maiores alias consequatur aut perferendis doloribus.
+"#,
+    ),
+];
+
+/// Sample comments as author and content, added to every article
+const COMMENTS: &[(&str, &str)] = &[
+    (
+        "Marcus",
+        "Nam libero tempore, cum soluta nobis est eligendi optio.",
+    ),
+    (
+        "Tullius",
+        "Omnis voluptas assumenda est, omnis dolor repellendus.",
+    ),
+];
+
+/// Fills the database with sample articles, tags and comments for theme development and demos
+pub async fn seed() -> miette::Result<()> {
+    let mut conn = connect().await?;
+
+    for (age, (title, tags, content)) in ARTICLES.iter().enumerate() {
+        let mut article = Article::new(title.to_string(), content.to_string());
+        article.published = (Utc::now() - Duration::days(age as i64 * 3)).naive_utc();
+        insert_article(&article, &mut conn).await?;
+        let tags = tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        tag::set_tags(&article.id, &tags, &mut conn).await?;
+
+        for (minutes, (author, content)) in COMMENTS.iter().enumerate() {
+            let comment = Comment {
+                id: Uuid::new_v4().to_string(),
+                article: article.id.clone(),
+                author: author.to_string(),
+                content: content.to_string(),
+                published: article.published + Duration::minutes(minutes as i64 * 90 + 30),
+                ip_hash: None,
+                hidden: false,
+            };
+            sqlx::query!(
+                "INSERT INTO comments ( id, article, author, content, published ) VALUES (?1, ?2, ?3, ?4, ?5)",
+                comment.id,
+                comment.article,
+                comment.author,
+                comment.content,
+                comment.published
+            )
+            .execute(&mut conn)
+            .await
+            .into_diagnostic()?;
+        }
+    }
+
+    println!(
+        "Added {} articles with {} comments each",
+        ARTICLES.len(),
+        COMMENTS.len()
+    );
+    Ok(())
+}
@@ -1,376 +1,5465 @@
 use askama::Template;
 use askama_axum::IntoResponse;
 use axum::{
-    extract::{Path, State},
-    http::header,
+    body::{to_bytes, Body, Bytes},
+    extract::{rejection::BytesRejection, DefaultBodyLimit, Path, Query, State},
+    http::{header, HeaderMap, Request as HttpRequest, StatusCode, Uri},
+    middleware::{self, Next},
     response::{Redirect, Response as AxumResponse},
     routing::{get, get_service, post},
-    Form, Json, Router,
+    Extension, Form, Json, Router,
 };
 
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
 use comrak::Options;
+use futures::StreamExt;
 use itertools::Itertools;
 use miette::IntoDiagnostic;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 
-use rss::ChannelBuilder;
+use resvg::usvg::fontdb;
+use rss::{ChannelBuilder, ImageBuilder};
+use rustls_acme::caches::DirCache;
+use serde::Deserialize;
+use serde_json::json;
 use sqlx::{
-    pool::PoolConnection, sqlite::SqliteConnectOptions, ConnectOptions, Pool, Sqlite,
-    SqliteConnection, SqlitePool,
+    pool::PoolConnection,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
+    ConnectOptions, Connection, Pool, Sqlite, SqliteConnection,
 };
 use tokio::net::TcpListener;
-use tower_http::services::{ServeDir, ServeFile};
+use tower_http::{
+    limit::RequestBodyLimitLayer,
+    services::{ServeDir, ServeFile},
+    timeout::TimeoutLayer,
+};
 
 use crate::{
-    article::{to_url, Article, ArticleTemplate},
-    comment::{Comment, CommentRequest},
-    error::TkError,
-    request::{ArticleMetadata, InnerRequest, Request, Response},
-    ServerConfig,
+    accesslog::{self, AccessLog},
+    alias, anchors,
+    appearance::{self, Stylesheet},
+    archive,
+    article::{
+        to_url, url_of, Article, ArticleTemplate, PrintPage, Translation, DRAFT, PUBLISHED,
+        SUBMITTED,
+    },
+    assets::{self, Assets},
+    attachment,
+    auth::{self, Lockout, Scope, Signature},
+    author, backup,
+    ban::{self, Ban, BanKind},
+    changes,
+    comment::{self, Challenge, Comment, CommentRequest, FlaggedComment},
+    contact::{ContactForm, ContactNotification, ContactPage, Message},
+    datetime, db, diagram,
+    email::{CommentNotification, Email, Mailer, SubscriptionConfirmation},
+    epub,
+    error::{self, ErrorPages, TkError},
+    feed::{self, FeedCache},
+    frontmatter, gemini,
+    icons::Icons,
+    ingest,
+    jobs::{self, Job, JobContext},
+    limit::RateLimiter,
+    media::{self, Media},
+    mention::{self, Fault, Mention},
+    net::{self, ClientIp},
+    oembed::{self, OEmbed, OEmbedQuery},
+    og::{self, OgCache},
+    plugin::Plugins,
+    preview::{self, PreviewPage},
+    request::{ArticleFields, ArticleMetadata, InnerRequest, NewArticle, Request, Response},
+    retention,
+    revision::{self, HistoryPage},
+    scheduler, search,
+    secret::{self, SecretMetadata},
+    session::{self, Login, Session},
+    site::{self, SiteCache, SiteContext},
+    sitemap::{self, absolute_url},
+    stats::{self, PopularArticle, PopularCache},
+    tag::{self, TagCount},
+    totp, ServerConfig,
 };
 use comfy_table::{Row, Table};
-use rand::{
-    distributions::{Alphanumeric, DistString},
-    thread_rng,
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
 };
-use std::str::FromStr;
 
 #[derive(Clone)]
 struct BlogState {
     pool: Pool<Sqlite>,
     config: ServerConfig,
+    maintenance: Arc<AtomicBool>,
+    /// Recently used HMAC signatures with their timestamps, to reject replays
+    seen_signatures: Arc<Mutex<HashMap<String, i64>>>,
+    lockout: Arc<Lockout>,
+    contact_limiter: Arc<RateLimiter>,
+    popular: Arc<PopularCache>,
+    feeds: Arc<FeedCache>,
+    site: Arc<SiteCache>,
+    og_images: Arc<OgCache>,
+    /// System fonts for rendering article preview images
+    fonts: Arc<fontdb::Database>,
+    /// Key the session cookies are signed with, as last read from the database
+    session_key: Arc<RwLock<Option<String>>>,
 }
 
 impl BlogState {
-    async fn get_conn(&self) -> PoolConnection<Sqlite> {
-        self.pool.acquire().await.unwrap()
+    fn new(
+        pool: Pool<Sqlite>,
+        config: ServerConfig,
+        maintenance: bool,
+        fonts: fontdb::Database,
+    ) -> Self {
+        BlogState {
+            pool,
+            maintenance: Arc::new(AtomicBool::new(maintenance)),
+            seen_signatures: Arc::default(),
+            lockout: Arc::default(),
+            contact_limiter: Arc::new(RateLimiter::new(
+                config.contact.max_messages,
+                Duration::from_secs(config.contact.window),
+            )),
+            popular: Arc::default(),
+            feeds: Arc::default(),
+            site: Arc::default(),
+            og_images: Arc::default(),
+            fonts: Arc::new(fonts),
+            session_key: Arc::default(),
+            config,
+        }
+    }
+
+    async fn get_conn(&self) -> Result<PoolConnection<Sqlite>, TkError> {
+        let conn = match self.pool.acquire().await {
+            Err(sqlx::Error::PoolTimedOut) => return Err(TkError::Unavailable),
+            result => result.into_diagnostic()?,
+        };
+        Ok(conn)
+    }
+
+    async fn session_key(&self) -> Result<String, TkError> {
+        let cached = self.session_key.read().unwrap().clone();
+        match cached {
+            Some(key) => Ok(key),
+            None => self.reload_session_key().await,
+        }
+    }
+
+    /// Reads the session key again, as it changes when secrets are revoked, possibly by
+    /// another process
+    async fn reload_session_key(&self) -> Result<String, TkError> {
+        let mut conn = self.get_conn().await?;
+        let key = session::load_key(&mut conn).await?;
+        *self.session_key.write().unwrap() = Some(key.clone());
+        Ok(key)
     }
 }
 
 async fn handle_api_request(
     State(state): State<BlogState>,
-    Json(request): Json<Request>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
 ) -> Result<AxumResponse, TkError> {
-    let mut conn = state.get_conn().await;
+    let body = match body {
+        Ok(body) => body,
+        Err(rejection) if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+            let limit = state.config.api.max_body_size;
+            return Ok((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                Json(Response::Error(format!(
+                    "The request is larger than the {limit} bytes the server accepts. Upload large images with `thoughtkeeper upload` and link them instead of embedding them."
+                ))),
+            )
+                .into_response());
+        }
+        Err(rejection) => {
+            return Ok((
+                rejection.status(),
+                Json(Response::Error(rejection.body_text())),
+            )
+                .into_response())
+        }
+    };
+    let is_multipart = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|t| t.to_str().ok())
+        .is_some_and(|t| t.starts_with("multipart/form-data"));
+    let decoded = if is_multipart {
+        attachment::decode(&headers, body.clone()).await
+    } else {
+        serde_json::from_slice(&body)
+            .map(|request| (request, Vec::new()))
+            .map_err(|e| e.to_string())
+    };
+    let (request, attachments): (Request, _) = match decoded {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                Json(Response::Error(format!("Malformed request: {e}"))),
+            )
+                .into_response())
+        }
+    };
+
+    if let Some(remaining) = state.lockout.remaining(ip) {
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, remaining.as_secs().to_string())],
+            Json(Response::Error(
+                "Too many failed attempts, try again later".to_string(),
+            )),
+        )
+            .into_response());
+    }
+
+    let mut conn = state.get_conn().await?;
 
-    if !is_secret_valid(&request.secret, &mut conn).await? {
+    let caller = match (&request.secret, Signature::from_headers(&headers)) {
+        (Some(secret), _) => is_secret_valid(secret, &mut conn).await?,
+        (None, Some(signature)) => {
+            is_signature_valid(&signature, &body, &state.seen_signatures, &mut conn).await?
+        }
+        (None, None) => None,
+    };
+    let Some((secret_id, scope)) = caller else {
+        let auth_config = &state.config.auth;
+        if state.lockout.record_failure(
+            ip,
+            auth_config.max_failures,
+            Duration::from_secs(auth_config.window),
+            Duration::from_secs(auth_config.lockout),
+        ) {
+            tracing::warn!(client = %ip, "locked out after too many failed authentication attempts");
+        } else {
+            tracing::info!(client = %ip, "failed authentication attempt");
+        }
         return Ok(Json(Response::Error("Invalid secret".to_string())).into_response());
+    };
+    state.lockout.record_success(ip);
+
+    if scope == Scope::Submit
+        && !matches!(
+            request.request,
+            InnerRequest::CreateArticle { .. }
+                | InnerRequest::CreateArticles(_)
+                | InnerRequest::RenderPreview { .. }
+        )
+    {
+        return Ok((
+            StatusCode::FORBIDDEN,
+            Json(Response::Error(
+                "This secret may only submit articles".to_string(),
+            )),
+        )
+            .into_response());
+    }
+
+    if !attachments.is_empty() {
+        let error = if scope == Scope::Submit {
+            Some("This secret may not upload files")
+        } else if !matches!(request.request, InnerRequest::CreateArticle { .. }) {
+            Some("Only new articles can have attachments")
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            return Ok((
+                StatusCode::FORBIDDEN,
+                Json(Response::Error(error.to_string())),
+            )
+                .into_response());
+        }
     }
 
     match request.request {
-        InnerRequest::CreateArticle { title, content } => {
-            let article = Article::new(title, content);
+        InnerRequest::CreateArticle {
+            title,
+            content,
+            idempotency_key,
+            force_slug,
+            fields,
+        } => {
+            // The attachments are only kept if the article is created
+            let mut tx = conn.begin().await.into_diagnostic()?;
+            let content = attachment::store(&content, attachments, &mut tx).await?;
+            let new = NewArticle {
+                title,
+                content,
+                idempotency_key,
+                force_slug,
+                fields,
+            };
+            let response = create_article(new, scope, secret_id, &state.config, &mut tx).await?;
+            if matches!(response, Response::ArticleId(_)) {
+                tx.commit().await.into_diagnostic()?;
+            }
 
-            sqlx::query!(
-                "INSERT INTO articles ( id, title, content, published ) VALUES (?1, ?2, ?3, ?4)",
-                article.id,
-                article.title,
-                article.content,
-                article.published
-            )
-            .execute(&mut *conn)
-            .await
-            .into_diagnostic()?;
+            Ok(Json(response).into_response())
+        }
+        InnerRequest::CreateArticles(articles) => {
+            let mut tx = conn.begin().await.into_diagnostic()?;
+            let mut results = Vec::with_capacity(articles.len());
+            for new in articles {
+                // Each article gets a savepoint so a failing one leaves no partial rows behind
+                let mut item = tx.begin().await.into_diagnostic()?;
+                match create_article(new, scope, secret_id, &state.config, &mut item).await {
+                    Ok(Response::ArticleId(id)) => {
+                        item.commit().await.into_diagnostic()?;
+                        results.push(Response::ArticleId(id));
+                    }
+                    Ok(response) => {
+                        item.rollback().await.into_diagnostic()?;
+                        results.push(response);
+                    }
+                    Err(e) => {
+                        item.rollback().await.into_diagnostic()?;
+                        let error_id = error::report(&e);
+                        results.push(Response::Error(format!(
+                            "Internal error (error ID {error_id})"
+                        )));
+                    }
+                }
+            }
+            tx.commit().await.into_diagnostic()?;
 
-            Ok(Json(Response::ArticleId(article.id)).into_response())
+            Ok(Json(Response::Results(results)).into_response())
         }
         InnerRequest::GetArticle { url } => {
-            let titles = sqlx::query!("SELECT id, title FROM articles")
-                .fetch_all(&mut *conn)
+            let Some(article) = find_article_by_url(&url, &mut conn).await? else {
+                return Ok((
+                    StatusCode::NOT_FOUND,
+                    Json(Response::Error(format!("No article with url {url} found"))),
+                )
+                    .into_response());
+            };
+
+            Ok(Json(Response::Article(article)).into_response())
+        }
+        InnerRequest::GetArticleById { id } => {
+            let Some(article) = sqlx::query_as!(Article, "SELECT * FROM articles WHERE id = ?", id)
+                .fetch_optional(&mut *conn)
                 .await
-                .into_diagnostic()?;
-
-            let id = titles
-                .iter()
-                .find_map(|r| {
-                    if to_url(&r.title) == url {
-                        Some(&r.id)
-                    } else {
-                        None
-                    }
-                })
-                .ok_or(miette::miette!("No article with url {url} found"))?;
-            let article = sqlx::query_as!(Article, "SELECT * FROM articles WHERE id = ?", id)
-                .fetch_one(&mut *conn)
+                .into_diagnostic()?
+            else {
+                return Ok(no_article(&id));
+            };
+
+            Ok(Json(Response::Article(article)).into_response())
+        }
+        InnerRequest::PreviewArticle { id, days } => {
+            let exists = sqlx::query!("SELECT id FROM articles WHERE id = ?", id)
+                .fetch_optional(&mut *conn)
                 .await
-                .into_diagnostic()?;
+                .into_diagnostic()?
+                .is_some();
+            if !exists {
+                return Ok(no_article(&id));
+            }
+            let max_days = state.config.api.max_preview_days;
+            let Some(expires) = days
+                .checked_mul(24 * 60 * 60)
+                .and_then(|seconds| Utc::now().timestamp().checked_add(seconds))
+                .filter(|_| (1..=max_days).contains(&days))
+            else {
+                return Ok((
+                    StatusCode::BAD_REQUEST,
+                    Json(Response::Error(format!(
+                        "Preview links are valid for 1 to {max_days} days"
+                    ))),
+                )
+                    .into_response());
+            };
+            // The cached key would need a second connection
+            let key = session::load_key(&mut conn).await?;
 
-            Ok(Json(serde_json::to_string(&article).into_diagnostic()?).into_response())
+            Ok(Json(Response::ArticleUrl(format!(
+                "/preview/{}",
+                preview::token(&id, expires, &key)
+            )))
+            .into_response())
         }
         InnerRequest::YankArticle { id } => {
-            sqlx::query!("DELETE FROM articles WHERE id = ?", id)
-                .execute(&mut *conn)
+            let exists = sqlx::query!("SELECT id FROM articles WHERE id = ?", id)
+                .fetch_optional(&mut *conn)
                 .await
-                .into_diagnostic()?;
+                .into_diagnostic()?
+                .is_some();
+            if !exists {
+                return Ok(no_article(&id));
+            }
+            delete_article(&id, &mut conn).await?;
 
             Ok(Json(Response::Ok).into_response())
         }
-        InnerRequest::ListArticles => {
-            let articles = sqlx::query!("SELECT id, title, published FROM articles")
-                .fetch_all(&mut *conn)
-                .await
-                .into_diagnostic()?;
-
-            Ok(Json(Response::ArticleMetadata(
-                articles
-                    .into_iter()
-                    .map(|r| ArticleMetadata {
-                        id: r.id,
-                        title: r.title,
-                        published: r.published,
-                    })
-                    .collect::<Vec<_>>(),
-            ))
-            .into_response())
+        InnerRequest::DuplicateArticle { id, draft } => {
+            let Some(original) =
+                sqlx::query_as!(Article, "SELECT * FROM articles WHERE id = ?", id)
+                    .fetch_optional(&mut *conn)
+                    .await
+                    .into_diagnostic()?
+            else {
+                return Ok(no_article(&id));
+            };
+            let copy = NewArticle {
+                title: format!("{} (copy)", original.title),
+                content: original.content,
+                idempotency_key: None,
+                // Copying an article twice shouldn't fail
+                force_slug: true,
+                fields: ArticleFields {
+                    draft: Some(draft),
+                    ..Default::default()
+                },
+            };
+            let response = create_article(copy, scope, secret_id, &state.config, &mut conn).await?;
+
+            Ok(Json(response).into_response())
         }
-        InnerRequest::UpdateArticle { id, title, content } => {
-            match (title, content) {
-                (Some(title), Some(content)) => {
-                    sqlx::query!(
-                        "UPDATE articles SET title = ?, content = ? WHERE id = ?",
-                        title,
-                        content,
-                        id
-                    )
+        InnerRequest::SetSlug { id, slug } => {
+            let Some(article) = sqlx::query_as!(Article, "SELECT * FROM articles WHERE id = ?", id)
+                .fetch_optional(&mut *conn)
+                .await
+                .into_diagnostic()?
+            else {
+                return Ok(no_article(&id));
+            };
+            let slug = to_url(slug.trim());
+            if slug.is_empty() {
+                return Ok(Json(Response::Error(
+                    "The slug must contain at least one letter or digit".to_string(),
+                ))
+                .into_response());
+            }
+            if let Some(other) = find_article_by_url(&slug, &mut conn).await? {
+                if other.id != id {
+                    return Ok(Json(Response::Error(format!(
+                        "\"{}\" is already at /article/{slug}",
+                        other.title
+                    )))
+                    .into_response());
+                }
+            }
+
+            let old = article.url();
+            if old != slug {
+                sqlx::query!("UPDATE articles SET slug = ? WHERE id = ?", slug, id)
                     .execute(&mut *conn)
                     .await
                     .into_diagnostic()?;
-                }
-                (None, Some(content)) => {
-                    sqlx::query!("UPDATE articles SET content = ? WHERE id = ?", content, id)
-                        .execute(&mut *conn)
-                        .await
-                        .into_diagnostic()?;
-                }
-                (Some(title), None) => {
-                    sqlx::query!("UPDATE articles SET title = ? WHERE id = ?", title, id)
-                        .execute(&mut *conn)
-                        .await
-                        .into_diagnostic()?;
-                }
-
-                (None, None) => (),
+                alias::add(&id, &old, &mut conn).await?;
+                // Moving an article back to an old URL must not redirect it away from itself
+                alias::remove(&slug, &mut conn).await?;
             }
 
-            Ok(Json(Response::Ok).into_response())
+            Ok(Json(Response::ArticleUrl(format!("/article/{slug}"))).into_response())
         }
-    }
-}
-
-async fn get_article(
-    Path(url): Path<String>,
-    State(state): State<BlogState>,
-) -> Result<AxumResponse, TkError> {
-    let mut conn = state.get_conn().await;
-    let titles = sqlx::query!("SELECT id, title FROM articles")
-        .fetch_all(&mut *conn)
-        .await
-        .into_diagnostic()?;
+        InnerRequest::ListArticles(filter) => {
+            let tag = filter.tag.as_deref().map(tag::normalize);
+            let title = filter.title_contains.as_deref().map(str::to_lowercase);
+            let mut articles = sqlx::query!(
+                "SELECT id, title, published, updated, created_by FROM articles WHERE status = 'published'
+                AND (?1 IS NULL OR COALESCE(updated, published) > ?1)
+                AND (?2 IS NULL OR published < ?2)
+                AND (?3 IS NULL OR instr(lower(title), ?3) > 0)
+                AND (?4 IS NULL OR id IN (SELECT article FROM article_tags WHERE tag = ?4))
+                AND (?5 IS NULL OR created_by = ?5)",
+                filter.since,
+                filter.until,
+                title,
+                tag,
+                filter.created_by
+            )
+            .fetch_all(&mut *conn)
+            .await
+            .into_diagnostic()?
+            .into_iter()
+            .map(|r| ArticleMetadata {
+                id: r.id,
+                title: r.title,
+                published: r.published,
+                updated: r.updated,
+                created_by: r.created_by,
+            })
+            .collect::<Vec<_>>();
+            filter.sort.apply(&mut articles);
 
-    match titles.iter().find_map(|r| {
-        if to_url(&r.title) == url {
-            Some(&r.id)
-        } else {
-            None
+            Ok(Json(Response::ArticleMetadata(articles)).into_response())
         }
-    }) {
-        Some(id) => {
-            let article = sqlx::query_as!(Article, "SELECT * FROM articles WHERE id = ?", id)
-                .fetch_one(&mut *conn)
-                .await
-                .unwrap();
+        InnerRequest::UpdateArticle {
+            id,
+            title,
+            content,
+            change_note,
+            mut fields,
+        } => {
+            let Some(mut article) =
+                sqlx::query_as!(Article, "SELECT * FROM articles WHERE id = ?", id)
+                    .fetch_optional(&mut *conn)
+                    .await
+                    .into_diagnostic()?
+            else {
+                return Ok(no_article(&id));
+            };
+
+            let edited = title.is_some() || content.is_some();
+            if let Some(title) = title {
+                article.title = title;
+            }
+            if let Some(content) = content {
+                article.content = content;
+            }
+            let errors = state.config.validation.check(&article);
+            if !errors.is_empty() {
+                return Ok(Json(Response::Invalid(errors)).into_response());
+            }
+            if let Some(error) = resolve_translation(&mut fields, &mut conn).await? {
+                return Ok(Json(error).into_response());
+            }
+            article.apply(&fields);
+            article.updated = Some(Utc::now().naive_utc());
+            // Publishing a draft makes it appear as new
+            let publish = fields.draft == Some(false) && article.status == DRAFT;
+            if publish {
+                article.status = PUBLISHED.to_string();
+                article.published = Utc::now().naive_utc();
+                article.updated = None;
+            } else if fields.draft == Some(true) && article.status == PUBLISHED {
+                article.status = DRAFT.to_string();
+            }
 
-            let mut options = Options::default();
-            options.extension.footnotes = true;
-            options.extension.table = true;
-            options.extension.header_ids = Some("content-".to_string());
-            options.extension.strikethrough = true;
-            options.extension.tagfilter = true;
-            options.extension.autolink = true;
-            options.render.escape = true;
+            save_article(&article, &mut conn).await?;
+            let summary = change_note
+                .as_deref()
+                .map(str::trim)
+                .filter(|n| !n.is_empty());
+            if edited || summary.is_some() {
+                revision::record(&article, summary, &mut conn).await?;
+            }
+            if let Some(tags) = &fields.tags {
+                tag::set_tags(&id, tags, &mut conn).await?;
+            }
+            if let Some(authors) = &fields.authors {
+                author::set_authors(&id, authors, &mut conn).await?;
+            }
+            if let Some(aliases) = &fields.aliases {
+                alias::set_aliases(&id, aliases, &mut conn).await?;
+            }
+            if publish {
+                announce_article(&article, &state.config, &mut conn).await?;
+            } else if let Some(summary) = summary {
+                if article.status == PUBLISHED {
+                    changes::record(&id, summary, &mut conn).await?;
+                }
+            }
 
-            let comments = sqlx::query_as!(
-                Comment,
-                "SELECT * FROM comments WHERE article = ? ORDER BY published DESC",
-                id
+            Ok(Json(Response::Ok).into_response())
+        }
+        InnerRequest::ListArticlesSince { timestamp } => {
+            let changed = sqlx::query_as!(
+                Article,
+                "SELECT * FROM articles WHERE status = 'published' AND COALESCE(updated, published) > ? ORDER BY published",
+                timestamp
             )
             .fetch_all(&mut *conn)
             .await
-            .unwrap();
+            .into_diagnostic()?;
+            let existing = sqlx::query!("SELECT id FROM articles WHERE status = 'published'")
+                .fetch_all(&mut *conn)
+                .await
+                .into_diagnostic()?
+                .into_iter()
+                .map(|r| r.id)
+                .collect_vec();
+            let is_changed = |id: &String| changed.iter().any(|a| &a.id == id);
+            let mut tags = tag::all_tags(&mut conn).await?;
+            tags.retain(|id, _| is_changed(id));
+            let mut authors = author::all_authors(&mut conn).await?;
+            authors.retain(|id, _| is_changed(id));
 
-            Ok(ArticleTemplate {
-                config: state.config,
-                article,
-                comments,
-                options: &options,
-            }
+            Ok(Json(Response::Changes {
+                changed,
+                existing,
+                tags,
+                authors,
+            })
             .into_response())
         }
-        None => Ok(ErrorPage {
-            config: state.config,
+        InnerRequest::SetMaintenance { enabled } => {
+            let value = enabled.to_string();
+            sqlx::query!(
+                "INSERT OR REPLACE INTO settings ( key, value ) VALUES ('maintenance', ?)",
+                value
+            )
+            .execute(&mut *conn)
+            .await
+            .into_diagnostic()?;
+            state.maintenance.store(enabled, Ordering::Relaxed);
+
+            Ok(Json(Response::Ok).into_response())
         }
-        .into_response()),
-    }
-}
+        InnerRequest::UploadMedia {
+            filename,
+            content_type,
+            data,
+        } => {
+            let media = media::upload(&filename, &content_type, &data, &mut conn).await?;
 
-async fn post_comment(
-    Path(_id): Path<String>,
-    State(state): State<BlogState>,
-    Form(request): Form<CommentRequest>,
-) -> Result<AxumResponse, TkError> {
-    let mut conn = state.get_conn().await;
-    let comment = Comment::from_request(request);
+            Ok(Json(Response::MediaUrl(media.url())).into_response())
+        }
+        InnerRequest::ListMedia => {
+            Ok(Json(Response::Media(media::list(&mut conn).await?)).into_response())
+        }
+        InnerRequest::DeleteMedia { id } => {
+            if !media::delete(&id, &mut conn).await? {
+                return Ok(
+                    Json(Response::Error(format!("No media with id {id} found"))).into_response(),
+                );
+            }
+
+            Ok(Json(Response::Ok).into_response())
+        }
+        InnerRequest::SearchArticles { query } => {
+            let results = search::search(&query, true, &mut conn).await?;
 
-    sqlx::query!("INSERT INTO comments ( id, article, author, content, published ) VALUES (?1, ?2, ?3, ?4, ?5)",
-comment.id, comment.article, comment.author, comment.content, comment.published).execute(&mut *conn).await.into_diagnostic()?;
+            Ok(Json(Response::SearchResults(results)).into_response())
+        }
+        InnerRequest::RenderPreview { content } => {
+            let article = Article::new(String::new(), content);
+            let config = &state.config;
+            let mut stylesheets = vec![Stylesheet {
+                href: config.assets.url("style.css"),
+                media: "all".to_string(),
+            }];
+            stylesheets.extend(config.appearance.stylesheets(&config.assets));
 
-    Ok(Redirect::to("").into_response())
+            Ok(Json(Response::Preview {
+                html: render_article(&article, config, &mut conn).await?,
+                stylesheets,
+            })
+            .into_response())
+        }
+    }
 }
 
 #[derive(Template)]
-#[template(path = "index.html")]
-struct IndexPage {
+#[template(path = "admin/secrets.html")]
+struct SecretsPage {
     config: ServerConfig,
-    articles: Vec<Article>,
+    secrets: Vec<SecretMetadata>,
+    /// A newly created secret, which is only ever shown once
+    created: Option<(i64, String)>,
+    csrf: String,
 }
 
-async fn index(State(state): State<BlogState>) -> Result<AxumResponse, TkError> {
-    let mut conn = state.get_conn().await;
-    let articles = sqlx::query_as!(Article, "SELECT * FROM articles ORDER BY published DESC")
-        .fetch_all(&mut *conn)
-        .await
-        .into_diagnostic()?;
+async fn secrets_page(
+    state: BlogState,
+    session: Session,
+    created: Option<(i64, String)>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let secrets = secret::list(&mut conn).await?;
 
-    Ok(IndexPage {
+    Ok(SecretsPage {
         config: state.config,
-        articles,
+        secrets,
+        created,
+        csrf: session.csrf,
     }
     .into_response())
 }
 
-#[derive(Template)]
-#[template(path = "404.html")]
-struct ErrorPage {
-    config: ServerConfig,
+async fn list_secrets_admin(
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+) -> Result<AxumResponse, TkError> {
+    secrets_page(state, session, None).await
 }
 
-async fn rss_feed(State(state): State<BlogState>) -> Result<AxumResponse, TkError> {
-    let mut conn = state.get_conn().await;
-    let articles = sqlx::query_as!(Article, "SELECT * FROM articles ORDER BY published DESC")
-        .fetch_all(&mut *conn)
-        .await
-        .into_diagnostic()?;
-    let channel = ChannelBuilder::default()
-        .title(state.config.blog_name)
-        .description(state.config.description)
-        .items(articles.into_iter().map(Into::into).collect_vec())
-        .build();
+#[derive(Deserialize)]
+struct SecretForm {
+    #[serde(default)]
+    description: String,
+    /// Checkbox, only sent when checked
+    hmac: Option<String>,
+    scope: String,
+    /// A date, or empty if the secret never expires
+    #[serde(default)]
+    expires: String,
+}
+
+impl SecretForm {
+    fn expires_at(&self) -> Result<Option<chrono::NaiveDateTime>, TkError> {
+        if self.expires.is_empty() {
+            return Ok(None);
+        }
+        let date = NaiveDate::parse_from_str(&self.expires, "%Y-%m-%d").into_diagnostic()?;
+        Ok(Some(date.and_time(NaiveTime::MIN)))
+    }
+}
+
+async fn create_secret_admin(
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+    Form(form): Form<SecretForm>,
+) -> Result<AxumResponse, TkError> {
+    let expires_at = form.expires_at()?;
+    let description = Some(form.description.trim().to_string()).filter(|d| !d.is_empty());
+    let mut conn = state.get_conn().await?;
+    let created = secret::create(
+        description,
+        form.hmac.is_some(),
+        Scope::from_db(&form.scope),
+        expires_at,
+        &mut conn,
+    )
+    .await?;
+    drop(conn);
+
+    secrets_page(state, session, Some(created)).await
+}
+
+async fn update_secret_admin(
+    Path(id): Path<i64>,
+    State(state): State<BlogState>,
+    Form(form): Form<SecretForm>,
+) -> Result<AxumResponse, TkError> {
+    let expires_at = form.expires_at()?;
+    let mut conn = state.get_conn().await?;
+    secret::update(id, Scope::from_db(&form.scope), expires_at, &mut conn).await?;
+
+    Ok(Redirect::to("/admin/secrets").into_response())
+}
+
+/// Revokes a secret and logs out every session, since any of them may have been opened
+/// with it. The admin doing this gets a new session right away.
+async fn revoke_secret_admin(
+    Path(id): Path<i64>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let mut tx = conn.begin().await.into_diagnostic()?;
+    secret::revoke(id, &mut tx).await?;
+    session::rotate_key(&mut tx).await?;
+    tx.commit().await.into_diagnostic()?;
+    drop(conn);
 
+    let key = state.reload_session_key().await?;
+    let session = Session::new(Login::Admin, &key, &state.config.session);
     Ok((
-        [(header::CONTENT_TYPE, "application/rss+xml")],
-        channel.to_string(),
+        [(
+            header::SET_COOKIE,
+            session.cookie(&key, &state.config.session),
+        )],
+        Redirect::to("/admin/secrets"),
     )
         .into_response())
 }
 
-pub async fn serve(config: ServerConfig) -> miette::Result<()> {
-    let state = BlogState {
-        pool: SqlitePool::connect("sqlite://articles.db")
-            .await
-            .into_diagnostic()?,
-        config: config.clone(),
+/// Serves an uploaded file. The file name in the URL is only there for readability.
+async fn get_media(
+    Path((id, _filename)): Path<(String, String)>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let Some((content_type, data)) = media::contents(&id, &mut conn).await? else {
+        drop(conn);
+        return not_found(state, &format!("/media/{id}")).await;
     };
 
-    let error_cfg = config.clone();
-    let router = Router::new()
-        .nest_service(
-            "/static",
-            get_service(ServeDir::new("static").not_found_service(ServeFile::new("/404.html"))),
-        )
-        .route("/", get(index))
-        .route("/article/:id", get(get_article))
-        .route("/article/:id", post(post_comment))
-        .route("/api", post(handle_api_request))
-        .route("/rss", get(rss_feed))
-        .fallback(get(|| async { ErrorPage { config: error_cfg } }))
-        .with_state(state);
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (
+                header::CACHE_CONTROL,
+                "public, max-age=31536000, immutable".to_string(),
+            ),
+        ],
+        data,
+    )
+        .into_response())
+}
 
-    let listener = TcpListener::bind(&config.addr).await.into_diagnostic()?;
-    axum::serve(listener, router.into_make_service())
-        .await
-        .into_diagnostic()?;
-    Ok(())
+/// Sends browsers that haven't logged in to the admin panel to the login page
+async fn require_admin(
+    Extension(session): Extension<Session>,
+    request: HttpRequest<Body>,
+    next: Next,
+) -> AxumResponse {
+    if session.login != Login::Admin {
+        return Redirect::to("/admin/login").into_response();
+    }
+
+    next.run(request).await
 }
 
-pub async fn create_secret(description: Option<String>) -> miette::Result<()> {
-    let secret = Alphanumeric.sample_string(&mut thread_rng(), 64);
+#[derive(Template)]
+#[template(path = "admin/login.html")]
+struct LoginPage {
+    config: ServerConfig,
+    /// Whether the secret was accepted and the second factor is asked for
+    code: bool,
+    failed: bool,
+    csrf: String,
+}
 
-    let mut conn = SqliteConnectOptions::from_str("sqlite://articles.db")
-        .into_diagnostic()?
-        .connect()
-        .await
-        .into_diagnostic()?;
+async fn login_form(
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+) -> LoginPage {
+    LoginPage {
+        config: state.config,
+        code: session.login == Login::AwaitingCode,
+        failed: false,
+        csrf: session.csrf,
+    }
+}
 
-    sqlx::query!(
-        "INSERT INTO secrets (secret, description) VALUES (?1, ?2)",
-        secret,
-        description
+/// Answers a failed login attempt and counts it towards the client's lockout
+fn login_failed(state: BlogState, ip: IpAddr, session: Session, code: bool) -> AxumResponse {
+    let auth_config = &state.config.auth;
+    state.lockout.record_failure(
+        ip,
+        auth_config.max_failures,
+        Duration::from_secs(auth_config.window),
+        Duration::from_secs(auth_config.lockout),
+    );
+    tracing::info!(client = %ip, "failed admin login");
+    (
+        StatusCode::UNAUTHORIZED,
+        LoginPage {
+            config: state.config,
+            code,
+            failed: true,
+            csrf: session.csrf,
+        },
     )
-    .execute(&mut conn)
-    .await
-    .into_diagnostic()?;
+        .into_response()
+}
 
-    println!("Your client secret is:");
-    println!("{secret}");
-    println!("Please note that you will *not* be able to see it again.");
-    Ok(())
+/// Replaces the session with one at the given login stage and continues to the admin panel
+async fn start_session(state: &BlogState, login: Login) -> Result<AxumResponse, TkError> {
+    let key = state.session_key().await?;
+    let session = Session::new(login, &key, &state.config.session);
+    let next = match login {
+        Login::Admin => "/admin/media",
+        _ => "/admin/login",
+    };
+    Ok((
+        [(
+            header::SET_COOKIE,
+            session.cookie(&key, &state.config.session),
+        )],
+        Redirect::to(next),
+    )
+        .into_response())
 }
 
-pub async fn list_secrets() -> miette::Result<()> {
-    let mut conn = SqliteConnectOptions::from_str("sqlite://articles.db")
-        .into_diagnostic()?
-        .connect()
-        .await
-        .into_diagnostic()?;
+fn locked_out(remaining: Duration) -> AxumResponse {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, remaining.as_secs().to_string())],
+        "Too many failed attempts, try again later",
+    )
+        .into_response()
+}
 
-    let secrets = sqlx::query!("SELECT id, description FROM secrets",)
-        .fetch_all(&mut conn)
-        .await
-        .into_diagnostic()?;
+#[derive(Deserialize)]
+struct LoginForm {
+    secret: String,
+}
 
-    let mut table = Table::new();
-    table.set_header(Row::from(vec!["ID", "Description"]));
-    for row in secrets {
-        table.add_row([
-            &row.id.to_string(),
-            &row.description.unwrap_or("-".to_string()),
-        ]);
+/// Logs in with a `publish` scoped secret, starting a new session so a session
+/// id planted before the login can't be used to hijack it
+async fn login(
+    State(state): State<BlogState>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Extension(session): Extension<Session>,
+    Form(form): Form<LoginForm>,
+) -> Result<AxumResponse, TkError> {
+    if let Some(remaining) = state.lockout.remaining(ip) {
+        return Ok(locked_out(remaining));
     }
-    println!("{table}");
-
-    Ok(())
-}
 
-pub async fn revoke_secret(id: i64) -> miette::Result<()> {
-    let mut conn = SqliteConnectOptions::from_str("sqlite://articles.db")
-        .into_diagnostic()?
-        .connect()
-        .await
-        .into_diagnostic()?;
+    let mut conn = state.get_conn().await?;
+    let scope = is_secret_valid(&form.secret, &mut conn).await?;
+    if scope.map(|(_, scope)| scope) != Some(Scope::Publish) {
+        drop(conn);
+        return Ok(login_failed(state, ip, session, false));
+    }
+    let two_factor = totp::is_enabled(&mut conn).await?;
+    drop(conn);
 
-    sqlx::query!("DELETE FROM secrets where id = ?", id)
-        .execute(&mut conn)
-        .await
-        .into_diagnostic()?;
+    if two_factor {
+        return start_session(&state, Login::AwaitingCode).await;
+    }
+    state.lockout.record_success(ip);
+    start_session(&state, Login::Admin).await
+}
 
-    Ok(())
+#[derive(Deserialize)]
+struct CodeForm {
+    code: String,
 }
 
-async fn is_secret_valid(secret: &str, conn: &mut SqliteConnection) -> miette::Result<bool> {
-    Ok(
-        sqlx::query!("SELECT id FROM secrets WHERE secret = ?", secret)
-            .fetch_optional(conn)
+/// The second step of logging in, with a code from the authenticator app or a recovery code
+async fn login_code(
+    State(state): State<BlogState>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Extension(session): Extension<Session>,
+    Form(form): Form<CodeForm>,
+) -> Result<AxumResponse, TkError> {
+    if session.login != Login::AwaitingCode {
+        return Ok(Redirect::to("/admin/login").into_response());
+    }
+    if let Some(remaining) = state.lockout.remaining(ip) {
+        return Ok(locked_out(remaining));
+    }
+
+    let mut conn = state.get_conn().await?;
+    let valid = totp::verify(&form.code, &state.config.blog_name, &mut conn).await?;
+    drop(conn);
+    if !valid {
+        return Ok(login_failed(state, ip, session, true));
+    }
+
+    state.lockout.record_success(ip);
+    start_session(&state, Login::Admin).await
+}
+
+#[derive(Template)]
+#[template(path = "admin/two_factor.html")]
+struct TwoFactorPage {
+    config: ServerConfig,
+    enabled: bool,
+    remaining_codes: i64,
+    /// A secret waiting to be confirmed
+    setup: Option<totp::Setup>,
+    /// Freshly generated recovery codes, which are only ever shown once
+    recovery_codes: Vec<String>,
+    failed: bool,
+    csrf: String,
+}
+
+async fn two_factor_page(
+    state: BlogState,
+    session: Session,
+    recovery_codes: Vec<String>,
+    failed: bool,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let blog_name = &state.config.blog_name;
+    let enabled = totp::is_enabled(&mut conn).await?;
+    let remaining_codes = totp::remaining_recovery_codes(&mut conn).await?;
+    let setup = totp::pending_setup(blog_name, &mut conn).await?;
+
+    Ok(TwoFactorPage {
+        config: state.config,
+        enabled,
+        remaining_codes,
+        setup,
+        recovery_codes,
+        failed,
+        csrf: session.csrf,
+    }
+    .into_response())
+}
+
+async fn show_two_factor(
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+) -> Result<AxumResponse, TkError> {
+    two_factor_page(state, session, Vec::new(), false).await
+}
+
+#[derive(Deserialize)]
+struct CurrentCodeForm {
+    /// A code from the app in use, only needed while two-factor authentication is enabled
+    #[serde(default)]
+    current: String,
+}
+
+/// Whether the second factor may be changed: always while it is off, and otherwise only
+/// with a current code, so a stolen session can't enrol its own app or get recovery codes
+async fn may_change_two_factor(
+    current: &str,
+    state: &BlogState,
+    conn: &mut SqliteConnection,
+) -> miette::Result<bool> {
+    Ok(!totp::is_enabled(conn).await?
+        || totp::verify(current, &state.config.blog_name, conn).await?)
+}
+
+async fn setup_two_factor(
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+    Form(form): Form<CurrentCodeForm>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    if !may_change_two_factor(&form.current, &state, &mut conn).await? {
+        drop(conn);
+        return two_factor_page(state, session, Vec::new(), true).await;
+    }
+    totp::begin_setup(&state.config.blog_name, &mut conn).await?;
+
+    Ok(Redirect::to("/admin/two-factor").into_response())
+}
+
+#[derive(Deserialize)]
+struct ConfirmForm {
+    /// A code from the new app
+    code: String,
+    #[serde(default)]
+    current: String,
+}
+
+async fn confirm_two_factor(
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+    Form(form): Form<ConfirmForm>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let confirmed = if may_change_two_factor(&form.current, &state, &mut conn).await? {
+        totp::confirm(&form.code, &state.config.blog_name, &mut conn).await?
+    } else {
+        None
+    };
+    drop(conn);
+
+    match confirmed {
+        Some(codes) => two_factor_page(state, session, codes, false).await,
+        None => two_factor_page(state, session, Vec::new(), true).await,
+    }
+}
+
+async fn regenerate_recovery_codes(
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+    Form(form): Form<CurrentCodeForm>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    if !may_change_two_factor(&form.current, &state, &mut conn).await? {
+        drop(conn);
+        return two_factor_page(state, session, Vec::new(), true).await;
+    }
+    let codes = totp::regenerate_recovery_codes(&mut conn).await?;
+    drop(conn);
+
+    two_factor_page(state, session, codes, false).await
+}
+
+/// Turns off the second factor. Asks for a current code so a stolen session can't do it.
+async fn disable_two_factor(
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+    Form(form): Form<CodeForm>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    if !totp::verify(&form.code, &state.config.blog_name, &mut conn).await? {
+        drop(conn);
+        return two_factor_page(state, session, Vec::new(), true).await;
+    }
+    totp::disable(&mut conn).await?;
+
+    Ok(Redirect::to("/admin/two-factor").into_response())
+}
+
+async fn logout() -> AxumResponse {
+    (
+        [(header::SET_COOKIE, Session::clear_cookie())],
+        Redirect::to("/"),
+    )
+        .into_response()
+}
+
+/// The CSRF token of a submitted form
+#[derive(Deserialize)]
+struct CsrfToken {
+    csrf: Option<String>,
+}
+
+/// Endpoints for other programs, which authenticate themselves instead of using the session
+const CSRF_EXEMPT: &[&str] = &["/api", "/xmlrpc"];
+
+/// Attaches the browser's session to the request, starting a new one if needed, and
+/// rejects state-changing requests that don't carry the session's CSRF token
+async fn sessions(
+    State(state): State<BlogState>,
+    request: HttpRequest<Body>,
+    next: Next,
+) -> Result<AxumResponse, TkError> {
+    // Nothing holds a connection yet, so this is the place to notice a rotated key
+    let key = state.reload_session_key().await?;
+    let existing = Session::from_headers(request.headers(), &key);
+    let is_new = existing.is_none();
+    let session =
+        existing.unwrap_or_else(|| Session::new(Login::Guest, &key, &state.config.session));
+
+    let needs_token = !request.method().is_safe() && !CSRF_EXEMPT.contains(&request.uri().path());
+    let mut request = if needs_token {
+        let is_form = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|t| t.to_str().ok())
+            .is_some_and(|t| t.starts_with("application/x-www-form-urlencoded"));
+        let (parts, body) = request.into_parts();
+        let Ok(body) = to_bytes(body, state.config.limits.max_form_size).await else {
+            return Ok((StatusCode::PAYLOAD_TOO_LARGE, "This form is too large.").into_response());
+        };
+        // Only our own forms can carry the token, so any other kind of body is refused
+        let token = is_form
+            .then(|| serde_urlencoded::from_bytes::<CsrfToken>(&body).ok())
+            .flatten()
+            .and_then(|f| f.csrf);
+        if !token.is_some_and(|t| session.check_csrf(&t)) {
+            return Ok((
+                StatusCode::FORBIDDEN,
+                "This form has expired, please reload the page and try again.",
+            )
+                .into_response());
+        }
+        HttpRequest::from_parts(parts, Body::from(body))
+    } else {
+        request
+    };
+
+    request.extensions_mut().insert(session.clone());
+    let mut response = next.run(request).await;
+    // Only pages can contain forms, so static files and feeds don't need to set cookies
+    let is_page = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|t| t.to_str().ok())
+        .is_some_and(|t| t.starts_with("text/html"));
+    if is_new && is_page && !response.headers().contains_key(header::SET_COOKIE) {
+        let cookie = session.cookie(&key, &state.config.session);
+        response
+            .headers_mut()
+            .insert(header::SET_COOKIE, cookie.parse().into_diagnostic()?);
+    }
+    Ok(response)
+}
+
+#[derive(Template)]
+#[template(path = "admin/media.html")]
+struct MediaPage {
+    config: ServerConfig,
+    media: Vec<Media>,
+    csrf: String,
+}
+
+async fn media_library(
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let media = media::list(&mut conn).await?;
+
+    Ok(MediaPage {
+        config: state.config,
+        media,
+        csrf: session.csrf,
+    }
+    .into_response())
+}
+
+/// The earlier versions of any article, including drafts
+async fn article_history_admin(
+    Path(id): Path<String>,
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let Some(article) = sqlx::query_as!(Article, "SELECT * FROM articles WHERE id = ?", id)
+        .fetch_optional(&mut *conn)
+        .await
+        .into_diagnostic()?
+    else {
+        return Ok(error::page(&state.config, StatusCode::NOT_FOUND));
+    };
+    let revisions = revision::history(&article.id, &mut conn).await?;
+
+    Ok(HistoryPage {
+        config: state.config,
+        article,
+        revisions,
+        csrf: Some(session.csrf),
+    }
+    .into_response())
+}
+
+#[derive(Template)]
+#[template(path = "admin/comments.html")]
+struct FlaggedCommentsPage {
+    config: ServerConfig,
+    comments: Vec<FlaggedComment>,
+    csrf: String,
+}
+
+async fn flagged_comments(
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let comments = comment::flagged(&mut conn).await?;
+
+    Ok(FlaggedCommentsPage {
+        config: state.config,
+        comments,
+        csrf: session.csrf,
+    }
+    .into_response())
+}
+
+async fn keep_comment(
+    Path(id): Path<String>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    comment::keep(&id, &mut conn).await?;
+
+    Ok(Redirect::to("/admin/comments").into_response())
+}
+
+async fn delete_comment(
+    Path(id): Path<String>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    comment::delete(&id, &mut conn).await?;
+
+    Ok(Redirect::to("/admin/comments").into_response())
+}
+
+/// Bans whoever posted a reported comment and removes the comment
+async fn ban_comment_author(
+    Path(id): Path<String>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    ban::ban_author(&id, Some("Reported comment"), &mut conn).await?;
+    comment::delete(&id, &mut conn).await?;
+
+    Ok(Redirect::to("/admin/comments").into_response())
+}
+
+#[derive(Template)]
+#[template(path = "admin/bans.html")]
+struct BansPage {
+    config: ServerConfig,
+    bans: Vec<Ban>,
+    csrf: String,
+}
+
+async fn bans_page(
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let bans = ban::list(&mut conn).await?;
+
+    Ok(BansPage {
+        config: state.config,
+        bans,
+        csrf: session.csrf,
+    }
+    .into_response())
+}
+
+#[derive(Deserialize)]
+struct BanForm {
+    kind: BanKind,
+    value: String,
+    #[serde(default)]
+    reason: String,
+}
+
+async fn add_ban(
+    State(state): State<BlogState>,
+    Form(form): Form<BanForm>,
+) -> Result<AxumResponse, TkError> {
+    let value = form.value.trim();
+    if value.is_empty() || (form.kind == BanKind::Ip && value.parse::<IpAddr>().is_err()) {
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, "Nothing to ban.").into_response());
+    }
+    let mut conn = state.get_conn().await?;
+    ban::add(form.kind, value, Some(&form.reason), &mut conn).await?;
+
+    Ok(Redirect::to("/admin/bans").into_response())
+}
+
+async fn remove_ban(
+    Path(id): Path<i64>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    ban::remove(id, &mut conn).await?;
+
+    Ok(Redirect::to("/admin/bans").into_response())
+}
+
+async fn delete_media(
+    Path(id): Path<String>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    media::delete(&id, &mut conn).await?;
+
+    Ok(Redirect::to("/admin/media").into_response())
+}
+
+/// The social preview image of an article, referenced by its OpenGraph tags
+async fn og_image(
+    Path(url): Path<String>,
+    State(state): State<BlogState>,
+    headers: HeaderMap,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let Some(article) = find_article_by_url(&url, &mut conn).await? else {
+        return Ok(error::page(&state.config, StatusCode::NOT_FOUND));
+    };
+    drop(conn);
+
+    let last_modified = article.last_modified();
+    let etag = format!(
+        "\"{}-{}\"",
+        article.id,
+        Utc.from_utc_datetime(&last_modified).timestamp_micros()
+    );
+    let unchanged = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|tags| tags.to_str().ok())
+        .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag));
+    let caching = [
+        (header::CACHE_CONTROL, "public, max-age=86400".to_string()),
+        (header::ETAG, etag),
+    ];
+    if unchanged {
+        return Ok((StatusCode::NOT_MODIFIED, caching).into_response());
+    }
+
+    let png = match state.og_images.get(&article.id, last_modified) {
+        Some(png) => png,
+        None => {
+            let fonts = state.fonts.clone();
+            let blog_name = state.config.blog_name.clone();
+            let title = article.title.clone();
+            let png = tokio::task::spawn_blocking(move || og::render(&title, &blog_name, &fonts))
+                .await
+                .into_diagnostic()??;
+            let png = Bytes::from(png);
+            state
+                .og_images
+                .insert(article.id, last_modified, png.clone());
+            png
+        }
+    };
+
+    Ok(([(header::CONTENT_TYPE, "image/png")], caching, png).into_response())
+}
+
+async fn print_article(
+    Path(url): Path<String>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let Some(article) = find_article_by_url(&url, &mut conn).await? else {
+        return Ok(error::page(&state.config, StatusCode::NOT_FOUND));
+    };
+    let authors = author::authors_for(&article.id, &state.config.author, &mut conn).await?;
+    let content = render_article(&article, &state.config, &mut conn).await?;
+    drop(conn);
+
+    Ok(PrintPage {
+        config: state.config,
+        article,
+        authors,
+        content,
+    }
+    .into_response())
+}
+
+/// The article as an EPUB book, with the uploaded images it shows
+async fn export_article(
+    Path(url): Path<String>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let Some(article) = find_article_by_url(&url, &mut conn).await? else {
+        return Ok(error::page(&state.config, StatusCode::NOT_FOUND));
+    };
+    let authors = author::authors_for(&article.id, &state.config.author, &mut conn).await?;
+    let mut images = Vec::new();
+    for url in article.images() {
+        let Some(id) = media::id_of(&url) else {
+            continue;
+        };
+        if let Some((content_type, data)) = media::contents(id, &mut conn).await? {
+            images.push(epub::Image {
+                url,
+                content_type,
+                data,
+            });
+        }
+    }
+    let content = render_article(&article, &state.config, &mut conn).await?;
+    drop(conn);
+
+    let book = epub::book(
+        &article,
+        &authors,
+        &content,
+        &images,
+        state.config.domain.as_deref(),
+    )?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/epub+zip".to_string()),
+            (header::CONTENT_DISPOSITION, download_as(&article, "epub")),
+        ],
+        book,
+    )
+        .into_response())
+}
+
+/// The article's markdown with its front matter, as `publish` reads it
+async fn article_markdown(
+    Path(url): Path<String>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let article = match find_article_by_url(&url, &mut conn).await? {
+        Some(article) if state.config.actions.markdown => article,
+        _ => return Ok(error::page(&state.config, StatusCode::NOT_FOUND)),
+    };
+    let tags = tag::tags_for(&article.id, &mut conn).await?;
+    let authors = author::explicit_authors(&article.id, &mut conn).await?;
+    drop(conn);
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/markdown; charset=utf-8".to_string(),
+            ),
+            (header::CONTENT_DISPOSITION, download_as(&article, "md")),
+        ],
+        frontmatter::render(&article, &tags, &authors)?,
+    )
+        .into_response())
+}
+
+/// A `Content-Disposition` saving the response under the article's URL
+fn download_as(article: &Article, extension: &str) -> String {
+    let filename: String = article
+        .url()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || "-_".contains(*c))
+        .collect();
+    format!("attachment; filename=\"{filename}.{extension}\"")
+}
+
+/// The earlier versions of an article, if they are public
+async fn article_history(
+    Path(url): Path<String>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let article = match find_article_by_url(&url, &mut conn).await? {
+        Some(article) if state.config.public_history => article,
+        _ => return Ok(error::page(&state.config, StatusCode::NOT_FOUND)),
+    };
+    let revisions = revision::history(&article.id, &mut conn).await?;
+
+    Ok(HistoryPage {
+        config: state.config,
+        article,
+        revisions,
+        csrf: None,
+    }
+    .into_response())
+}
+
+async fn get_article(
+    Path(url): Path<String>,
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+) -> Result<AxumResponse, TkError> {
+    let key = state.session_key().await?;
+    let mut conn = state.get_conn().await?;
+
+    match find_article_by_url(&url, &mut conn).await? {
+        Some(article) => {
+            let comments = sqlx::query_as!(
+                Comment,
+                "SELECT * FROM comments WHERE article = ? AND NOT hidden ORDER BY published DESC",
+                article.id
+            )
+            .fetch_all(&mut *conn)
+            .await
+            .into_diagnostic()?;
+
+            let mentions = sqlx::query_as!(
+                Mention,
+                "SELECT * FROM mentions WHERE article = ? ORDER BY received",
+                article.id
+            )
+            .fetch_all(&mut *conn)
+            .await
+            .into_diagnostic()?;
+
+            let tags = tag::tags_for(&article.id, &mut conn).await?;
+            let authors = author::authors_for(&article.id, &state.config.author, &mut conn).await?;
+            let translations = translations_of(&article, &mut conn).await?;
+            stats::record_view(&article.id, &mut conn).await?;
+            let challenge = Challenge::new(&article.id, &state.config.comments, &key);
+            let snapshot = archive::latest(&article.id, &mut conn).await?;
+            let edit_notes = if state.config.edit_notes {
+                revision::edit_notes(&article.id, &mut conn).await?
+            } else {
+                Vec::new()
+            };
+
+            let content = render_article(&article, &state.config, &mut conn).await?;
+
+            let pingback = state
+                .config
+                .domain
+                .as_ref()
+                .map(|domain| absolute_url(domain, "/xmlrpc"))
+                .unwrap_or("/xmlrpc".to_string());
+
+            Ok((
+                [("x-pingback", pingback)],
+                ArticleTemplate {
+                    config: state.config,
+                    article,
+                    authors,
+                    tags,
+                    translations,
+                    comments,
+                    challenge,
+                    mentions,
+                    content,
+                    snapshot,
+                    edit_notes,
+                    csrf: session.csrf,
+                },
+            )
+                .into_response())
+        }
+        None => {
+            // The error page needs a connection of its own
+            drop(conn);
+            not_found(state, &format!("/article/{url}")).await
+        }
+    }
+}
+
+/// The HTML of an article's content, after the plugins had their say
+async fn render_article(
+    article: &Article,
+    config: &ServerConfig,
+    conn: &mut SqliteConnection,
+) -> miette::Result<String> {
+    let mut options = Options::default();
+    options.extension.footnotes = true;
+    options.extension.table = true;
+    options.extension.header_ids = Some("content-".to_string());
+    options.extension.strikethrough = true;
+    options.extension.tagfilter = true;
+    options.extension.autolink = true;
+    let html = config.html.render(article, options);
+    let html = anchors::heading_links(&html, &config.appearance.heading_link);
+    let html = anchors::footnote_backlinks(&html, &config.appearance.footnote_backlink);
+    let html = match config.mermaid {
+        Some(_) => diagram::mermaid(&html),
+        None => html,
+    };
+    let html = config.links.mark_external(&html, config.domain.as_deref());
+    let dimensions = media::dimensions(&article.images(), conn).await?;
+    let html = media::size_images(&html, &dimensions);
+    Ok(config.plugin_runtime.on_render(article, html))
+}
+
+/// Shows a draft to anyone with a valid preview link
+async fn preview(
+    Path(token): Path<String>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let key = state.reload_session_key().await?;
+    let Some((id, expires)) = preview::verify(&token, &key) else {
+        return not_found(state, "/preview").await;
+    };
+    let mut conn = state.get_conn().await?;
+    let Some(article) = sqlx::query_as!(Article, "SELECT * FROM articles WHERE id = ?", id)
+        .fetch_optional(&mut *conn)
+        .await
+        .into_diagnostic()?
+    else {
+        drop(conn);
+        return not_found(state, "/preview").await;
+    };
+
+    Ok(PreviewPage {
+        content: render_article(&article, &state.config, &mut conn).await?,
+        config: state.config,
+        article,
+        expires: datetime::display(expires),
+    }
+    .into_response())
+}
+
+/// Redirects aliases to their article and shows the error page for anything else
+async fn not_found(state: BlogState, path: &str) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    if let Some(url) = alias::resolve(path, &mut conn).await? {
+        let location = format!("/article/{}", utf8_percent_encode(&url, NON_ALPHANUMERIC));
+        return Ok((
+            StatusCode::MOVED_PERMANENTLY,
+            [(header::LOCATION, location)],
+        )
+            .into_response());
+    }
+
+    Ok(error::page(&state.config, StatusCode::NOT_FOUND))
+}
+
+/// The legacy XML-RPC pingback endpoint
+async fn pingback(State(state): State<BlogState>, body: String) -> Result<AxumResponse, TkError> {
+    let xml = |body: String| ([(header::CONTENT_TYPE, "text/xml")], body).into_response();
+
+    let (source, target) = match mention::parse_ping(&body) {
+        Ok(ping) => ping,
+        Err(fault) => return Ok(xml(fault.to_xml())),
+    };
+    let url = match mention::article_url(&target, state.config.domain.as_deref()) {
+        Ok(url) => url,
+        Err(fault) => return Ok(xml(fault.to_xml())),
+    };
+
+    let mut conn = state.get_conn().await?;
+    let Some(article) = find_article_by_url(&url, &mut conn).await? else {
+        return Ok(xml(Fault::TargetNotFound.to_xml()));
+    };
+
+    let existing = sqlx::query!(
+        "SELECT id FROM mentions WHERE article = ? AND source = ?",
+        article.id,
+        source
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .into_diagnostic()?;
+    if existing.is_some() {
+        return Ok(xml(Fault::AlreadyRegistered.to_xml()));
+    }
+
+    // Don't hold on to the connection while waiting for the other site
+    drop(conn);
+    if let Err(fault) = mention::verify_source(&source, &target).await {
+        return Ok(xml(fault.to_xml()));
+    }
+
+    let mention = Mention::new(article.id, source, "pingback");
+    let mut conn = state.get_conn().await?;
+    sqlx::query!(
+        "INSERT OR IGNORE INTO mentions ( id, article, source, kind, received ) VALUES (?1, ?2, ?3, ?4, ?5)",
+        mention.id,
+        mention.article,
+        mention.source,
+        mention.kind,
+        mention.received
+    )
+    .execute(&mut *conn)
+    .await
+    .into_diagnostic()?;
+    tracing::info!("registered pingback from {}", mention.source);
+
+    Ok(xml(mention::success_xml()))
+}
+
+/// Reports a comment for review and goes back to its article
+async fn flag_comment(
+    Path(id): Path<String>,
+    State(state): State<BlogState>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+) -> Result<AxumResponse, TkError> {
+    let threshold = state.config.comments.flag_threshold;
+    if !state.config.comments.enabled || threshold == 0 {
+        return Ok(error::page(&state.config, StatusCode::NOT_FOUND));
+    }
+    let mut conn = state.get_conn().await?;
+    let ip_hash = comment::hash_ip(ip, &mut conn).await?;
+    let Some(article) = comment::flag(&id, &ip_hash, threshold, &mut conn).await? else {
+        return Ok(error::page(&state.config, StatusCode::NOT_FOUND));
+    };
+    let article = sqlx::query!("SELECT title, slug FROM articles WHERE id = ?", article)
+        .fetch_one(&mut *conn)
+        .await
+        .into_diagnostic()?;
+
+    let url = url_of(&article.title, article.slug.as_deref());
+    Ok(Redirect::to(&format!(
+        "/article/{}#comments",
+        utf8_percent_encode(&url, NON_ALPHANUMERIC)
+    ))
+    .into_response())
+}
+
+async fn post_comment(
+    Path(url): Path<String>,
+    State(state): State<BlogState>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Form(request): Form<CommentRequest>,
+) -> Result<AxumResponse, TkError> {
+    if !state.config.comments.enabled {
+        return Ok((StatusCode::FORBIDDEN, "Comments are disabled.").into_response());
+    }
+    let key = state.session_key().await?;
+    if let Err(reason) = request.check_challenge(&state.config.comments, &key) {
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, reason).into_response());
+    }
+    let mut conn = state.get_conn().await?;
+    if state.config.comments.pow_difficulty.is_some() && !request.spend_challenge(&mut conn).await?
+    {
+        return Ok((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The comment could not be verified, please reload the page and try again.",
+        )
+            .into_response());
+    }
+    let subscription = request.subscription();
+    let email = request.email().map(str::to_string);
+    let mut comment = Comment::from_request(request);
+    let open = sqlx::query!(
+        "SELECT comments_enabled FROM articles WHERE id = ? AND status = 'published'",
+        comment.article
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .into_diagnostic()?
+    .is_some_and(|r| r.comments_enabled);
+    if !open {
+        return Ok((
+            StatusCode::FORBIDDEN,
+            "Comments on this article are closed.",
+        )
+            .into_response());
+    }
+    let ip_hash = comment::hash_ip(ip, &mut conn).await?;
+    // Banned commenters are answered like everyone else, so they don't just try again
+    if ban::is_banned(&ip_hash, email.as_deref(), &comment.author, &mut conn).await? {
+        tracing::info!(client = %ip, "discarded comment from a banned commenter");
+        return Ok(Redirect::to("").into_response());
+    }
+
+    let since = comment.published - chrono::Duration::seconds(state.config.comments.window as i64);
+    let recent = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!: i64" FROM comments WHERE ip_hash = ? AND published > ?"#,
+        ip_hash,
+        since
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .into_diagnostic()?
+    .count;
+    if recent >= state.config.comments.max_comments {
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            "You have posted too many comments, please try again later.",
+        )
+            .into_response());
+    }
+    comment.ip_hash = Some(ip_hash);
+    if let Err(reason) = state.config.plugin_runtime.on_comment(&mut comment) {
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, reason).into_response());
+    }
+
+    sqlx::query!("INSERT INTO comments ( id, article, author, content, published, ip_hash ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+comment.id, comment.article, comment.author, comment.content, comment.published, comment.ip_hash).execute(&mut *conn).await.into_diagnostic()?;
+
+    if let Some(email_config) = &state.config.email {
+        let article = sqlx::query!("SELECT title FROM articles WHERE id = ?", comment.article)
+            .fetch_one(&mut *conn)
+            .await
+            .into_diagnostic()?;
+        let link = match &state.config.domain {
+            Some(domain) => format!("https://{domain}/article/{url}#{}", comment.id),
+            None => format!("/article/{url}#{}", comment.id),
+        };
+        if let Some(notify) = email_config.notify.clone() {
+            let email = Email::from_template(
+                notify,
+                format!("New comment on \"{}\"", article.title),
+                &CommentNotification {
+                    blog_name: &state.config.blog_name,
+                    article_title: &article.title,
+                    author: &comment.author,
+                    content: &comment.content,
+                    link: &link,
+                },
+            )?;
+            jobs::enqueue(&Job::SendEmail { email }, &mut conn).await?;
+        }
+
+        let origin = match &state.config.domain {
+            Some(domain) => absolute_url(domain, "/"),
+            None => "/".to_string(),
+        };
+        let delay = chrono::Duration::seconds(state.config.comments.notify_delay as i64);
+        let job = Job::NotifySubscribers {
+            comment: comment.id.clone(),
+            except: subscription.clone(),
+            blog_name: state.config.blog_name.clone(),
+            link,
+            unsubscribe: format!("{origin}unsubscribe/"),
+        };
+        jobs::enqueue_at(&job, comment.published + delay, &mut conn).await?;
+
+        if let Some(email) = subscription {
+            if let Some(token) = comment::subscribe(&comment.article, &email, &mut conn).await? {
+                let confirm = format!("{origin}subscribe/{token}");
+                let email = Email::from_template(
+                    email,
+                    format!("Confirm notifications about \"{}\"", article.title),
+                    &SubscriptionConfirmation {
+                        blog_name: &state.config.blog_name,
+                        article_title: &article.title,
+                        confirm: &confirm,
+                    },
+                )?;
+                jobs::enqueue(&Job::SendEmail { email }, &mut conn).await?;
+            }
+        }
+    }
+
+    Ok(Redirect::to("").into_response())
+}
+
+/// Confirms a comment subscription from the link in the confirmation email
+async fn confirm_subscription(
+    Path(token): Path<String>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    if !comment::confirm(&token, &mut conn).await? {
+        return Ok(error::page(&state.config, StatusCode::NOT_FOUND));
+    }
+    Ok(SubscribedPage {
+        config: state.config,
+    }
+    .into_response())
+}
+
+#[derive(Template)]
+#[template(path = "subscribed.html")]
+struct SubscribedPage {
+    config: ServerConfig,
+}
+
+/// Ends a comment subscription from the link in a notification
+async fn unsubscribe(
+    Path(token): Path<String>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    if !comment::unsubscribe(&token, &mut conn).await? {
+        return Ok(error::page(&state.config, StatusCode::NOT_FOUND));
+    }
+    Ok(UnsubscribedPage {
+        config: state.config,
+    }
+    .into_response())
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribed.html")]
+struct UnsubscribedPage {
+    config: ServerConfig,
+}
+
+async fn contact_form(
+    State(state): State<BlogState>,
+    Extension(session): Extension<Session>,
+) -> ContactPage {
+    ContactPage {
+        config: state.config,
+        form: ContactForm::default(),
+        errors: Vec::new(),
+        sent: false,
+        csrf: session.csrf,
+    }
+}
+
+async fn send_contact_message(
+    State(state): State<BlogState>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Extension(session): Extension<Session>,
+    Form(form): Form<ContactForm>,
+) -> Result<AxumResponse, TkError> {
+    // Bots are told that everything went fine so they don't adapt
+    if form.is_spam() {
+        tracing::info!(client = %ip, "discarded contact message caught by the honeypot");
+        return Ok(ContactPage {
+            config: state.config,
+            form: ContactForm::default(),
+            errors: Vec::new(),
+            sent: true,
+            csrf: session.csrf,
+        }
+        .into_response());
+    }
+
+    let mut errors = form.validate();
+    if errors.is_empty() && !state.contact_limiter.check(ip) {
+        errors.push("You have sent too many messages, please try again later.".to_string());
+    }
+    if !errors.is_empty() {
+        return Ok((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ContactPage {
+                config: state.config,
+                form,
+                errors,
+                sent: false,
+                csrf: session.csrf,
+            },
+        )
+            .into_response());
+    }
+
+    let mut conn = state.get_conn().await?;
+    let message = Message::from_form(form);
+
+    sqlx::query!(
+        "INSERT INTO messages ( id, name, email, content, received ) VALUES (?1, ?2, ?3, ?4, ?5)",
+        message.id,
+        message.name,
+        message.email,
+        message.content,
+        message.received
+    )
+    .execute(&mut *conn)
+    .await
+    .into_diagnostic()?;
+
+    if let Some(notify) = state.config.email.as_ref().and_then(|e| e.notify.clone()) {
+        let email = Email::from_template(
+            notify,
+            format!(
+                "Message from {} via {}",
+                message.name, state.config.blog_name
+            ),
+            &ContactNotification {
+                blog_name: &state.config.blog_name,
+                message: &message,
+            },
+        )?;
+        jobs::enqueue(&Job::SendEmail { email }, &mut conn).await?;
+    }
+
+    Ok(ContactPage {
+        config: state.config,
+        form: ContactForm::default(),
+        errors: Vec::new(),
+        sent: true,
+        csrf: session.csrf,
+    }
+    .into_response())
+}
+
+#[derive(Template)]
+#[template(path = "index.html")]
+struct IndexPage {
+    config: ServerConfig,
+    articles: Vec<Article>,
+    /// Comments per article, missing for articles without any
+    comment_counts: HashMap<String, i64>,
+    tags: Vec<TagCount>,
+    popular: Vec<PopularArticle>,
+}
+
+impl IndexPage {
+    fn comment_count(&self, article: &str) -> String {
+        comment::count_label(
+            self.comment_counts
+                .get(article)
+                .copied()
+                .unwrap_or_default(),
+        )
+    }
+}
+
+async fn index(State(state): State<BlogState>) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let articles = sqlx::query_as!(
+        Article,
+        "SELECT * FROM articles WHERE status = 'published' ORDER BY published DESC"
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .into_diagnostic()?;
+    let comment_counts = comment::counts(&mut conn).await?;
+    let tags = if state.config.tags.show_cloud {
+        tag::tag_counts(&state.config.tags, &mut conn).await?
+    } else {
+        Vec::new()
+    };
+    let popular = if state.config.popular.enabled {
+        state.popular.get(&state.config.popular, &mut conn).await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(IndexPage {
+        config: state.config,
+        articles,
+        comment_counts,
+        tags,
+        popular,
+    }
+    .into_response())
+}
+
+#[derive(Template)]
+#[template(path = "tag.html")]
+struct TagPage {
+    config: ServerConfig,
+    tag: String,
+    articles: Vec<Article>,
+}
+
+async fn tagged_articles(
+    Path(tag): Path<String>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let articles = sqlx::query_as!(
+        Article,
+        "SELECT * FROM articles WHERE status = 'published' AND id IN (SELECT article FROM article_tags WHERE tag = ?) ORDER BY published DESC",
+        tag
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .into_diagnostic()?;
+
+    if articles.is_empty() {
+        return Ok(error::page(&state.config, StatusCode::NOT_FOUND));
+    }
+
+    Ok(TagPage {
+        config: state.config,
+        tag,
+        articles,
+    }
+    .into_response())
+}
+
+#[derive(Template)]
+#[template(path = "author.html")]
+struct AuthorPage {
+    config: ServerConfig,
+    author: String,
+    articles: Vec<Article>,
+}
+
+async fn author_articles(
+    Path(slug): Path<String>,
+    State(state): State<BlogState>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let Some(author) = author::all_names(&state.config.author, &mut conn)
+        .await?
+        .into_iter()
+        .find(|name| to_url(name) == slug)
+    else {
+        return Ok(error::page(&state.config, StatusCode::NOT_FOUND));
+    };
+
+    // Articles without explicit authors are credited to the blog's author
+    let is_default = author == state.config.author;
+    let articles = sqlx::query_as!(
+        Article,
+        "SELECT * FROM articles
+        WHERE status = 'published'
+        AND (id IN (SELECT article FROM article_authors WHERE author = ?1)
+        OR (?2 AND id NOT IN (SELECT article FROM article_authors)))
+        ORDER BY published DESC",
+        author,
+        is_default
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .into_diagnostic()?;
+
+    Ok(AuthorPage {
+        config: state.config,
+        author,
+        articles,
+    }
+    .into_response())
+}
+
+/// Answers every public route with the maintenance page while maintenance mode is enabled.
+/// The API and the admin panel stay available so maintenance mode can be turned off again.
+async fn maintenance_mode(
+    State(state): State<BlogState>,
+    request: HttpRequest<Body>,
+    next: Next,
+) -> AxumResponse {
+    let path = request.uri().path();
+    if state.maintenance.load(Ordering::Relaxed) && path != "/api" && !path.starts_with("/admin/") {
+        return (
+            [(header::RETRY_AFTER, "600")],
+            error::maintenance_page(&state.config),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Makes the site context available to the templates rendered while handling the request
+async fn site_context(
+    State(state): State<BlogState>,
+    request: HttpRequest<Body>,
+    next: Next,
+) -> AxumResponse {
+    // These never render a page
+    let path = request.uri().path();
+    if path == "/api" || path.starts_with("/static/") || path.starts_with("/media/") {
+        return next.run(request).await;
+    }
+
+    // The connection is released before the handler needs one
+    let site: miette::Result<Arc<SiteContext>> = async {
+        let mut conn = state.pool.acquire().await.into_diagnostic()?;
+        state.site.get(&state.config.tags, &mut conn).await
+    }
+    .await;
+    let site = site.unwrap_or_else(|e| {
+        tracing::error!("loading the site context failed: {e}");
+        Arc::default()
+    });
+    site::scope(site, next.run(request)).await
+}
+
+/// Describes an article for other sites embedding a link to it
+async fn oembed(
+    State(state): State<BlogState>,
+    Query(query): Query<OEmbedQuery>,
+) -> Result<AxumResponse, TkError> {
+    if query.format.as_deref().is_some_and(|f| f != "json") {
+        return Ok(StatusCode::NOT_IMPLEMENTED.into_response());
+    }
+    let Some(url) = oembed::article_url(&query.url, &state.config) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let url = percent_encoding::percent_decode_str(url)
+        .decode_utf8()
+        .into_diagnostic()?;
+
+    let mut conn = state.get_conn().await?;
+    let Some(article) = find_article_by_url(&url, &mut conn).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let authors = author::authors_for(&article.id, &state.config.author, &mut conn).await?;
+
+    Ok(Json(OEmbed::new(
+        &article,
+        &authors,
+        query.maxwidth,
+        &state.config,
+    ))
+    .into_response())
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+}
+
+async fn search_json(
+    State(state): State<BlogState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let results = search::search(&query.q, false, &mut conn).await?;
+    Ok(Json(results).into_response())
+}
+
+#[derive(Deserialize)]
+struct FeedQuery {
+    /// Only include articles in this language
+    lang: Option<String>,
+}
+
+async fn rss_feed(
+    State(state): State<BlogState>,
+    Query(query): Query<FeedQuery>,
+    headers: HeaderMap,
+) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let version = feed::version(query.lang.as_deref(), &mut conn).await?;
+
+    let changed = Utc.from_utc_datetime(&version.changed);
+    let etag = format!("\"{}\"", version.version);
+    // Only the exact validators we sent are current, an earlier or later date may be from
+    // before a deletion
+    let unchanged = match headers.get(header::IF_NONE_MATCH) {
+        Some(tags) => tags
+            .to_str()
+            .is_ok_and(|tags| tags.split(',').any(|tag| tag.trim() == etag)),
+        None => headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|since| since.to_str().ok())
+            .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+            // HTTP dates only have a resolution of seconds
+            .is_some_and(|since| since.timestamp() == changed.timestamp()),
+    };
+    let validators = [
+        (
+            header::LAST_MODIFIED,
+            changed.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+        ),
+        (header::ETAG, etag),
+    ];
+    if unchanged {
+        return Ok((StatusCode::NOT_MODIFIED, validators).into_response());
+    }
+
+    let xml = match state.feeds.get(query.lang.as_deref(), version) {
+        Some(xml) => xml,
+        None => {
+            let xml = render_feed(query.lang.clone(), &state.config, &mut conn).await?;
+            // Languages without articles aren't cached, so made up ones can't fill the cache
+            if query.lang.is_none() || version.count > 0 {
+                state.feeds.insert(query.lang, version, xml.clone());
+            }
+            xml
+        }
+    };
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/rss+xml")],
+        validators,
+        xml,
+    )
+        .into_response())
+}
+
+/// The feed of significant updates to published articles
+async fn changes_feed(State(state): State<BlogState>) -> Result<AxumResponse, TkError> {
+    let mut conn = state.get_conn().await?;
+    let changes = changes::recent(&mut conn).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/rss+xml")],
+        changes::feed(changes, &state.config),
+    )
+        .into_response())
+}
+
+/// Generates the RSS feed of all published articles, or only those in a language
+async fn render_feed(
+    lang: Option<String>,
+    config: &ServerConfig,
+    conn: &mut SqliteConnection,
+) -> miette::Result<String> {
+    let articles = sqlx::query_as!(
+        Article,
+        "SELECT * FROM articles WHERE status = 'published' AND (?1 IS NULL OR lang = ?1) ORDER BY published DESC",
+        lang
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .into_diagnostic()?;
+
+    let mut authors = author::all_authors(&mut *conn).await?;
+    let mut tags = tag::all_tags(conn).await?;
+    let image = config.logo.as_ref().map(|logo| {
+        let url = match &config.domain {
+            Some(domain) => absolute_url(domain, logo),
+            None => logo.clone(),
+        };
+        ImageBuilder::default()
+            .url(url)
+            .title(config.blog_name.clone())
+            .link(
+                config
+                    .domain
+                    .as_ref()
+                    .map(|d| absolute_url(d, "/"))
+                    .unwrap_or_default(),
+            )
+            .build()
+    });
+    let channel = ChannelBuilder::default()
+        .title(config.blog_name.clone())
+        .link(
+            config
+                .domain
+                .as_ref()
+                .map(|d| absolute_url(d, "/"))
+                .unwrap_or_default(),
+        )
+        .description(config.description.clone())
+        .language(lang)
+        .image(image)
+        .namespaces(BTreeMap::from([(
+            "atom".to_string(),
+            "http://www.w3.org/2005/Atom".to_string(),
+        )]))
+        .items(
+            articles
+                .into_iter()
+                .map(|article| {
+                    let authors = authors
+                        .remove(&article.id)
+                        .unwrap_or_else(|| vec![config.author.clone()]);
+                    let tags = tags.remove(&article.id).unwrap_or_default();
+                    article.into_item(authors, tags, config)
+                })
+                .collect_vec(),
+        )
+        .build();
+
+    Ok(channel.to_string())
+}
+
+async fn sitemap_xml(State(state): State<BlogState>) -> Result<AxumResponse, TkError> {
+    let Some(domain) = &state.config.domain else {
+        return Ok(error::page(&state.config, StatusCode::NOT_FOUND));
+    };
+
+    let mut conn = state.get_conn().await?;
+    let articles = sqlx::query_as!(
+        Article,
+        "SELECT * FROM articles WHERE status = 'published' AND NOT noindex ORDER BY published DESC"
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .into_diagnostic()?;
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/xml")],
+        sitemap::sitemap(domain, &articles),
+    )
+        .into_response())
+}
+
+async fn robots_txt(State(state): State<BlogState>) -> AxumResponse {
+    let robots = &state.config.robots;
+    let mut txt = String::new();
+    for agent in &robots.blocked_agents {
+        txt.push_str(&format!("User-agent: {agent}\nDisallow: /\n\n"));
+    }
+    txt.push_str("User-agent: *\n");
+    for path in &robots.disallow {
+        txt.push_str(&format!("Disallow: {path}\n"));
+    }
+    if let Some(domain) = &state.config.domain {
+        txt.push_str(&format!(
+            "\nSitemap: {}\n",
+            absolute_url(domain, "/sitemap.xml")
+        ));
+    }
+
+    ([(header::CONTENT_TYPE, "text/plain")], txt).into_response()
+}
+
+async fn favicon(State(state): State<BlogState>) -> AxumResponse {
+    match state.config.icons.favicon() {
+        Some(ico) => (
+            [
+                (header::CONTENT_TYPE, "image/x-icon"),
+                (header::CACHE_CONTROL, "public, max-age=86400"),
+            ],
+            ico.to_vec(),
+        )
+            .into_response(),
+        None => error::page(&state.config, StatusCode::NOT_FOUND),
+    }
+}
+
+/// Serves the generated touch icons as `/icons/<size>.png`
+async fn touch_icon(Path(file): Path<String>, State(state): State<BlogState>) -> AxumResponse {
+    let icon = file
+        .strip_suffix(".png")
+        .and_then(|size| size.parse().ok())
+        .and_then(|size| state.config.icons.touch(size));
+    match icon {
+        Some(png) => (
+            [
+                (header::CONTENT_TYPE, "image/png"),
+                (header::CACHE_CONTROL, "public, max-age=86400"),
+            ],
+            png.to_vec(),
+        )
+            .into_response(),
+        None => error::page(&state.config, StatusCode::NOT_FOUND),
+    }
+}
+
+async fn web_manifest(State(state): State<BlogState>) -> AxumResponse {
+    (
+        [(header::CONTENT_TYPE, "application/manifest+json")],
+        state.config.icons.manifest(&state.config).to_string(),
+    )
+        .into_response()
+}
+
+pub async fn serve(mut config: ServerConfig) -> miette::Result<()> {
+    config.assets = Arc::new(Assets::load("static")?);
+    config.plugin_runtime = Arc::new(Plugins::load(&config.plugins)?);
+    config.access_logger = Arc::new(AccessLog::open(config.access_log.as_ref())?);
+    config.error_pages = Arc::new(ErrorPages::load(config.theme.as_deref())?);
+    config.icons = Arc::new(Icons::load(config.icon.as_deref())?);
+    let db = &config.database;
+    let options = SqliteConnectOptions::from_str("sqlite://articles.db")
+        .into_diagnostic()?
+        .journal_mode(SqliteJournalMode::from_str(&db.journal_mode).into_diagnostic()?)
+        .synchronous(SqliteSynchronous::from_str(&db.synchronous).into_diagnostic()?)
+        .busy_timeout(Duration::from_millis(db.busy_timeout))
+        .pragma("wal_autocheckpoint", db.wal_autocheckpoint.to_string());
+    let pool = SqlitePoolOptions::new()
+        .max_connections(db.max_connections)
+        .acquire_timeout(Duration::from_secs(db.acquire_timeout))
+        .connect_with(options)
+        .await
+        .into_diagnostic()?;
+    let maintenance = sqlx::query!("SELECT value FROM settings WHERE key = 'maintenance'")
+        .fetch_optional(&pool)
+        .await
+        .into_diagnostic()?
+        .is_some_and(|r| r.value == "true");
+    if maintenance {
+        tracing::warn!("maintenance mode is enabled");
+    }
+
+    let mailer = config.email.as_ref().map(Mailer::new).transpose()?;
+    tokio::spawn(jobs::worker(
+        pool.clone(),
+        JobContext {
+            client: reqwest::Client::new(),
+            mailer,
+            pool: pool.clone(),
+        },
+    ));
+    tokio::spawn(retention::run(pool.clone(), config.retention.clone()));
+    if let Some(backup) = config.backup.clone() {
+        tokio::spawn(backup::run(pool.clone(), backup));
+    }
+    tokio::spawn(scheduler::run(
+        pool.clone(),
+        config.database.maintain_interval,
+    ));
+    tokio::spawn(db::run_checkpoints(
+        pool.clone(),
+        config.database.checkpoint_interval,
+        config.database.checkpoint_mode,
+    ));
+    tokio::spawn(ingest::run(pool.clone(), config.ingest.clone()));
+
+    if let Some(gemini_config) = config.gemini.clone() {
+        let (pool, config) = (pool.clone(), config.clone());
+        tokio::spawn(async move {
+            if let Err(e) = gemini::serve(pool, config, gemini_config).await {
+                tracing::error!("gemini server stopped: {e}");
+            }
+        });
+    }
+
+    let mut fonts = fontdb::Database::new();
+    fonts.load_system_fonts();
+
+    let state = BlogState::new(pool, config.clone(), maintenance, fonts);
+    if let Some(admin_addr) = config.admin_addr {
+        let admin = router(state.clone(), true).into_make_service_with_connect_info::<SocketAddr>();
+        let listener = TcpListener::bind(admin_addr).await.into_diagnostic()?;
+        tracing::info!("serving the API and admin panel on {admin_addr}");
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, admin).await {
+                tracing::error!("admin server stopped: {e}");
+            }
+        });
+    }
+
+    let router = router(state, config.admin_addr.is_none());
+    let service = router.into_make_service_with_connect_info::<SocketAddr>();
+    if let Some(acme) = &config.acme {
+        let domain = config
+            .domain
+            .clone()
+            .ok_or(miette::miette!("automatic certificates require a domain"))?;
+        let mut acme_state = rustls_acme::AcmeConfig::new([domain])
+            .contact(acme.contact.iter().map(|c| format!("mailto:{c}")))
+            .cache(DirCache::new(acme.cache_dir.clone()))
+            .directory_lets_encrypt(!acme.staging)
+            .state();
+        let acceptor = acme_state.axum_acceptor(acme_state.default_rustls_config());
+        tokio::spawn(async move {
+            while let Some(event) = acme_state.next().await {
+                match event {
+                    Ok(event) => tracing::info!("acme: {event:?}"),
+                    Err(e) => tracing::error!("acme: {e}"),
+                }
+            }
+        });
+
+        tracing::info!("serving {} on {} with TLS", config.blog_name, config.addr);
+        axum_server::bind(config.addr)
+            .acceptor(acceptor)
+            .serve(service)
+            .await
+            .into_diagnostic()?;
+        return Ok(());
+    }
+
+    let listener = TcpListener::bind(&config.addr).await.into_diagnostic()?;
+    tracing::info!("serving {} on {}", config.blog_name, config.addr);
+    axum::serve(listener, service).await.into_diagnostic()?;
+    Ok(())
+}
+
+/// Builds the blog's routes on top of the given state. Without `with_admin`, the API
+/// and admin panel are left out, for when they are served on their own address.
+fn router(state: BlogState, with_admin: bool) -> Router {
+    let config = state.config.clone();
+    let limits = &config.limits;
+    // Uploads need more time and space than anything else
+    let api = Router::new()
+        .route(
+            "/api",
+            post(handle_api_request)
+                .layer(DefaultBodyLimit::max(config.api.max_body_size))
+                .layer(middleware::from_fn_with_state(
+                    config.clone(),
+                    net::api_allow_list,
+                )),
+        )
+        .layer(TimeoutLayer::new(Duration::from_secs(limits.api_timeout)));
+    let admin = Router::new()
+        .route("/admin/login", get(login_form).post(login))
+        .route("/admin/login/code", post(login_code))
+        .route("/admin/logout", post(logout))
+        .nest(
+            "/admin",
+            Router::new()
+                .route("/media", get(media_library))
+                .route("/media/:id/delete", post(delete_media))
+                .route("/comments", get(flagged_comments))
+                .route("/comments/:id/keep", post(keep_comment))
+                .route("/comments/:id/delete", post(delete_comment))
+                .route("/comments/:id/ban", post(ban_comment_author))
+                .route("/bans", get(bans_page).post(add_ban))
+                .route("/bans/:id/delete", post(remove_ban))
+                .route("/articles/:id/history", get(article_history_admin))
+                .route(
+                    "/secrets",
+                    get(list_secrets_admin).post(create_secret_admin),
+                )
+                .route("/secrets/:id", post(update_secret_admin))
+                .route("/secrets/:id/revoke", post(revoke_secret_admin))
+                .route("/two-factor", get(show_two_factor))
+                .route("/two-factor/setup", post(setup_two_factor))
+                .route("/two-factor/confirm", post(confirm_two_factor))
+                .route(
+                    "/two-factor/recovery-codes",
+                    post(regenerate_recovery_codes),
+                )
+                .route("/two-factor/disable", post(disable_two_factor))
+                .layer(middleware::from_fn(require_admin)),
+        );
+
+    let mut site = Router::new()
+        .nest_service(
+            "/static",
+            get_service(ServeDir::new("static").not_found_service(ServeFile::new("/404.html")))
+                .layer(middleware::from_fn_with_state(
+                    config.assets.clone(),
+                    assets::serve_fingerprinted,
+                )),
+        )
+        .route("/", get(index))
+        .route("/article/:id", get(get_article))
+        .route("/article/:id", post(post_comment))
+        .route("/comment/:id/flag", post(flag_comment))
+        .route("/article/:id/og.png", get(og_image))
+        .route("/article/:id/print", get(print_article))
+        .route("/article/:id/epub", get(export_article))
+        .route("/article/:id/markdown", get(article_markdown))
+        .route("/article/:id/history", get(article_history))
+        .route("/preview/:token", get(preview))
+        .route("/tag/:tag", get(tagged_articles))
+        .route("/author/:author", get(author_articles))
+        .route("/rss", get(rss_feed))
+        .route("/changes.xml", get(changes_feed))
+        .route("/oembed", get(oembed))
+        .route("/search.json", get(search_json))
+        .route("/xmlrpc", post(pingback))
+        .route("/sitemap.xml", get(sitemap_xml))
+        .route("/robots.txt", get(robots_txt))
+        .route("/favicon.ico", get(favicon))
+        .route("/icons/:file", get(touch_icon))
+        .route("/site.webmanifest", get(web_manifest))
+        .route(
+            "/color-scheme/:scheme",
+            get(appearance::choose_color_scheme),
+        )
+        .route("/contact", get(contact_form).post(send_contact_message))
+        .route("/subscribe/:token", get(confirm_subscription))
+        .route("/unsubscribe/:token", get(unsubscribe))
+        .route("/media/:id/:filename", get(get_media));
+    if with_admin {
+        site = site.merge(admin);
+    }
+    let mut router = site
+        .layer(RequestBodyLimitLayer::new(limits.max_form_size))
+        .layer(TimeoutLayer::new(Duration::from_secs(
+            limits.request_timeout,
+        )));
+    if with_admin {
+        router = router.merge(api);
+    }
+
+    router
+        .fallback(get(|State(state): State<BlogState>, uri: Uri| async move {
+            let path = percent_encoding::percent_decode_str(uri.path()).decode_utf8_lossy();
+            not_found(state, &path).await
+        }))
+        .layer(middleware::from_fn_with_state(state.clone(), sessions))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            maintenance_mode,
+        ))
+        .layer(middleware::from_fn_with_state(
+            config.clone(),
+            error::themed_errors,
+        ))
+        .layer(middleware::from_fn_with_state(
+            config.clone(),
+            appearance::color_scheme,
+        ))
+        .layer(middleware::from_fn_with_state(state.clone(), site_context))
+        .layer(middleware::from_fn_with_state(
+            config.clone(),
+            net::deny_list,
+        ))
+        .layer(middleware::from_fn_with_state(
+            config.clone(),
+            accesslog::record,
+        ))
+        .layer(middleware::from_fn_with_state(
+            config.clone(),
+            net::resolve_client_ip,
+        ))
+        .with_state(state)
+}
+
+/// The server against an in-memory database with all migrations applied, for tests
+#[cfg(test)]
+pub(crate) async fn test_app(config: ServerConfig) -> (Router, Pool<Sqlite>) {
+    // Every connection to an in-memory database gets its own database, so keep exactly one
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!().run(&pool).await.unwrap();
+
+    let state = BlogState::new(pool.clone(), config, false, fontdb::Database::new());
+    (router(state, true), pool)
+}
+
+/// Opens a single connection to the database for command line operations
+pub async fn connect() -> miette::Result<SqliteConnection> {
+    SqliteConnectOptions::from_str("sqlite://articles.db")
+        .into_diagnostic()?
+        .connect()
+        .await
+        .into_diagnostic()
+}
+
+pub async fn create_secret(
+    description: Option<String>,
+    hmac: bool,
+    scope: Scope,
+    expires: Option<NaiveDate>,
+) -> miette::Result<()> {
+    let mut conn = connect().await?;
+    let expires_at = expires.map(|date| date.and_time(NaiveTime::MIN));
+    let (id, secret) = secret::create(description, hmac, scope, expires_at, &mut conn).await?;
+
+    println!("Your client secret (ID {id}) is:");
+    println!("{secret}");
+    println!("Please note that you will *not* be able to see it again.");
+    Ok(())
+}
+
+/// Revokes every secret and issues a new `publish` scoped one in the same transaction,
+/// so there is no moment without a working secret or with an old one still valid
+/// Revokes every secret and logs out every session, which may have been opened with one
+/// of them. Returns how many secrets there were and a new publishing secret.
+async fn purge(conn: &mut SqliteConnection) -> miette::Result<(u64, (i64, String))> {
+    let mut tx = conn.begin().await.into_diagnostic()?;
+    let revoked = secret::revoke_all(&mut tx).await?;
+    session::rotate_key(&mut tx).await?;
+    let created = secret::create(
+        Some("Issued by secret purge".to_string()),
+        false,
+        Scope::Publish,
+        None,
+        &mut tx,
+    )
+    .await?;
+    tx.commit().await.into_diagnostic()?;
+    Ok((revoked, created))
+}
+
+pub async fn purge_secrets(confirm: bool) -> miette::Result<()> {
+    if !confirm {
+        return Err(miette::miette!(
+            "this revokes every secret, including the ones of guests and other clients. Pass --confirm to do it anyway."
+        ));
+    }
+    let mut conn = connect().await?;
+    let (revoked, (id, secret)) = purge(&mut conn).await?;
+
+    println!("Revoked {revoked} secrets and logged out every admin session. Preview links stop working, too.");
+    println!("Your new client secret (ID {id}) is:");
+    println!("{secret}");
+    println!("Please note that you will *not* be able to see it again.");
+    Ok(())
+}
+
+pub async fn list_secrets() -> miette::Result<()> {
+    let mut conn = connect().await?;
+    let secrets = secret::list(&mut conn).await?;
+
+    let mut table = Table::new();
+    table.set_header(Row::from(vec![
+        "ID",
+        "Description",
+        "Auth",
+        "Scope",
+        "Last Used",
+        "Expires",
+    ]));
+    for row in secrets {
+        let expires = match row.expires_at {
+            Some(_) if row.is_expired() => format!("{} (expired)", row.expires()),
+            Some(_) => row.expires(),
+            None => "never".to_string(),
+        };
+        table.add_row([
+            &row.id.to_string(),
+            row.description.as_deref().unwrap_or("-"),
+            &row.auth_mode,
+            &row.scope,
+            &row.last_used(),
+            &expires,
+        ]);
+    }
+    println!("{table}");
+
+    Ok(())
+}
+
+/// Revokes a secret and, if asked to, deletes every article created with it, e.g. after
+/// it leaked
+pub async fn revoke_secret(id: i64, yank_articles: bool) -> miette::Result<()> {
+    let mut conn = connect().await?;
+    let mut tx = conn.begin().await.into_diagnostic()?;
+    secret::revoke(id, &mut tx).await?;
+    session::rotate_key(&mut tx).await?;
+
+    if yank_articles {
+        let articles = sqlx::query!("SELECT id FROM articles WHERE created_by = ?", id)
+            .fetch_all(&mut *tx)
+            .await
+            .into_diagnostic()?;
+        for article in &articles {
+            delete_article(&article.id, &mut tx).await?;
+        }
+        println!(
+            "Yanked {} articles created with secret {id}",
+            articles.len()
+        );
+    }
+    tx.commit().await.into_diagnostic()?;
+    println!("Revoked secret {id} and logged out every admin session");
+
+    Ok(())
+}
+
+/// Returns the ID and scope of the matching secret, if any
+async fn is_secret_valid(
+    secret: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Option<(i64, Scope)>> {
+    let now = Utc::now().naive_utc();
+    let secrets = sqlx::query!(
+        "SELECT id, secret, scope FROM secrets WHERE auth_mode = 'bearer' AND (expires_at IS NULL OR expires_at > ?)",
+        now
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .into_diagnostic()?;
+
+    // Compare against every secret so the time taken does not depend on which one matches
+    let mut matched = None;
+    for row in secrets {
+        if auth::secrets_equal(&row.secret, secret) {
+            matched = Some((row.id, row.scope));
+        }
+    }
+
+    let Some((id, scope)) = matched else {
+        return Ok(None);
+    };
+    mark_secret_used(id, conn).await?;
+    Ok(Some((id, Scope::from_db(&scope))))
+}
+
+/// Checks an HMAC signature over the request body. Each signature is only accepted once
+/// within the allowed clock skew to prevent replaying captured requests. Returns the ID
+/// and scope of the signing secret.
+async fn is_signature_valid(
+    signature: &Signature,
+    body: &[u8],
+    seen_signatures: &Mutex<HashMap<String, i64>>,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Option<(i64, Scope)>> {
+    let now = Utc::now().timestamp();
+    if (now - signature.timestamp).abs() > auth::MAX_CLOCK_SKEW {
+        return Ok(None);
+    }
+
+    let checked_at = Utc::now().naive_utc();
+    let Some(row) = sqlx::query!(
+        "SELECT secret, scope FROM secrets WHERE id = ? AND auth_mode = 'hmac' AND (expires_at IS NULL OR expires_at > ?)",
+        signature.key_id,
+        checked_at
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .into_diagnostic()?
+    else {
+        return Ok(None);
+    };
+
+    if !auth::verify(&row.secret, signature.timestamp, body, &signature.signature) {
+        return Ok(None);
+    }
+
+    {
+        let mut seen = seen_signatures.lock().unwrap();
+        // Hex decodes either case, so the lowercase form is what identifies a signature
+        seen.retain(|_, timestamp| (now - *timestamp).abs() <= auth::MAX_CLOCK_SKEW);
+        if seen
+            .insert(
+                signature.signature.to_ascii_lowercase(),
+                signature.timestamp,
+            )
+            .is_some()
+        {
+            return Ok(None);
+        }
+    }
+
+    mark_secret_used(signature.key_id, conn).await?;
+    Ok(Some((signature.key_id, Scope::from_db(&row.scope))))
+}
+
+async fn mark_secret_used(id: i64, conn: &mut SqliteConnection) -> miette::Result<()> {
+    let now = Utc::now().naive_utc();
+    sqlx::query!("UPDATE secrets SET last_used_at = ? WHERE id = ?", now, id)
+        .execute(conn)
+        .await
+        .into_diagnostic()?;
+    Ok(())
+}
+
+pub async fn list_submissions() -> miette::Result<()> {
+    let mut conn = connect().await?;
+
+    let submissions = sqlx::query_as!(
+        Article,
+        "SELECT * FROM articles WHERE status = 'submitted' ORDER BY published"
+    )
+    .fetch_all(&mut conn)
+    .await
+    .into_diagnostic()?;
+
+    let mut table = Table::new();
+    table.set_header(Row::from(vec!["ID", "Title", "Authors", "Submitted"]));
+    for article in submissions {
+        let authors = author::authors_for(&article.id, "-", &mut conn).await?;
+        table.add_row([
+            &article.id,
+            &article.title,
+            &authors.join(", "),
+            &article.published(),
+        ]);
+    }
+    println!("{table}");
+
+    Ok(())
+}
+
+/// Publishes a guest submission as if it had just been created
+pub async fn approve_submission(id: String, config: &ServerConfig) -> miette::Result<()> {
+    let mut conn = connect().await?;
+    let config = ServerConfig {
+        plugin_runtime: Arc::new(Plugins::load(&config.plugins)?),
+        ..config.clone()
+    };
+
+    let now = Utc::now().naive_utc();
+    let approved = sqlx::query!(
+        "UPDATE articles SET status = 'published', published = ? WHERE id = ? AND status = 'submitted'",
+        now,
+        id
+    )
+    .execute(&mut conn)
+    .await
+    .into_diagnostic()?
+    .rows_affected();
+    if approved == 0 {
+        return Err(miette::miette!("No submission with ID {id} found"));
+    }
+
+    let article = sqlx::query_as!(Article, "SELECT * FROM articles WHERE id = ?", id)
+        .fetch_one(&mut conn)
+        .await
+        .into_diagnostic()?;
+    announce_article(&article, &config, &mut conn).await?;
+
+    Ok(())
+}
+
+pub async fn reject_submission(id: String) -> miette::Result<()> {
+    let mut conn = connect().await?;
+
+    let submitted = sqlx::query!(
+        "SELECT id FROM articles WHERE id = ? AND status = 'submitted'",
+        id
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .into_diagnostic()?;
+    if submitted.is_none() {
+        return Err(miette::miette!("No submission with ID {id} found"));
+    }
+    delete_article(&id, &mut conn).await?;
+
+    Ok(())
+}
+
+/// Stores a new article with its tags and authors, answering with its ID,
+/// [`Response::SlugTaken`], [`Response::Invalid`] or an error. Articles created with a `submit`
+/// scoped secret are held back for approval instead of being announced. `secret_id` is
+/// recorded as the article's creator.
+async fn create_article(
+    new: NewArticle,
+    scope: Scope,
+    secret_id: i64,
+    config: &ServerConfig,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Response> {
+    let NewArticle {
+        title,
+        content,
+        idempotency_key,
+        force_slug,
+        mut fields,
+    } = new;
+
+    if let Some(key) = &idempotency_key {
+        let existing = sqlx::query!(
+            "SELECT article FROM idempotency_keys WHERE secret = ? AND key = ? AND article IN (SELECT id FROM articles)",
+            secret_id,
+            key
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .into_diagnostic()?;
+        if let Some(existing) = existing {
+            return Ok(Response::ArticleId(existing.article));
+        }
+    }
+
+    let mut article = Article::new(title, content);
+    let errors = config.validation.check(&article);
+    if !errors.is_empty() {
+        return Ok(Response::Invalid(errors));
+    }
+    let url = article.url();
+    if let Some(existing) = article_at(&url, conn).await? {
+        if !force_slug {
+            return Ok(Response::SlugTaken { url, existing });
+        }
+        let mut n = 2;
+        while article_at(&format!("{url}_{n}"), conn).await?.is_some() {
+            n += 1;
+        }
+        article.slug = Some(format!("{url}_{n}"));
+    }
+    if let Some(error) = resolve_translation(&mut fields, conn).await? {
+        return Ok(error);
+    }
+    if scope == Scope::Submit {
+        // Only authors with a publishing secret are trusted with their own HTML policy
+        fields.html = None;
+    }
+    article.apply(&fields);
+    article.created_by = Some(secret_id);
+    if scope == Scope::Submit {
+        article.status = SUBMITTED.to_string();
+    } else if fields.draft == Some(true) {
+        article.status = DRAFT.to_string();
+    }
+
+    insert_article(&article, conn).await?;
+    if let Some(tags) = &fields.tags {
+        tag::set_tags(&article.id, tags, conn).await?;
+    }
+    if let Some(authors) = &fields.authors {
+        author::set_authors(&article.id, authors, conn).await?;
+    }
+    if let Some(aliases) = &fields.aliases {
+        alias::set_aliases(&article.id, aliases, conn).await?;
+    }
+    if let Some(key) = &idempotency_key {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO idempotency_keys ( secret, key, article, created ) VALUES (?1, ?2, ?3, ?4)",
+            secret_id,
+            key,
+            article.id,
+            article.published
+        )
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    }
+
+    if article.status == PUBLISHED {
+        announce_article(&article, config, conn).await?;
+    } else if article.status == SUBMITTED {
+        tracing::info!(id = %article.id, "received a guest submission");
+    }
+
+    Ok(Response::ArticleId(article.id))
+}
+
+/// The ID of the article at a URL, whether it is published or not
+async fn article_at(url: &str, conn: &mut SqliteConnection) -> miette::Result<Option<String>> {
+    Ok(sqlx::query!("SELECT id, title, slug FROM articles")
+        .fetch_all(conn)
+        .await
+        .into_diagnostic()?
+        .into_iter()
+        .find_map(|r| (url_of(&r.title, r.slug.as_deref()) == url).then_some(r.id)))
+}
+
+/// Notifies the configured webhooks and the plugins about a newly published article and
+/// has it archived if configured
+async fn announce_article(
+    article: &Article,
+    config: &ServerConfig,
+    conn: &mut SqliteConnection,
+) -> miette::Result<()> {
+    for url in &config.webhooks {
+        let job = Job::Webhook {
+            url: url.clone(),
+            payload: json!({
+                "event": "article.published",
+                "id": article.id,
+                "title": article.title,
+                "url": format!("/article/{}", article.url()),
+                "content_warning": article.content_warning,
+            }),
+        };
+        jobs::enqueue(&job, conn).await?;
+    }
+    for job in config.plugin_runtime.on_publish(article) {
+        jobs::enqueue(&job, conn).await?;
+    }
+    if config.archive {
+        match &config.domain {
+            Some(domain) => {
+                let job = Job::ArchiveSnapshot {
+                    article: article.id.clone(),
+                    url: absolute_url(domain, &format!("/article/{}", article.url())),
+                };
+                jobs::enqueue(&job, conn).await?;
+            }
+            None => tracing::warn!("can't archive articles without a configured domain"),
+        }
+    }
+    Ok(())
+}
+
+/// Looks up the article whose title maps to the given URL.
+/// Shared by the API and the public article page so both resolve URLs identically.
+pub(crate) async fn find_article_by_url(
+    url: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Option<Article>> {
+    let titles = sqlx::query!("SELECT id, title, slug FROM articles WHERE status = 'published'")
+        .fetch_all(&mut *conn)
+        .await
+        .into_diagnostic()?;
+
+    let Some(id) = titles
+        .into_iter()
+        .find_map(|r| (url_of(&r.title, r.slug.as_deref()) == url).then_some(r.id))
+    else {
+        return Ok(None);
+    };
+
+    sqlx::query_as!(Article, "SELECT * FROM articles WHERE id = ?", id)
+        .fetch_optional(conn)
+        .await
+        .into_diagnostic()
+}
+
+/// Deletes an article along with everything that refers to it
+async fn delete_article(id: &str, conn: &mut SqliteConnection) -> miette::Result<()> {
+    tag::set_tags(id, &[], conn).await?;
+    author::set_authors(id, &[], conn).await?;
+    alias::set_aliases(id, &[], conn).await?;
+    sqlx::query!("DELETE FROM idempotency_keys WHERE article = ?", id)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    sqlx::query!("DELETE FROM views WHERE article = ?", id)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    sqlx::query!("DELETE FROM mentions WHERE article = ?", id)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    sqlx::query!("DELETE FROM archive_snapshots WHERE article = ?", id)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    sqlx::query!("DELETE FROM comment_subscriptions WHERE article = ?", id)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    sqlx::query!(
+        "DELETE FROM comment_flags WHERE comment IN (SELECT id FROM comments WHERE article = ?)",
+        id
+    )
+    .execute(&mut *conn)
+    .await
+    .into_diagnostic()?;
+    sqlx::query!("DELETE FROM comments WHERE article = ?", id)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    sqlx::query!("DELETE FROM article_changes WHERE article = ?", id)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    sqlx::query!("DELETE FROM article_revisions WHERE article = ?", id)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    // Translations reference their original, so the oldest one takes its place
+    let heir = sqlx::query!(
+        "SELECT id FROM articles WHERE translation_of = ? ORDER BY published, rowid LIMIT 1",
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .into_diagnostic()?;
+    if let Some(heir) = heir {
+        sqlx::query!(
+            "UPDATE articles SET translation_of = CASE WHEN id = ?1 THEN NULL ELSE ?1 END WHERE translation_of = ?2",
+            heir.id,
+            id
+        )
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    }
+    sqlx::query!("DELETE FROM articles WHERE id = ?", id)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    Ok(())
+}
+
+pub(crate) async fn insert_article(
+    article: &Article,
+    conn: &mut SqliteConnection,
+) -> miette::Result<()> {
+    sqlx::query!(
+        "INSERT INTO articles ( id, title, content, published, canonical_url, lang, translation_of, content_warning, license, status, noindex, slug, summary, expires_at, comments_enabled, created_by, html ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+        article.id,
+        article.title,
+        article.content,
+        article.published,
+        article.canonical_url,
+        article.lang,
+        article.translation_of,
+        article.content_warning,
+        article.license,
+        article.status,
+        article.noindex,
+        article.slug,
+        article.summary,
+        article.expires_at,
+        article.comments_enabled,
+        article.created_by,
+        article.html
+    )
+    .execute(&mut *conn)
+    .await
+    .into_diagnostic()?;
+    revision::record(article, None, conn).await
+}
+
+/// Writes all editable fields of an existing article back to the database
+async fn save_article(article: &Article, conn: &mut SqliteConnection) -> miette::Result<()> {
+    sqlx::query!(
+        "UPDATE articles SET title = ?, content = ?, canonical_url = ?, lang = ?, translation_of = ?, content_warning = ?, license = ?, summary = ?, expires_at = ?, comments_enabled = ?, updated = ?, noindex = ?, status = ?, published = ?, html = ? WHERE id = ?",
+        article.title,
+        article.content,
+        article.canonical_url,
+        article.lang,
+        article.translation_of,
+        article.content_warning,
+        article.license,
+        article.summary,
+        article.expires_at,
+        article.comments_enabled,
+        article.updated,
+        article.noindex,
+        article.status,
+        article.published,
+        article.html,
+        article.id
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?;
+    Ok(())
+}
+
+/// Points `translation_of` at the original article, even if it was given as another
+/// translation, so all translations of an article form a single group. Returns the error
+/// for the client if there is no such article.
+async fn resolve_translation(
+    fields: &mut ArticleFields,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Option<Response>> {
+    let Some(id) = fields.translation_of.as_ref().filter(|id| !id.is_empty()) else {
+        return Ok(None);
+    };
+
+    let Some(original) = sqlx::query!("SELECT id, translation_of FROM articles WHERE id = ?", id)
+        .fetch_optional(conn)
+        .await
+        .into_diagnostic()?
+    else {
+        return Ok(Some(Response::Error(format!(
+            "Can't translate {id}: no article with id {id} found"
+        ))));
+    };
+
+    fields.translation_of = Some(original.translation_of.unwrap_or(original.id));
+    Ok(None)
+}
+
+/// The answer to an API request about an article that doesn't exist
+fn no_article(id: &str) -> AxumResponse {
+    (
+        StatusCode::NOT_FOUND,
+        Json(Response::Error(format!("No article with id {id} found"))),
+    )
+        .into_response()
+}
+
+/// All other language versions of an article
+async fn translations_of(
+    article: &Article,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Vec<Translation>> {
+    let group = article.translation_of.as_ref().unwrap_or(&article.id);
+
+    Ok(sqlx::query!(
+        "SELECT title, slug, lang FROM articles WHERE (id = ?1 OR translation_of = ?1) AND id != ?2 AND lang IS NOT NULL AND status = 'published'",
+        group,
+        article.id
+    )
+    .fetch_all(conn)
+    .await
+    .into_diagnostic()?
+    .into_iter()
+    .filter_map(|r| {
+        Some(Translation {
+            title: r.title,
+            slug: r.slug,
+            lang: r.lang?,
+        })
+    })
+    .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Request as HttpRequest, StatusCode},
+    };
+    use chrono::{Duration, Utc};
+    use sqlx::{Pool, Sqlite};
+    use std::{
+        collections::HashMap,
+        net::IpAddr,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::{
+        accesslog::AccessLog,
+        archive,
+        article::DRAFT,
+        attachment::{self, Attachment},
+        auth::{self, Lockout, Scope, Signature},
+        author, backup, comment, db,
+        error::ErrorPages,
+        frontmatter,
+        html::HtmlPolicy,
+        icons::Icons,
+        ingest,
+        jobs::{Job, JobContext},
+        media,
+        mention::{self, Fault},
+        net,
+        plugin::Plugins,
+        request::{
+            ArticleFields, ArticleFilter, ArticleSort, InnerRequest, NewArticle, Request, Response,
+        },
+        retention, scheduler, secret,
+        site::SiteCache,
+        tag,
+        testing::{self, GUEST_SECRET, SECRET},
+        totp, AccessLogConfig, BackupConfig, RetentionConfig,
+    };
+
+    fn create(title: &str) -> InnerRequest {
+        InnerRequest::CreateArticle {
+            title: title.to_string(),
+            content: "Some *content*".to_string(),
+            idempotency_key: None,
+            force_slug: false,
+            fields: ArticleFields::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn index_lists_articles() {
+        let (app, pool) = testing::test_app().await;
+        testing::article(&pool, "Hello World", "First post").await;
+
+        let (status, body) = testing::get(&app, "/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("Hello World"));
+    }
+
+    #[tokio::test]
+    async fn index_shows_teaser_up_to_more_marker() {
+        let (app, pool) = testing::test_app().await;
+        testing::article(
+            &pool,
+            "Hello World",
+            "Before the fold\n\n<!-- more -->\n\nAfter the fold",
+        )
+        .await;
+
+        let (_, body) = testing::get(&app, "/").await;
+        assert!(body.contains("Before the fold"));
+        assert!(!body.contains("After the fold"));
+    }
+
+    #[tokio::test]
+    async fn index_shows_comment_counts() {
+        let (app, pool) = testing::test_app().await;
+        let article = testing::article(&pool, "Hello World", "First post").await;
+        testing::article(&pool, "Quiet", "Nobody commented").await;
+        for id in ["c1", "c2"] {
+            sqlx::query!(
+                "INSERT INTO comments ( id, article, author, content, published ) VALUES (?1, ?2, 'Reader', 'Nice', ?3)",
+                id,
+                article.id,
+                article.published
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let (_, body) = testing::get(&app, "/").await;
+        assert!(body.contains("2 comments"));
+        assert!(body.contains("0 comments"));
+    }
+
+    #[tokio::test]
+    async fn article_page_renders_markdown() {
+        let (app, pool) = testing::test_app().await;
+        testing::article(&pool, "Hello World", "Some **bold** text").await;
+
+        let (status, body) = testing::get(&app, "/article/Hello_World").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("<strong>bold</strong>"));
+    }
+
+    #[tokio::test]
+    async fn unknown_article_shows_error_page() {
+        let (app, _) = testing::test_app().await;
+
+        let (_, body) = testing::get(&app, "/article/Nothing_here").await;
+        assert!(body.contains("This page was not found."));
+    }
+
+    #[tokio::test]
+    async fn api_rejects_invalid_secret() {
+        let (app, _) = testing::test_app().await;
+
+        let response = testing::api(
+            &app,
+            "wrong",
+            InnerRequest::ListArticles(Default::default()),
+        )
+        .await;
+        assert!(matches!(response, Response::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn api_creates_articles() {
+        let (app, _) = testing::test_app().await;
+
+        let Response::ArticleId(id) = testing::api(&app, SECRET, create("Via the API")).await
+        else {
+            panic!("expected an article ID");
+        };
+        let request = InnerRequest::GetArticle {
+            url: "Via_the_API".to_string(),
+        };
+        let Response::Article(article) = testing::api(&app, SECRET, request).await else {
+            panic!("expected an article");
+        };
+        assert_eq!(article.id, id);
+    }
+
+    #[tokio::test]
+    async fn submissions_stay_hidden() {
+        let (app, _) = testing::test_app().await;
+
+        let response = testing::api(&app, GUEST_SECRET, create("Guest post")).await;
+        assert!(matches!(response, Response::ArticleId(_)));
+        let (_, body) = testing::get(&app, "/").await;
+        assert!(!body.contains("Guest post"));
+
+        let response = testing::api(
+            &app,
+            GUEST_SECRET,
+            InnerRequest::ListArticles(Default::default()),
+        )
+        .await;
+        assert!(matches!(response, Response::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn duplicated_drafts_are_hidden_until_published() {
+        let (app, pool) = testing::test_app().await;
+        let original = testing::article(&pool, "Weekly notes", "Template").await;
+
+        let request = InnerRequest::DuplicateArticle {
+            id: original.id,
+            draft: true,
+        };
+        let Response::ArticleId(id) = testing::api(&app, SECRET, request).await else {
+            panic!("expected an article ID");
+        };
+        let (_, body) = testing::get(&app, "/").await;
+        assert!(!body.contains("Weekly notes (copy)"));
+
+        let request = InnerRequest::UpdateArticle {
+            id,
+            title: None,
+            content: None,
+            change_note: None,
+            fields: ArticleFields {
+                draft: Some(false),
+                ..Default::default()
+            },
+        };
+        assert!(matches!(
+            testing::api(&app, SECRET, request).await,
+            Response::Ok
+        ));
+        let (_, body) = testing::get(&app, "/").await;
+        assert!(body.contains("Weekly notes (copy)"));
+    }
+
+    #[tokio::test]
+    async fn new_slug_redirects_old_url() {
+        let (app, pool) = testing::test_app().await;
+        let article = testing::article(&pool, "Hello World", "First post").await;
+
+        let request = InnerRequest::SetSlug {
+            id: article.id,
+            slug: "hello".to_string(),
+        };
+        let Response::ArticleUrl(url) = testing::api(&app, SECRET, request).await else {
+            panic!("expected the new URL");
+        };
+        assert_eq!(url, "/article/hello");
+
+        let (status, body) = testing::get(&app, "/article/hello").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("First post"));
+        let (status, _) = testing::get(&app, "/article/Hello_World").await;
+        assert_eq!(status, StatusCode::MOVED_PERMANENTLY);
+    }
+
+    #[tokio::test]
+    async fn colliding_titles_are_rejected_unless_forced() {
+        let (app, _) = testing::test_app().await;
+        testing::api(&app, SECRET, create("Hello World")).await;
+
+        let response = testing::api(&app, SECRET, create("Hello World")).await;
+        assert!(matches!(response, Response::SlugTaken { ref url, .. } if url == "Hello_World"));
+
+        let mut request = create("Hello World");
+        if let InnerRequest::CreateArticle { force_slug, .. } = &mut request {
+            *force_slug = true;
+        }
+        testing::api(&app, SECRET, request).await;
+        let (status, _) = testing::get(&app, "/article/Hello_World_2").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn list_filters_and_sorts_articles() {
+        let (app, _) = testing::test_app().await;
+        for title in ["Rust tips", "Gardening", "More rust"] {
+            testing::api(&app, SECRET, create(title)).await;
+        }
+
+        let filter = ArticleFilter {
+            title_contains: Some("RUST".to_string()),
+            sort: ArticleSort::Title,
+            ..Default::default()
+        };
+        let Response::ArticleMetadata(articles) =
+            testing::api(&app, SECRET, InnerRequest::ListArticles(filter)).await
+        else {
+            panic!("expected a list of articles");
+        };
+        let titles: Vec<_> = articles.iter().map(|a| a.title.as_str()).collect();
+        assert_eq!(titles, ["More rust", "Rust tips"]);
+    }
+
+    #[tokio::test]
+    async fn old_views_are_pruned() {
+        let (_, pool) = testing::test_app().await;
+        let article = testing::article(&pool, "Hello World", "First post").await;
+        let old = Utc::now().naive_utc() - Duration::days(400);
+        let recent = Utc::now().naive_utc();
+        sqlx::query!(
+            "INSERT INTO views ( article, viewed ) VALUES (?1, ?2), (?1, ?3)",
+            article.id,
+            old,
+            recent
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let mut conn = pool.acquire().await.unwrap();
+        let deleted = retention::prune(&RetentionConfig::default(), &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(deleted, 1);
+    }
+
+    #[tokio::test]
+    async fn plugins_rewrite_rendered_articles() {
+        let path = std::env::temp_dir().join(format!("{}.wat", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"(module
+                (memory (export "memory") 1)
+                (data (i32.const 0) "{\"html\":\"<p>Rendered by a plugin</p>\"}")
+                (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+                (func (export "on_render") (param i32 i32) (result i64) (i64.const 38)))"#,
+        )
+        .unwrap();
+        let mut config = testing::config();
+        config.plugins.modules = vec![path.clone()];
+        config.plugin_runtime = Arc::new(Plugins::load(&config.plugins).unwrap());
+        std::fs::remove_file(path).unwrap();
+
+        let (app, pool) = super::test_app(config).await;
+        testing::article(&pool, "Hello World", "First post").await;
+        let (_, body) = testing::get(&app, "/article/Hello_World").await;
+        assert!(body.contains("Rendered by a plugin"));
+    }
+
+    #[tokio::test]
+    async fn api_sends_rfc3339_timestamps() {
+        let (app, pool) = testing::test_app().await;
+        testing::article(&pool, "Hello World", "First post").await;
+
+        let request = InnerRequest::GetArticle {
+            url: "Hello_World".to_string(),
+        };
+        let body = serde_json::to_string(&testing::api(&app, SECRET, request).await).unwrap();
+        let article: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let published = article["Article"]["published"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(published).is_ok());
+    }
+
+    #[tokio::test]
+    async fn preview_links_show_drafts() {
+        let (app, _) = testing::test_app().await;
+        let mut request = create("Secret plans");
+        if let InnerRequest::CreateArticle { fields, .. } = &mut request {
+            fields.draft = Some(true);
+        }
+        let Response::ArticleId(id) = testing::api(&app, SECRET, request).await else {
+            panic!("expected an article ID");
+        };
+
+        // Links can't be made to expire in the past or outlive the configured limit
+        for days in [0, -1, 91, i64::MAX] {
+            let request = InnerRequest::PreviewArticle {
+                id: id.clone(),
+                days,
+            };
+            let Response::Error(e) = testing::api(&app, SECRET, request).await else {
+                panic!("expected an error for {days} days");
+            };
+            assert_eq!(e, "Preview links are valid for 1 to 90 days");
+        }
+
+        let request = InnerRequest::PreviewArticle { id, days: 1 };
+        let Response::ArticleUrl(url) = testing::api(&app, SECRET, request).await else {
+            panic!("expected a preview URL");
+        };
+        let (status, body) = testing::get(&app, &url).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("Secret plans"));
+
+        let (status, _) = testing::get(&app, &format!("{url}0")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn expired_articles_are_unpublished() {
+        let (app, pool) = testing::test_app().await;
+        let mut request = create("Sale ends soon");
+        if let InnerRequest::CreateArticle { fields, .. } = &mut request {
+            fields.expires_at = Some(Utc::now().naive_utc() - Duration::minutes(1));
+        }
+        testing::api(&app, SECRET, request).await;
+        let (status, _) = testing::get(&app, "/article/Sale_ends_soon").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let mut conn = pool.acquire().await.unwrap();
+        assert_eq!(scheduler::expire_articles(&mut conn).await.unwrap(), 1);
+        drop(conn);
+        let (status, _) = testing::get(&app, "/article/Sale_ends_soon").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (_, feed) = testing::get(&app, "/rss").await;
+        assert!(!feed.contains("Sale ends soon"));
+    }
+
+    #[tokio::test]
+    async fn article_links_to_archived_snapshot() {
+        let (app, pool) = testing::test_app().await;
+        let article = testing::article(&pool, "Hello World", "First post").await;
+        let (_, body) = testing::get(&app, "/article/Hello_World").await;
+        assert!(!body.contains("Wayback Machine"));
+
+        let snapshot = "https://web.archive.org/web/20240419120000/https://blog.example.com/article/Hello_World";
+        let mut conn = pool.acquire().await.unwrap();
+        archive::record(&article.id, snapshot, &mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+        let (_, body) = testing::get(&app, "/article/Hello_World").await;
+        assert!(body.contains(snapshot));
+    }
+
+    #[tokio::test]
+    async fn only_the_address_the_proxy_saw_is_trusted() {
+        let mut config = testing::config();
+        config.trust_proxy = true;
+        config.access = toml::from_str(r#"deny = ["192.0.2.0/24"]"#).unwrap();
+        let (app, _) = super::test_app(config).await;
+
+        let status = |name: &'static str, value: &'static str| {
+            let app = app.clone();
+            async move {
+                let request = HttpRequest::get("/")
+                    .header(name, value)
+                    .body(Body::empty())
+                    .unwrap();
+                testing::respond(&app, request).await.status()
+            }
+        };
+        assert_eq!(
+            status("x-forwarded-for", "192.0.2.1").await,
+            StatusCode::FORBIDDEN
+        );
+        // The client can put anything in front of what the proxy appends
+        assert_eq!(
+            status("x-forwarded-for", "198.51.100.1, 192.0.2.1").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status("x-forwarded-for", "192.0.2.1, 198.51.100.1").await,
+            StatusCode::OK
+        );
+
+        // The header the proxy doesn't set is the client's to choose, so it is ignored
+        let both = |forwarded: &'static str, x_forwarded_for: &'static str, app: axum::Router| async move {
+            let request = HttpRequest::get("/")
+                .header("forwarded", forwarded)
+                .header("x-forwarded-for", x_forwarded_for)
+                .body(Body::empty())
+                .unwrap();
+            testing::respond(&app, request).await.status()
+        };
+        assert_eq!(
+            both("for=198.51.100.1", "192.0.2.1", app.clone()).await,
+            StatusCode::FORBIDDEN
+        );
+        let mut config = testing::config();
+        config.trust_proxy = true;
+        config.proxy_header = net::ProxyHeader::Forwarded;
+        config.access = toml::from_str(r#"deny = ["192.0.2.0/24"]"#).unwrap();
+        let (app, _) = super::test_app(config).await;
+        assert_eq!(
+            both(
+                "for=198.51.100.1, for=192.0.2.1",
+                "198.51.100.1",
+                app.clone()
+            )
+            .await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            both("for=198.51.100.1", "192.0.2.1", app).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn pingback_sources_in_private_networks_are_not_fetched() {
+        for source in [
+            "http://127.0.0.1:9/",
+            "http://localhost:9/",
+            "http://[::1]:9/",
+            "http://10.0.0.1/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::ffff:192.168.0.1]/",
+            "http://[fd00::1]/",
+        ] {
+            let result = mention::verify_source(source, "https://example.com/article/a").await;
+            assert!(
+                matches!(result, Err(Fault::SourceNotFound)),
+                "{source} was fetched"
+            );
+        }
+        assert!(net::is_global("93.184.216.34".parse().unwrap()));
+        assert!(net::is_global("2606:2800:220:1::".parse().unwrap()));
+        assert!(!net::is_global("100.64.0.1".parse().unwrap()));
+        assert!(!net::is_global("64:ff9b::7f00:1".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn requests_are_written_to_access_log() {
+        let path = std::env::temp_dir().join(format!("{}.log", uuid::Uuid::new_v4()));
+        let mut config = testing::config();
+        config.access_log = Some(AccessLogConfig {
+            path: path.clone(),
+            max_size: 100,
+            keep: 1,
+        });
+        config.access_logger = Arc::new(AccessLog::open(config.access_log.as_ref()).unwrap());
+        let (app, _) = super::test_app(config).await;
+
+        let request = HttpRequest::get("/robots.txt?lang=en")
+            .header(header::USER_AGENT, r#"Crawler "1.0""#)
+            .body(Body::empty())
+            .unwrap();
+        testing::respond(&app, request).await;
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert!(log.starts_with("127.0.0.1 - - ["));
+        assert!(log.contains(r#""GET /robots.txt?lang=en HTTP/1.1" 200 "#));
+        assert!(log.ends_with("\"-\" \"Crawler \\\"1.0\\\"\"\n"));
+
+        // The second line doesn't fit anymore
+        testing::get(&app, "/robots.txt").await;
+        let rotated = format!("{}.1", path.display());
+        assert_eq!(std::fs::read_to_string(&rotated).unwrap(), log);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(rotated).unwrap();
+    }
+
+    #[tokio::test]
+    async fn errors_show_themed_pages_except_on_the_api() {
+        let theme = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir(&theme).unwrap();
+        std::fs::write(theme.join("404.html"), "<h1>Lost in the woods</h1>").unwrap();
+        let mut config = testing::config();
+        config.error_pages = Arc::new(ErrorPages::load(Some(&theme)).unwrap());
+        std::fs::remove_dir_all(theme).unwrap();
+
+        let (app, pool) = super::test_app(config).await;
+        let (status, body) = testing::get(&app, "/nowhere").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, "<h1>Lost in the woods</h1>");
+
+        pool.close().await;
+        let (status, body) = testing::get(&app, "/").await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(body.contains("Something went wrong."));
+        let response = testing::api(
+            &app,
+            SECRET,
+            InnerRequest::ListArticles(ArticleFilter::default()),
+        )
+        .await;
+        let Response::Error(message) = response else {
+            panic!("expected an error");
+        };
+        // The details stay in the log
+        assert!(message.starts_with("Internal Server Error (error ID "));
+        assert!(!message.to_lowercase().contains("pool"));
+    }
+
+    #[tokio::test]
+    async fn client_mistakes_are_explained_rather_than_hidden() {
+        let (app, _) = testing::test_app().await;
+
+        for request in [
+            InnerRequest::GetArticleById {
+                id: "missing".to_string(),
+            },
+            InnerRequest::YankArticle {
+                id: "missing".to_string(),
+            },
+        ] {
+            let Response::Error(message) = testing::api(&app, SECRET, request).await else {
+                panic!("expected an error");
+            };
+            assert_eq!(message, "No article with id missing found");
+        }
+
+        let request = InnerRequest::CreateArticles(vec![NewArticle {
+            title: "Hallo Welt".to_string(),
+            content: "Erster Beitrag".to_string(),
+            idempotency_key: None,
+            force_slug: false,
+            fields: ArticleFields {
+                translation_of: Some("missing".to_string()),
+                ..Default::default()
+            },
+        }]);
+        let Response::Results(results) = testing::api(&app, SECRET, request).await else {
+            panic!("expected results");
+        };
+        assert!(
+            matches!(&results[0], Response::Error(e) if e.contains("no article with id missing"))
+        );
+    }
+
+    #[tokio::test]
+    async fn icons_are_generated_from_the_configured_icon() {
+        let path = std::env::temp_dir().join(format!("{}.svg", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="64" height="48"><rect width="64" height="48" fill="teal"/></svg>"#,
+        )
+        .unwrap();
+        let mut config = testing::config();
+        config.icons = Arc::new(Icons::load(Some(&path)).unwrap());
+        std::fs::remove_file(path).unwrap();
+        let (app, _) = super::test_app(config).await;
+
+        let request = HttpRequest::get("/favicon.ico")
+            .body(Body::empty())
+            .unwrap();
+        let response = testing::respond(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/x-icon");
+        let request = HttpRequest::get("/icons/180.png")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            testing::respond(&app, request).await.status(),
+            StatusCode::OK
+        );
+        let request = HttpRequest::get("/icons/100.png")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            testing::respond(&app, request).await.status(),
+            StatusCode::NOT_FOUND
+        );
+
+        let (_, manifest) = testing::get(&app, "/site.webmanifest").await;
+        assert!(manifest.contains("/icons/512.png"));
+        let (_, index) = testing::get(&app, "/").await;
+        assert!(index.contains(r#"<link rel="apple-touch-icon" href="/icons/180.png">"#));
+    }
+
+    #[tokio::test]
+    async fn color_scheme_is_remembered_in_a_cookie() {
+        let (app, _) = testing::test_app().await;
+        let (_, page) = testing::get(&app, "/").await;
+        assert!(page.contains(r#"href="/static/dark.css" media="(prefers-color-scheme: dark)""#));
+
+        let request = HttpRequest::get("/color-scheme/dark")
+            .header(header::REFERER, "https://blog.example.com/tag/rust?page=2")
+            .body(Body::empty())
+            .unwrap();
+        let response = testing::respond(&app, request).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()[header::LOCATION], "/tag/rust?page=2");
+        let cookie = "color-scheme=dark";
+        assert!(response
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .any(|c| c.to_str().unwrap().starts_with(cookie)));
+
+        let request = HttpRequest::get("/")
+            .header(header::COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap();
+        let (_, page) = testing::send(&app, request).await;
+        assert!(page.contains(r#"href="/static/dark.css" media="all""#));
+        assert!(!page.contains("/static/light.css"));
+    }
+
+    #[tokio::test]
+    async fn articles_can_be_downloaded_as_epub() {
+        let (app, pool) = testing::test_app().await;
+        testing::article(&pool, "Hello World", "First *post*").await;
+
+        let request = HttpRequest::get("/article/Hello_World/epub")
+            .body(Body::empty())
+            .unwrap();
+        let response = testing::respond(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"Hello_World.epub\""
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        // The uncompressed `mimetype` file comes first
+        assert!(body.starts_with(b"PK"));
+        assert_eq!(&body[30..58], b"mimetypeapplication/epub+zip");
+
+        let (status, _) = testing::get(&app, "/article/Nothing_here/epub").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn articles_offer_their_markdown_and_link() {
+        let (app, pool) = testing::test_app().await;
+        let article = testing::article(&pool, "Hello World", "First *post*").await;
+        let mut conn = pool.acquire().await.unwrap();
+        let tags = vec!["rust".to_string(), "web".to_string()];
+        let authors = vec!["Ferris".to_string(), "Corro".to_string()];
+        tag::set_tags(&article.id, &tags, &mut conn).await.unwrap();
+        author::set_authors(&article.id, &authors, &mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains("href=\"/article/Hello_World/markdown\""));
+        assert!(page.contains("data-url=\"https://blog.example.com/article/Hello_World\""));
+        let (status, markdown) = testing::get(&app, "/article/Hello_World/markdown").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(markdown.starts_with("+++\ntitle = \"Hello World\"\n"));
+        assert!(markdown.ends_with("First *post*"));
+        let (front_matter, content) = frontmatter::parse(&markdown).unwrap();
+        assert_eq!(front_matter.fields.tags, Some(tags));
+        assert_eq!(front_matter.fields.authors, Some(authors));
+        assert_eq!(content, "First *post*");
+
+        let mut config = testing::config();
+        config.actions.markdown = false;
+        config.actions.copy_link = false;
+        let (app, pool) = super::test_app(config).await;
+        testing::article(&pool, "Hello World", "First *post*").await;
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(!page.contains("/article/Hello_World/markdown"));
+        assert!(!page.contains("Copy link"));
+        let (status, _) = testing::get(&app, "/article/Hello_World/markdown").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn headings_and_footnotes_link_to_each_other() {
+        let content = "## Hello there\n\nSee the note.[^1]\n\n[^1]: A note.";
+        let (app, pool) = testing::test_app().await;
+        testing::article(&pool, "Hello World", content).await;
+
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains(r##"<a href="#content-hello-there" class="anchor" id="content-hello-there" aria-label="Link to this section">#</a>Hello there"##));
+        assert!(page.contains("href=\"#fnref-1\""));
+        assert!(page.contains("↩</a>"));
+
+        let mut config = testing::config();
+        config.appearance.heading_link = "§".to_string();
+        config.appearance.footnote_backlink = String::new();
+        let (app, pool) = super::test_app(config).await;
+        testing::article(&pool, "Hello World", content).await;
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains(r#"aria-label="Link to this section">§</a>"#));
+        assert!(!page.contains("href=\"#fnref-1\""));
+        assert!(page.contains("A note.</p>"));
+    }
+
+    #[tokio::test]
+    async fn mermaid_blocks_become_diagrams_when_enabled() {
+        let content = "```mermaid\ngraph TD\n  A --> B\n```";
+        let (app, pool) = testing::test_app().await;
+        testing::article(&pool, "Hello World", content).await;
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains("<code class=\"language-mermaid\">"));
+        assert!(!page.contains("mermaid.initialize"));
+
+        let mut config = testing::config();
+        config.mermaid = Some(toml::from_str("").unwrap());
+        let (app, pool) = super::test_app(config).await;
+        testing::article(&pool, "Hello World", content).await;
+        testing::article(&pool, "No diagrams", "Just text").await;
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains("<pre class=\"mermaid\">graph TD\n  A --&gt; B\n</pre>"));
+        assert!(page.contains("mermaid.initialize"));
+        let (_, page) = testing::get(&app, "/article/No_diagrams").await;
+        assert!(!page.contains("mermaid.initialize"));
+    }
+
+    #[tokio::test]
+    async fn external_links_are_marked() {
+        let content = "[Elsewhere](https://example.org/?a=1&b=2), \
+            [here](https://blog.example.com/tag/rust) and [there](/tag/rust)";
+        let (app, pool) = testing::test_app().await;
+        testing::article(&pool, "Hello World", content).await;
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains(
+            r#"<a rel="noopener noreferrer" href="https://example.org/?a=1&amp;b=2">Elsewhere</a>"#
+        ));
+        assert!(page.contains(r#"<a href="https://blog.example.com/tag/rust">here</a>"#));
+        assert!(page.contains(r#"<a href="/tag/rust">there</a>"#));
+
+        let mut config = testing::config();
+        config.links.new_tab = true;
+        config.links.icon = "↗".to_string();
+        let (app, pool) = super::test_app(config).await;
+        testing::article(&pool, "Hello World", content).await;
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains(
+            r#"<a rel="noopener noreferrer" target="_blank" href="https://example.org/?a=1&amp;b=2">Elsewhere<span class="external-link" aria-hidden="true">↗</span></a>"#
+        ));
+        assert!(page.contains(r#"<a href="/tag/rust">there</a>"#));
+    }
+
+    #[tokio::test]
+    async fn images_are_lazy_and_sized() {
+        let (app, pool) = testing::test_app().await;
+        // The start of a PNG of 2 by 3 pixels
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x02\0\0\0\x03".to_vec();
+        png.extend([8, 6, 0, 0, 0]);
+        let mut conn = pool.acquire().await.unwrap();
+        let image = media::store("dot.png", "image/png", png, &mut conn)
             .await
-            .into_diagnostic()?
-            .is_some(),
-    )
+            .unwrap();
+        // Uploaded before dimensions were recorded
+        sqlx::query!("UPDATE media SET width = NULL, height = NULL")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        drop(conn);
+        let content = format!(
+            "![A dot]({}) ![Elsewhere](https://example.org/a.png)",
+            image.url()
+        );
+        testing::article(&pool, "Hello World", &content).await;
+
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains(&format!(
+            r#"<img loading="lazy" width="2" height="3" src="{}""#,
+            image.url()
+        )));
+        assert!(page.contains(r#"<img loading="lazy" src="https://example.org/a.png""#));
+        let mut conn = pool.acquire().await.unwrap();
+        let stored = sqlx::query!("SELECT width, height FROM media")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!((stored.width, stored.height), (Some(2), Some(3)));
+    }
+
+    #[tokio::test]
+    async fn translations_are_alternates_of_each_other() {
+        let (app, _) = testing::test_app().await;
+        let Response::ArticleId(original) = testing::api(&app, SECRET, create("Hello")).await
+        else {
+            panic!("expected an article ID");
+        };
+        let fields = ArticleFields {
+            lang: Some("en".to_string()),
+            ..Default::default()
+        };
+        let request = InnerRequest::UpdateArticle {
+            id: original.clone(),
+            title: None,
+            content: None,
+            change_note: None,
+            fields,
+        };
+        testing::api(&app, SECRET, request).await;
+        let (_, page) = testing::get(&app, "/article/Hello").await;
+        assert!(!page.contains("hreflang"));
+
+        let mut request = create("Hallo");
+        if let InnerRequest::CreateArticle { fields, .. } = &mut request {
+            fields.lang = Some("de".to_string());
+            fields.translation_of = Some(original);
+        }
+        testing::api(&app, SECRET, request).await;
+        for url in ["/article/Hello", "/article/Hallo"] {
+            let (_, page) = testing::get(&app, url).await;
+            assert!(page.contains(r#"<link rel="alternate" hreflang="en" href="https://blog.example.com/article/Hello" />"#));
+            assert!(page.contains(r#"<link rel="alternate" hreflang="de" href="https://blog.example.com/article/Hallo" />"#));
+        }
+    }
+
+    #[tokio::test]
+    async fn deleting_an_original_keeps_its_translations_together() {
+        let (app, pool) = testing::test_app().await;
+        let mut ids = Vec::new();
+        for (title, lang) in [("Hello", "en"), ("Hallo", "de"), ("Bonjour", "fr")] {
+            let mut request = create(title);
+            if let InnerRequest::CreateArticle { fields, .. } = &mut request {
+                fields.lang = Some(lang.to_string());
+                fields.translation_of = ids.first().cloned();
+            }
+            let Response::ArticleId(id) = testing::api(&app, SECRET, request).await else {
+                panic!("expected an article ID");
+            };
+            ids.push(id);
+        }
+
+        let request = InnerRequest::YankArticle { id: ids[0].clone() };
+        assert!(matches!(
+            testing::api(&app, SECRET, request).await,
+            Response::Ok
+        ));
+        let group = sqlx::query!("SELECT id, translation_of FROM articles ORDER BY rowid")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let group: Vec<_> = group
+            .into_iter()
+            .map(|a| (a.id, a.translation_of))
+            .collect();
+        assert_eq!(
+            group,
+            [
+                (ids[1].clone(), None),
+                (ids[2].clone(), Some(ids[1].clone()))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn html_follows_the_policy() {
+        let content = "## Hello\n\n<b>bold</b> <script>alert(1)</script>";
+        let (app, pool) = testing::test_app().await;
+        testing::article(&pool, "Hello World", content).await;
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains("&lt;b&gt;bold&lt;/b&gt;"));
+        let (_, feed) = testing::get(&app, "/rss").await;
+        assert!(feed.contains("&lt;b&gt;bold&lt;/b&gt;"));
+
+        let mut config = testing::config();
+        config.html.policy = HtmlPolicy::Sanitize;
+        let (app, pool) = super::test_app(config).await;
+        testing::article(&pool, "Hello World", content).await;
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains("<b>bold</b>"));
+        assert!(!page.contains("alert(1)"));
+        assert!(page.contains(r#"class="anchor" id="content-hello""#));
+        let (_, feed) = testing::get(&app, "/rss").await;
+        assert!(feed.contains("<b>bold</b>"));
+        assert!(!feed.contains("alert(1)"));
+    }
+
+    #[tokio::test]
+    async fn only_publishers_may_override_the_html_policy() {
+        let (app, pool) = testing::test_app().await;
+        for (secret, title) in [(SECRET, "Trusted"), (GUEST_SECRET, "Guest")] {
+            let request = InnerRequest::CreateArticle {
+                title: title.to_string(),
+                content: "<b>bold</b>".to_string(),
+                idempotency_key: None,
+                force_slug: false,
+                fields: ArticleFields {
+                    html: Some(HtmlPolicy::Allow),
+                    ..Default::default()
+                },
+            };
+            testing::api(&app, secret, request).await;
+        }
+
+        let (_, page) = testing::get(&app, "/article/Trusted").await;
+        assert!(page.contains("<b>bold</b>"));
+        let mut conn = pool.acquire().await.unwrap();
+        let guest = sqlx::query!("SELECT html FROM articles WHERE title = 'Guest'")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!(guest.html, None);
+        // A misspelled policy is rejected instead of quietly falling back to the blog's
+        let typo = serde_json::from_str::<ArticleFields>(r#"{"html": "sanitise"}"#);
+        assert!(typo.is_err());
+    }
+
+    #[tokio::test]
+    async fn preview_images_are_revalidated_until_the_article_changes() {
+        let (app, pool) = testing::test_app().await;
+        let article = testing::article(&pool, "Hello World", "First post").await;
+        let fetch = |etag: Option<&str>| {
+            let mut request = HttpRequest::get("/article/Hello_World/og.png");
+            if let Some(etag) = etag {
+                request = request.header(header::IF_NONE_MATCH, etag);
+            }
+            let request = request.body(Body::empty()).unwrap();
+            let app = app.clone();
+            async move { testing::respond(&app, request).await }
+        };
+
+        let response = fetch(None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(fetch(Some(&etag)).await.status(), StatusCode::NOT_MODIFIED);
+
+        let request = InnerRequest::UpdateArticle {
+            id: article.id,
+            title: None,
+            content: Some("Edited".to_string()),
+            change_note: None,
+            fields: ArticleFields::default(),
+        };
+        testing::api(&app, SECRET, request).await;
+        let response = fetch(Some(&etag)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[header::ETAG], etag.as_str());
+    }
+
+    #[tokio::test]
+    async fn print_view_shows_only_the_article() {
+        let (app, pool) = testing::test_app().await;
+        testing::article(&pool, "Hello World", "First *post*").await;
+
+        let (status, body) = testing::get(&app, "/article/Hello_World/print").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("<em>post</em>"));
+        assert!(body.contains("/static/print.css"));
+        assert!(!body.contains("Comments"));
+        assert!(!body.contains("<nav"));
+
+        let (status, _) = testing::get(&app, "/article/Nothing_here/print").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn comments_can_be_closed_per_article() {
+        let (app, _) = testing::test_app().await;
+        let mut request = create("Sensitive topic");
+        if let InnerRequest::CreateArticle { fields, .. } = &mut request {
+            fields.comments_enabled = Some(false);
+        }
+        let Response::ArticleId(id) = testing::api(&app, SECRET, request).await else {
+            panic!("expected an article ID");
+        };
+
+        let (_, page) = testing::get(&app, "/article/Sensitive_topic").await;
+        assert!(page.contains("Comments on this article are closed."));
+        assert!(!page.contains("comment-form"));
+
+        let (cookie, csrf) = testing::session(&app).await;
+        let form = format!("article={id}&author=Troll&content=Hi&csrf={csrf}");
+        let response = testing::post_form(&app, &cookie, "/article/Sensitive_topic", &form).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn reported_comments_are_hidden_until_reviewed() {
+        let mut config = testing::config();
+        config.comments.flag_threshold = 2;
+        config.trust_proxy = true;
+        let (app, pool) = super::test_app(config).await;
+        sqlx::query!(
+            "INSERT INTO secrets (secret, auth_mode, scope) VALUES (?1, 'bearer', 'publish')",
+            SECRET
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let article = testing::article(&pool, "Hello World", "First post").await;
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "INSERT INTO comments ( id, article, author, content, published ) VALUES ('spam', ?1, 'Spammer', 'Buy now', ?2)",
+            article.id,
+            now
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let report = |ip: &'static str| {
+            let app = app.clone();
+            async move {
+                let (cookie, csrf) = testing::session(&app).await;
+                let request = HttpRequest::post("/comment/spam/flag")
+                    .header(header::COOKIE, cookie)
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .header("x-forwarded-for", ip)
+                    .body(Body::from(format!("csrf={csrf}")))
+                    .unwrap();
+                testing::respond(&app, request).await
+            }
+        };
+        let response = report("192.0.2.1").await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "/article/Hello_World#comments"
+        );
+        // Reporting twice doesn't count twice
+        report("192.0.2.1").await;
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains("Buy now"));
+
+        report("192.0.2.2").await;
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(!page.contains("Buy now"));
+        let (_, queue) = testing::admin(&app, SECRET, "/admin/comments").await;
+        assert!(queue.contains("Buy now"));
+        assert!(queue.contains("Reported 2 times"));
+
+        let mut conn = pool.acquire().await.unwrap();
+        comment::keep("spam", &mut conn).await.unwrap();
+        drop(conn);
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains("Buy now"));
+        let (_, queue) = testing::admin(&app, SECRET, "/admin/comments").await;
+        assert!(!queue.contains("Buy now"));
+    }
+
+    #[tokio::test]
+    async fn proof_of_work_is_signed_and_good_for_one_comment() {
+        use sha2::{Digest, Sha256};
+
+        let mut config = testing::config();
+        config.comments.pow_difficulty = Some(4);
+        let (app, pool) = super::test_app(config).await;
+        let article = testing::article(&pool, "Hello World", "First post").await;
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        let challenge = page
+            .split(r#"name="challenge" value=""#)
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap()
+            .to_string();
+        let solve = |challenge: &str| {
+            (0..)
+                .find(|nonce| Sha256::digest(format!("{challenge}:{nonce}"))[0] < 0x10)
+                .unwrap()
+        };
+
+        let (cookie, csrf) = testing::session(&app).await;
+        let post = |challenge: &str, content: &str| {
+            format!(
+                "article={}&author=Alice&content={content}&challenge={}&nonce={}&csrf={csrf}",
+                article.id,
+                challenge.replace(':', "%3A"),
+                solve(challenge)
+            )
+        };
+        let response = testing::post_form(
+            &app,
+            &cookie,
+            "/article/Hello_World",
+            &post(&challenge, "Solved"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let response = testing::post_form(
+            &app,
+            &cookie,
+            "/article/Hello_World",
+            &post(&challenge, "Replayed"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        // Commenters can't issue themselves challenges that never expire
+        let future = Utc::now().timestamp() + 86400;
+        let forged = format!("{}:{future}", article.id);
+        let response = testing::post_form(
+            &app,
+            &cookie,
+            "/article/Hello_World",
+            &post(&forged, "Forged"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains("Solved"));
+        assert!(!page.contains("Replayed"));
+        assert!(!page.contains("Forged"));
+    }
+
+    #[tokio::test]
+    async fn comments_of_banned_commenters_are_discarded() {
+        let mut config = testing::config();
+        config.trust_proxy = true;
+        let (app, pool) = super::test_app(config).await;
+        sqlx::query!(
+            "INSERT INTO secrets (secret, auth_mode, scope) VALUES (?1, 'bearer', 'publish')",
+            SECRET
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let article = testing::article(&pool, "Hello World", "First post").await;
+        let mut conn = pool.acquire().await.unwrap();
+        ban::add(BanKind::Name, "*Casino*", None, &mut conn)
+            .await
+            .unwrap();
+        ban::add(BanKind::Email, "Troll@Example.com", None, &mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let post = |ip: &'static str, fields: &'static str| {
+            let app = app.clone();
+            let id = article.id.clone();
+            async move {
+                let (cookie, csrf) = testing::session(&app).await;
+                let request = HttpRequest::post("/article/Hello_World")
+                    .header(header::COOKIE, cookie)
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .header("x-forwarded-for", ip)
+                    .body(Body::from(format!("article={id}&{fields}&csrf={csrf}")))
+                    .unwrap();
+                testing::respond(&app, request).await
+            }
+        };
+        let response = post("192.0.2.1", "author=Best+casino+deals&content=Cheap+chips").await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        post(
+            "192.0.2.1",
+            "author=Troll&content=Take+the+bait&email=troll%40example.com",
+        )
+        .await;
+        post("192.0.2.1", "author=Alice&content=Welcome").await;
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(!page.contains("Cheap chips"));
+        assert!(!page.contains("Take the bait"));
+        assert!(page.contains("Welcome"));
+
+        let mut conn = pool.acquire().await.unwrap();
+        let id = sqlx::query!("SELECT id FROM comments WHERE author = 'Alice'")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap()
+            .id;
+        assert!(ban::ban_author(&id, None, &mut conn).await.unwrap());
+        drop(conn);
+        post("192.0.2.1", "author=Alice&content=Welcome+back").await;
+        post("192.0.2.2", "author=Bob&content=Hello+from+Bob").await;
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(!page.contains("Welcome back"));
+        assert!(page.contains("Hello from Bob"));
+
+        let (_, bans) = testing::admin(&app, SECRET, "/admin/bans").await;
+        assert!(bans.contains("Name *Casino*"));
+        assert!(!bans.contains("troll@example.com"));
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_notified_about_new_comments() {
+        let mut config = testing::config();
+        config.email = Some(
+            toml::from_str(
+                r#"host = "localhost"
+from = "blog@example.com""#,
+            )
+            .unwrap(),
+        );
+        let (app, pool) = super::test_app(config).await;
+        let article = testing::article(&pool, "Hello World", "First post").await;
+        let (cookie, csrf) = testing::session(&app).await;
+        let comment = |author: &str, content: &str, subscribe: bool| {
+            let (app, cookie) = (app.clone(), cookie.clone());
+            let mut form = format!(
+                "article={}&author={author}&content={content}&csrf={csrf}",
+                article.id
+            );
+            if subscribe {
+                form.push_str("&email=alice%40example.com&subscribe=on");
+            }
+            async move { testing::post_form(&app, &cookie, "/article/Hello_World", &form).await }
+        };
+        // Runs the delayed notifications right away, returning the emails they sent
+        let notify = |pool: Pool<Sqlite>| async move {
+            let mut conn = pool.acquire().await.unwrap();
+            let delayed = sqlx::query!("SELECT payload FROM jobs WHERE run_at > created")
+                .fetch_all(&mut *conn)
+                .await
+                .unwrap();
+            sqlx::query!("DELETE FROM jobs")
+                .execute(&mut *conn)
+                .await
+                .unwrap();
+            drop(conn);
+            let ctx = JobContext {
+                client: reqwest::Client::new(),
+                mailer: None,
+                pool: pool.clone(),
+            };
+            for job in delayed {
+                let job: Job = serde_json::from_str(&job.payload).unwrap();
+                job.run(&ctx).await.unwrap();
+            }
+            sqlx::query!("SELECT payload FROM jobs")
+                .fetch_all(&pool)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|job| job.payload)
+                .collect::<Vec<_>>()
+        };
+
+        comment("Alice", "Hello", true).await;
+        let jobs = sqlx::query!("SELECT payload FROM jobs WHERE run_at <= created")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert!(jobs[0].payload.contains("alice@example.com"));
+        assert!(jobs[0].payload.contains("/subscribe/"));
+        let token = sqlx::query!("SELECT token FROM comment_subscriptions")
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .token;
+
+        // Nothing is sent to an address that didn't confirm
+        comment("Bob", "Unconfirmed", false).await;
+        assert!(notify(pool.clone()).await.is_empty());
+
+        let (status, _) = testing::get(&app, &format!("/subscribe/{token}")).await;
+        assert_eq!(status, StatusCode::OK);
+        comment("Bob", "Confirmed", false).await;
+        comment("Carol", "Reported", false).await;
+        comment("Alice", "Again", true).await;
+        sqlx::query!("UPDATE comments SET hidden = TRUE WHERE content = 'Reported'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        // Alice isn't told about her own comment or the hidden one, and not asked to confirm again
+        let emails = notify(pool.clone()).await;
+        assert_eq!(emails.len(), 1);
+        assert!(emails[0].contains("alice@example.com"));
+        assert!(emails[0].contains("Confirmed"));
+        assert!(emails[0].contains(&format!("/unsubscribe/{token}")));
+
+        let (status, _) = testing::get(&app, &format!("/unsubscribe/{token}")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = testing::get(&app, &format!("/unsubscribe/{token}")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = testing::get(&app, &format!("/subscribe/{token}")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn idempotency_keys_only_identify_retries_of_the_same_client() {
+        let (app, _) = testing::test_app().await;
+        let create = |key: &str| InnerRequest::CreateArticle {
+            title: "Hello World".to_string(),
+            content: "Some *content*".to_string(),
+            idempotency_key: Some(key.to_string()),
+            force_slug: true,
+            fields: ArticleFields::default(),
+        };
+        let id = |response| match response {
+            Response::ArticleId(id) => id,
+            _ => panic!("expected an article ID"),
+        };
+
+        let first = id(testing::api(&app, SECRET, create("first")).await);
+        let retry = id(testing::api(&app, SECRET, create("first")).await);
+        assert_eq!(first, retry);
+        // Publishing the same article again on purpose comes with a new key
+        let again = id(testing::api(&app, SECRET, create("second")).await);
+        assert_ne!(first, again);
+        let other = id(testing::api(&app, GUEST_SECRET, create("first")).await);
+        assert_ne!(first, other);
+    }
+
+    #[tokio::test]
+    async fn articles_record_the_secret_they_were_created_with() {
+        let (app, _) = testing::test_app().await;
+        testing::api(&app, SECRET, create("Hello World")).await;
+
+        let list = |created_by| {
+            InnerRequest::ListArticles(ArticleFilter {
+                created_by: Some(created_by),
+                ..Default::default()
+            })
+        };
+        let Response::ArticleMetadata(articles) = testing::api(&app, SECRET, list(1)).await else {
+            panic!("expected a list of articles");
+        };
+        assert_eq!(articles.len(), 1);
+        assert_eq!(articles[0].created_by, Some(1));
+
+        let Response::ArticleMetadata(articles) = testing::api(&app, SECRET, list(2)).await else {
+            panic!("expected a list of articles");
+        };
+        assert!(articles.is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_articles_are_rejected() {
+        let (app, _) = testing::test_app().await;
+        let request = InnerRequest::CreateArticle {
+            title: " ".to_string(),
+            content: "![](/media/cat.png)\n\n<script>alert(1)</script>".to_string(),
+            idempotency_key: None,
+            force_slug: false,
+            fields: ArticleFields::default(),
+        };
+        let Response::Invalid(errors) = testing::api(&app, SECRET, request).await else {
+            panic!("expected the article to be rejected");
+        };
+        let fields: Vec<_> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["title", "content", "content"]);
+
+        let Response::ArticleId(id) = testing::api(&app, SECRET, create("Hello World")).await
+        else {
+            panic!("expected an article ID");
+        };
+        let request = InnerRequest::UpdateArticle {
+            id,
+            title: None,
+            content: Some("<iframe src=\"https://example.com\"></iframe>".to_string()),
+            change_note: None,
+            fields: ArticleFields::default(),
+        };
+        assert!(matches!(
+            testing::api(&app, SECRET, request).await,
+            Response::Invalid(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn oversized_api_requests_are_rejected() {
+        let mut config = testing::config();
+        config.api.max_body_size = 1024;
+        let (app, _) = super::test_app(config).await;
+
+        let request = Request {
+            secret: Some(SECRET.to_string()),
+            request: InnerRequest::CreateArticle {
+                title: "Hello World".to_string(),
+                content: "a".repeat(2048),
+                idempotency_key: None,
+                force_slug: false,
+                fields: ArticleFields::default(),
+            },
+        };
+        let (status, body) = testing::send(
+            &app,
+            HttpRequest::post("/api")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(&request).unwrap()))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        let response: Response = serde_json::from_str(&body).unwrap();
+        assert!(matches!(response, Response::Error(e) if e.contains("1024 bytes")));
+    }
+
+    #[tokio::test]
+    async fn attachments_are_stored_and_referenced() {
+        let (app, _) = testing::test_app().await;
+        let request = Request {
+            secret: Some(SECRET.to_string()),
+            request: InnerRequest::CreateArticle {
+                title: "Hello World".to_string(),
+                content: "![A cat](./images/cat.png)\n\nSee [my notes](notes.txt \"Notes\") and [the old ones](notes.txt.bak)".to_string(),
+                idempotency_key: None,
+                force_slug: false,
+                fields: ArticleFields::default(),
+            },
+        };
+        let attachments = [
+            Attachment {
+                path: "images/cat.png".to_string(),
+                content_type: "image/png".to_string(),
+                data: b"meow".to_vec(),
+            },
+            Attachment {
+                path: "notes.txt".to_string(),
+                content_type: "text/plain".to_string(),
+                data: b"purr".to_vec(),
+            },
+        ];
+        let (content_type, body) =
+            attachment::encode(&serde_json::to_vec(&request).unwrap(), &attachments);
+        let (status, _) = testing::send(
+            &app,
+            HttpRequest::post("/api")
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        let url = page
+            .split("src=\"")
+            .find(|part| part.starts_with("/media/"))
+            .and_then(|part| part.split('"').next())
+            .unwrap()
+            .to_string();
+        assert!(url.ends_with("/cat.png"));
+        let (status, body) = testing::get(&app, &url).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "meow");
+        assert!(page.contains("/notes.txt\" title=\"Notes\">my notes</a>"));
+        assert!(!page.contains("href=\"notes.txt\""));
+        // Only whole paths are rewritten
+        assert!(page.contains("href=\"notes.txt.bak\">the old ones</a>"));
+    }
+
+    #[tokio::test]
+    async fn feed_items_become_drafts_once() {
+        let (app, pool) = testing::test_app().await;
+        let feed = r#"<?xml version="1.0"?>
+            <rss version="2.0"><channel>
+                <title>Elsewhere</title>
+                <link>https://elsewhere.example.com</link>
+                <description>My other blog</description>
+                <item>
+                    <title>Written elsewhere</title>
+                    <link>https://elsewhere.example.com/post</link>
+                    <guid>https://elsewhere.example.com/post</guid>
+                    <description>&lt;p&gt;Some &lt;em&gt;content&lt;/em&gt;&lt;/p&gt;</description>
+                </item>
+            </channel></rss>"#;
+
+        let mut conn = pool.acquire().await.unwrap();
+        let url = "https://elsewhere.example.com/rss";
+        assert_eq!(
+            ingest::ingest(url, feed.as_bytes(), &mut conn)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            ingest::ingest(url, feed.as_bytes(), &mut conn)
+                .await
+                .unwrap(),
+            0
+        );
+
+        let article = sqlx::query!("SELECT status, content, canonical_url FROM articles")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        drop(conn);
+        assert_eq!(article.status, DRAFT);
+        assert_eq!(article.content.trim(), "Some *content*");
+        assert_eq!(
+            article.canonical_url.as_deref(),
+            Some("https://elsewhere.example.com/post")
+        );
+        let (status, _) = testing::get(&app, "/article/Written_elsewhere").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn cached_feed_changes_with_the_articles() {
+        let (app, pool) = testing::test_app().await;
+        let first = testing::article(&pool, "First", "Hello").await;
+        let (_, feed) = testing::get(&app, "/rss").await;
+        assert!(feed.contains("First"));
+
+        let Response::ArticleId(id) = testing::api(&app, SECRET, create("Second")).await else {
+            panic!("expected an article ID");
+        };
+        let (_, feed) = testing::get(&app, "/rss").await;
+        assert!(feed.contains("Second"));
+
+        testing::api(&app, SECRET, InnerRequest::YankArticle { id }).await;
+        let (_, feed) = testing::get(&app, "/rss").await;
+        assert!(feed.contains("First"));
+        assert!(!feed.contains("Second"));
+
+        let conditional = |name: header::HeaderName, value: String| {
+            let request = HttpRequest::get("/rss")
+                .header(name, value)
+                .body(Body::empty())
+                .unwrap();
+            let app = app.clone();
+            async move { testing::respond(&app, request).await }
+        };
+        let response =
+            testing::respond(&app, HttpRequest::get("/rss").body(Body::empty()).unwrap()).await;
+        let value_of =
+            |name: header::HeaderName| response.headers()[name].to_str().unwrap().to_string();
+        let (etag, last_modified) = (value_of(header::ETAG), value_of(header::LAST_MODIFIED));
+        let response = conditional(header::IF_NONE_MATCH, etag.clone()).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        let response = conditional(header::IF_MODIFIED_SINCE, last_modified).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        // A date after the last change isn't proof the client saw it
+        let later = (Utc::now() + Duration::days(1)).to_rfc2822();
+        let response = conditional(header::IF_MODIFIED_SINCE, later).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Tags are part of the feed, so changing them makes it new
+        let request = InnerRequest::UpdateArticle {
+            id: first.id,
+            title: None,
+            content: None,
+            change_note: None,
+            fields: ArticleFields {
+                tags: Some(vec!["news".to_string()]),
+                ..Default::default()
+            },
+        };
+        testing::api(&app, SECRET, request).await;
+        let response = conditional(header::IF_NONE_MATCH, etag).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let (_, feed) = testing::get(&app, "/rss").await;
+        assert!(feed.contains(">news</category>"));
+    }
+
+    #[tokio::test]
+    async fn feed_items_are_filed_under_their_tags() {
+        let (app, _) = testing::test_app().await;
+        let mut request = create("Tagged");
+        if let InnerRequest::CreateArticle { fields, .. } = &mut request {
+            fields.tags = Some(vec!["Rust Lang".to_string(), "web".to_string()]);
+        }
+        testing::api(&app, SECRET, request).await;
+
+        let (_, feed) = testing::get(&app, "/rss").await;
+        assert!(feed.contains(
+            r#"<category domain="https://blog.example.com/tag/rust-lang">rust-lang</category>"#
+        ));
+        assert!(
+            feed.contains(r#"<category domain="https://blog.example.com/tag/web">web</category>"#)
+        );
+    }
+
+    #[tokio::test]
+    async fn previews_render_like_articles() {
+        let (app, pool) = testing::test_app().await;
+        let content = "## Heading\n\n~~struck~~ and https://example.com";
+        testing::article(&pool, "Rendered", content).await;
+        let (_, page) = testing::get(&app, "/article/Rendered").await;
+
+        let request = InnerRequest::RenderPreview {
+            content: content.to_string(),
+        };
+        let Response::Preview { html, stylesheets } =
+            testing::api(&app, GUEST_SECRET, request).await
+        else {
+            panic!("expected a preview");
+        };
+        assert!(html.contains(r#"id="content-heading""#));
+        assert!(html.contains("<del>struck</del>"));
+        assert!(page.contains(&html));
+        assert!(stylesheets[0].href.starts_with("/static/style"));
+    }
+
+    #[tokio::test]
+    async fn site_context_follows_the_articles() {
+        let (app, pool) = testing::test_app().await;
+        testing::article(&pool, "First", "Hello").await;
+        let config = testing::config();
+        let cache = SiteCache::default();
+
+        let mut conn = pool.acquire().await.unwrap();
+        let site = cache.get(&config.tags, &mut conn).await.unwrap();
+        assert_eq!(site.article_count, 1);
+        drop(conn);
+
+        testing::api(&app, SECRET, create("Second")).await;
+        let mut conn = pool.acquire().await.unwrap();
+        let site = cache.get(&config.tags, &mut conn).await.unwrap();
+        assert_eq!(site.article_count, 2);
+        assert_eq!(site.recent[0].title, "Second");
+        drop(conn);
+
+        let (_, page) = testing::get(&app, "/").await;
+        assert!(page.contains(&format!("thoughtkeeper {}", site.version())));
+    }
+
+    #[tokio::test]
+    async fn oversized_forms_are_rejected() {
+        let (app, _) = testing::test_app().await;
+        let (cookie, csrf) = testing::session(&app).await;
+        let message = "a".repeat(300 * 1024);
+        let form = format!("csrf={csrf}&name=Spam&email=spam%40example.com&message={message}");
+        let response = testing::post_form(&app, &cookie, "/contact", &form).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // The API keeps its own, larger limit
+        let request = InnerRequest::RenderPreview { content: message };
+        assert!(matches!(
+            testing::api(&app, SECRET, request).await,
+            Response::Preview { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn public_router_leaves_out_api_and_admin() {
+        let (_, pool) = testing::test_app().await;
+        let fonts = resvg::usvg::fontdb::Database::new();
+        let state = super::BlogState::new(pool, testing::config(), false, fonts);
+        let public = super::router(state, false);
+
+        let (status, _) = testing::get(&public, "/").await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = testing::get(&public, "/admin/login").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let request = HttpRequest::post("/api")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+        assert_eq!(
+            testing::respond(&public, request).await.status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn maintenance_keeps_the_articles() {
+        let (app, pool) = testing::test_app().await;
+        testing::article(&pool, "Hello World", "First post").await;
+
+        let mut conn = pool.acquire().await.unwrap();
+        assert!(db::maintain(&mut conn).await.unwrap().is_empty());
+        drop(conn);
+        let (status, _) = testing::get(&app, "/article/Hello_World").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn only_the_newest_backups_are_kept() {
+        let (_, pool) = testing::test_app().await;
+        testing::article(&pool, "Hello World", "First post").await;
+        let config = BackupConfig {
+            dir: std::env::temp_dir().join(format!("backups-{}", uuid::Uuid::new_v4())),
+            interval: 86400,
+            keep: 2,
+        };
+
+        let mut conn = pool.acquire().await.unwrap();
+        let first = backup::snapshot(&config, &mut conn).await.unwrap();
+        assert!(first.exists());
+        for old in ["articles-20200101-000000.db", "articles-20200102-000000.db"] {
+            std::fs::copy(&first, config.dir.join(old)).unwrap();
+        }
+        std::fs::write(config.dir.join("notes.txt"), "not a backup").unwrap();
+
+        assert_eq!(backup::prune(&config).await.unwrap(), 1);
+        assert!(first.exists());
+        assert!(!config.dir.join("articles-20200101-000000.db").exists());
+        assert!(config.dir.join("articles-20200102-000000.db").exists());
+        assert!(config.dir.join("notes.txt").exists());
+        std::fs::remove_dir_all(&config.dir).unwrap();
+
+        for invalid in ["keep = 0", "interval = 59"] {
+            let config = toml::from_str::<BackupConfig>(&format!("dir = \"backups\"\n{invalid}"));
+            assert!(config.is_err(), "{invalid} was accepted");
+        }
+        assert!(
+            toml::from_str::<BackupConfig>("dir = \"backups\"\nkeep = 1\ninterval = 60").is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn authors_search_drafts_too() {
+        let (app, pool) = testing::test_app().await;
+        let article = testing::article(&pool, "Gardening", "Growing tomatoes").await;
+        let mut request = create("Tomato recipes");
+        if let InnerRequest::CreateArticle { fields, .. } = &mut request {
+            fields.draft = Some(true);
+        }
+        testing::api(&app, SECRET, request).await;
+
+        let (_, public) = testing::get(&app, "/search.json?q=tomato").await;
+        assert!(public.contains("Gardening"));
+        assert!(!public.contains("Tomato recipes"));
+
+        let request = InnerRequest::SearchArticles {
+            query: "tomato".to_string(),
+        };
+        let Response::SearchResults(results) = testing::api(&app, SECRET, request).await else {
+            panic!("expected search results");
+        };
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().any(|r| r.id == article.id));
+    }
+
+    #[tokio::test]
+    async fn noted_updates_appear_in_the_changes_feed() {
+        let (app, _) = testing::test_app().await;
+        let Response::ArticleId(id) = testing::api(&app, SECRET, create("Hello World")).await
+        else {
+            panic!("expected an article ID");
+        };
+        let update = |change_note: Option<&str>| InnerRequest::UpdateArticle {
+            id: id.clone(),
+            title: None,
+            content: Some("Corrected *content*".to_string()),
+            change_note: change_note.map(str::to_string),
+            fields: ArticleFields::default(),
+        };
+        testing::api(&app, SECRET, update(None)).await;
+        testing::api(&app, SECRET, update(Some("Fixed the benchmark numbers"))).await;
+
+        let (status, feed) = testing::get(&app, "/changes.xml").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(feed.matches("<item>").count(), 1);
+        assert!(feed.contains("Updated: Hello World"));
+        assert!(feed.contains("Fixed the benchmark numbers"));
+
+        testing::api(&app, SECRET, InnerRequest::YankArticle { id }).await;
+        let (_, feed) = testing::get(&app, "/changes.xml").await;
+        assert!(!feed.contains("<item>"));
+    }
+
+    #[tokio::test]
+    async fn edit_summaries_are_shown_when_enabled() {
+        let mut config = testing::config();
+        config.edit_notes = true;
+        let (app, pool) = super::test_app(config).await;
+        let Response::ArticleId(id) = testing::api(&app, SECRET, create("Hello World")).await
+        else {
+            panic!("expected an article ID");
+        };
+        let request = InnerRequest::UpdateArticle {
+            id: id.clone(),
+            title: None,
+            content: Some("Some *content*, fixed".to_string()),
+            change_note: Some("Fixed a typo".to_string()),
+            fields: ArticleFields::default(),
+        };
+        testing::api(&app, SECRET, request).await;
+
+        let (_, page) = testing::get(&app, "/article/Hello_World").await;
+        assert!(page.contains(": Fixed a typo</small>"));
+        let mut conn = pool.acquire().await.unwrap();
+        let revisions = sqlx::query!(
+            "SELECT content FROM article_revisions WHERE article = ? ORDER BY id",
+            id
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .unwrap();
+        let contents: Vec<_> = revisions.into_iter().map(|r| r.content).collect();
+        assert_eq!(contents, ["Some *content*", "Some *content*, fixed"]);
+    }
+
+    #[tokio::test]
+    async fn revisions_are_compared_word_by_word() {
+        let (app, _) = testing::test_app().await;
+        let Response::ArticleId(id) = testing::api(&app, SECRET, create("Hello World")).await
+        else {
+            panic!("expected an article ID");
+        };
+        let request = InnerRequest::UpdateArticle {
+            id: id.clone(),
+            title: None,
+            content: Some("Some *better* content".to_string()),
+            change_note: Some("Reworded".to_string()),
+            fields: ArticleFields::default(),
+        };
+        testing::api(&app, SECRET, request).await;
+
+        let (status, _) = testing::get(&app, "/article/Hello_World/history").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, page) =
+            testing::admin(&app, SECRET, &format!("/admin/articles/{id}/history")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(page.contains("Reworded"));
+        assert!(page.contains("<ins>*better* content</ins>"));
+        assert!(page.contains("<del>*content*</del>"));
+    }
+
+    #[tokio::test]
+    async fn uploaded_media_is_served() {
+        let (app, _) = testing::test_app().await;
+
+        let request = InnerRequest::UploadMedia {
+            filename: "../notes.txt".to_string(),
+            content_type: "text/plain".to_string(),
+            data: "aGVsbG8=".to_string(),
+        };
+        let Response::MediaUrl(url) = testing::api(&app, SECRET, request).await else {
+            panic!("expected a media URL");
+        };
+        assert!(url.ends_with("/notes.txt"));
+        let (status, body) = testing::get(&app, &url).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "hello");
+    }
+
+    #[tokio::test]
+    async fn forms_require_csrf_token() {
+        let (app, _) = testing::test_app().await;
+        let (cookie, csrf) = testing::session(&app).await;
+        let message = "name=Visitor&email=visitor%40example.com&message=Hello";
+
+        let response = testing::post_form(&app, &cookie, "/contact", message).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let form = format!("{message}&csrf={csrf}");
+        let response = testing::post_form(&app, &cookie, "/contact", &form).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn two_factor_login_requires_code() {
+        let (app, pool) = testing::test_app().await;
+        let mut conn = pool.acquire().await.unwrap();
+        sqlx::query!("INSERT INTO settings ( key, value ) VALUES ('totp_secret', 'JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP')")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        let codes = totp::regenerate_recovery_codes(&mut conn).await.unwrap();
+        drop(conn);
+
+        let (status, _) = testing::admin(&app, SECRET, "/admin/media").await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+
+        let (cookie, csrf) = testing::session(&app).await;
+        let form = format!("secret={SECRET}&csrf={csrf}");
+        let response = testing::post_form(&app, &cookie, "/admin/login", &form).await;
+        let cookie = testing::session_cookie(&response).unwrap();
+        let (_, body) = testing::send(
+            &app,
+            HttpRequest::get("/admin/login")
+                .header(header::COOKIE, &cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        let csrf = testing::csrf_token(&body);
+
+        let form = format!("code={}&csrf={csrf}", codes[0]);
+        let response = testing::post_form(&app, &cookie, "/admin/login/code", &form).await;
+        let cookie = testing::session_cookie(&response).unwrap();
+        let (status, _) = testing::send(
+            &app,
+            HttpRequest::get("/admin/media")
+                .header(header::COOKIE, &cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn changing_two_factor_requires_a_current_code() {
+        let (app, pool) = testing::test_app().await;
+        let mut conn = pool.acquire().await.unwrap();
+        sqlx::query!("INSERT INTO settings ( key, value ) VALUES ('totp_secret', 'JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP')")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        let codes = totp::regenerate_recovery_codes(&mut conn).await.unwrap();
+        drop(conn);
+
+        let (cookie, csrf) = testing::session(&app).await;
+        let form = format!("secret={SECRET}&csrf={csrf}");
+        let response = testing::post_form(&app, &cookie, "/admin/login", &form).await;
+        let cookie = testing::session_cookie(&response).unwrap();
+        let (_, body) = testing::send(
+            &app,
+            HttpRequest::get("/admin/login")
+                .header(header::COOKIE, &cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        let csrf = testing::csrf_token(&body);
+        let form = format!("code={}&csrf={csrf}", codes[0]);
+        let response = testing::post_form(&app, &cookie, "/admin/login/code", &form).await;
+        let cookie = testing::session_cookie(&response).unwrap();
+        let (_, body) = testing::send(
+            &app,
+            HttpRequest::get("/admin/two-factor")
+                .header(header::COOKIE, &cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        let csrf = testing::csrf_token(&body);
+
+        // A session alone can neither replace the app nor see new recovery codes
+        let form = format!("csrf={csrf}");
+        let response = testing::post_form(&app, &cookie, "/admin/two-factor/setup", &form).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response =
+            testing::post_form(&app, &cookie, "/admin/two-factor/recovery-codes", &form).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("That code is not valid."));
+        let mut conn = pool.acquire().await.unwrap();
+        let pending = sqlx::query!("SELECT value FROM settings WHERE key = 'totp_pending'")
+            .fetch_optional(&mut *conn)
+            .await
+            .unwrap();
+        assert!(pending.is_none());
+        assert_eq!(totp::remaining_recovery_codes(&mut conn).await.unwrap(), 9);
+        drop(conn);
+
+        let form = format!("current={}&csrf={csrf}", codes[1]);
+        let response =
+            testing::post_form(&app, &cookie, "/admin/two-factor/recovery-codes", &form).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("Your recovery codes are listed below."));
+    }
+
+    #[tokio::test]
+    async fn expired_secrets_are_rejected() {
+        let (app, pool) = testing::test_app().await;
+        sqlx::query!(
+            "INSERT INTO secrets (secret, auth_mode, scope, expires_at) VALUES ('expired', 'bearer', 'publish', '2000-01-01 00:00:00')"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let response = testing::api(
+            &app,
+            "expired",
+            InnerRequest::ListArticles(Default::default()),
+        )
+        .await;
+        assert!(matches!(response, Response::Error(_)));
+    }
+
+    #[test]
+    fn old_failures_stop_counting() {
+        let lockout = Lockout::default();
+        let ip = IpAddr::from([192, 0, 2, 1]);
+        let window = std::time::Duration::from_millis(50);
+        let duration = std::time::Duration::from_secs(60);
+        assert!(!lockout.record_failure(ip, 2, window, duration));
+        std::thread::sleep(window);
+        assert!(!lockout.record_failure(ip, 2, window, duration));
+        assert!(lockout.remaining(ip).is_none());
+        assert!(lockout.record_failure(ip, 2, window, duration));
+        assert!(lockout.remaining(ip).is_some());
+    }
+
+    #[tokio::test]
+    async fn signatures_cannot_be_replayed_in_another_case() {
+        let (_, pool) = testing::test_app().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let (key_id, secret) = secret::create(None, true, Scope::Publish, None, &mut conn)
+            .await
+            .unwrap();
+        let body = br#"{"type": "ListArticles"}"#;
+        let timestamp = Utc::now().timestamp();
+        let signature = auth::sign(&secret, timestamp, body);
+        let seen = Mutex::new(HashMap::new());
+        let check = |signature: String| Signature {
+            key_id,
+            timestamp,
+            signature,
+        };
+
+        let first = super::is_signature_valid(&check(signature.clone()), body, &seen, &mut conn)
+            .await
+            .unwrap();
+        assert!(first.is_some());
+        let recased = check(signature.to_ascii_uppercase());
+        let replay = super::is_signature_valid(&recased, body, &seen, &mut conn)
+            .await
+            .unwrap();
+        assert!(replay.is_none());
+    }
+
+    #[tokio::test]
+    async fn only_forms_can_carry_the_csrf_token() {
+        let (app, pool) = testing::test_app().await;
+        let (cookie, csrf) = testing::session(&app).await;
+        let form = format!("secret={SECRET}&csrf={csrf}");
+        let response = testing::post_form(&app, &cookie, "/admin/login", &form).await;
+        let admin = testing::session_cookie(&response).unwrap();
+        let (_, page) = testing::send(
+            &app,
+            HttpRequest::get("/admin/secrets")
+                .header(header::COOKIE, &admin)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        let csrf = testing::csrf_token(&page);
+        let guest = sqlx::query!("SELECT id FROM secrets WHERE secret = ?", GUEST_SECRET)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .id;
+
+        // Another site can send a plain text body with the admin's cookie attached
+        let response = testing::respond(
+            &app,
+            HttpRequest::post(format!("/admin/secrets/{guest}/revoke"))
+                .header(header::COOKIE, &admin)
+                .header(header::CONTENT_TYPE, "text/plain")
+                .body(Body::from(format!("csrf={csrf}")))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let left = sqlx::query!("SELECT COUNT(*) AS count FROM secrets WHERE id = ?", guest)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .count;
+        assert_eq!(left, 1);
+    }
+
+    #[tokio::test]
+    async fn revoking_secrets_logs_out_their_sessions() {
+        let (app, pool) = testing::test_app().await;
+        let login = || {
+            let app = app.clone();
+            async move {
+                let (cookie, csrf) = testing::session(&app).await;
+                let form = format!("secret={SECRET}&csrf={csrf}");
+                let response = testing::post_form(&app, &cookie, "/admin/login", &form).await;
+                testing::session_cookie(&response).unwrap()
+            }
+        };
+        let status = |cookie: String| {
+            let app = app.clone();
+            async move {
+                testing::respond(
+                    &app,
+                    HttpRequest::get("/admin/secrets")
+                        .header(header::COOKIE, cookie)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .status()
+            }
+        };
+
+        let stolen = login().await;
+        let admin = login().await;
+        let (_, page) = testing::send(
+            &app,
+            HttpRequest::get("/admin/secrets")
+                .header(header::COOKIE, &admin)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        let csrf = testing::csrf_token(&page);
+        let guest = sqlx::query!("SELECT id FROM secrets WHERE secret = ?", GUEST_SECRET)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .id;
+        let response = testing::post_form(
+            &app,
+            &admin,
+            &format!("/admin/secrets/{guest}/revoke"),
+            &format!("csrf={csrf}"),
+        )
+        .await;
+        // The admin who revoked it stays logged in, everyone else is logged out
+        let admin = testing::session_cookie(&response).unwrap();
+        assert_eq!(status(admin.clone()).await, StatusCode::OK);
+        assert_eq!(status(stolen).await, StatusCode::SEE_OTHER);
+
+        let mut conn = pool.acquire().await.unwrap();
+        let (revoked, _) = super::purge(&mut conn).await.unwrap();
+        drop(conn);
+        assert_eq!(revoked, 1);
+        assert_eq!(status(admin).await, StatusCode::SEE_OTHER);
+    }
+
+    #[tokio::test]
+    async fn admin_requires_publish_secret() {
+        let (app, _) = testing::test_app().await;
+
+        let (status, _) = testing::get(&app, "/admin/media").await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+
+        let (status, _) = testing::admin(&app, GUEST_SECRET, "/admin/media").await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+
+        let (status, body) = testing::admin(&app, SECRET, "/admin/media").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("Nothing has been uploaded yet."));
+    }
 }
@@ -0,0 +1,149 @@
+use axum::http::{header, HeaderMap};
+use chrono::Utc;
+use miette::IntoDiagnostic;
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    thread_rng,
+};
+use sqlx::SqliteConnection;
+
+use crate::{auth, SessionConfig};
+
+/// Name of the session cookie
+pub const COOKIE: &str = "tk_session";
+
+/// How far a browser got logging in to the admin panel
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Login {
+    Guest,
+    /// Logged in with a secret, but the second factor is still missing
+    AwaitingCode,
+    Admin,
+}
+
+impl Login {
+    fn as_str(self) -> &'static str {
+        match self {
+            Login::Guest => "guest",
+            Login::AwaitingCode => "code",
+            Login::Admin => "admin",
+        }
+    }
+
+    fn from_cookie(login: &str) -> Self {
+        match login {
+            "admin" => Login::Admin,
+            "code" => Login::AwaitingCode,
+            _ => Login::Guest,
+        }
+    }
+}
+
+/// A browser session, kept entirely in a signed cookie
+#[derive(Clone)]
+pub struct Session {
+    pub id: String,
+    pub login: Login,
+    /// Unix timestamp after which the cookie is no longer accepted
+    pub expires: i64,
+    /// Token that has to be sent along with every form in this session
+    pub csrf: String,
+}
+
+impl Session {
+    pub fn new(login: Login, key: &str, config: &SessionConfig) -> Self {
+        let id = Alphanumeric.sample_string(&mut thread_rng(), 32);
+        Self {
+            csrf: auth::sign(key, 0, format!("csrf:{id}").as_bytes()),
+            id,
+            login,
+            expires: Utc::now().timestamp() + config.lifetime as i64,
+        }
+    }
+
+    /// Reads the session from the request's cookie if it is signed with the key and still valid
+    pub fn from_headers(headers: &HeaderMap, key: &str) -> Option<Self> {
+        let value = headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(';'))
+            .find_map(|c| c.trim().strip_prefix(&format!("{COOKIE}=")))?;
+
+        let (payload, signature) = value.rsplit_once('.')?;
+        let (data, expires) = payload.rsplit_once('.')?;
+        let expires = expires.parse().ok()?;
+        if expires < Utc::now().timestamp()
+            || !auth::verify(key, expires, data.as_bytes(), signature)
+        {
+            return None;
+        }
+
+        let (id, login) = data.split_once('.')?;
+        Some(Self {
+            csrf: auth::sign(key, 0, format!("csrf:{id}").as_bytes()),
+            id: id.to_string(),
+            login: Login::from_cookie(login),
+            expires,
+        })
+    }
+
+    /// The `Set-Cookie` header value storing this session
+    pub fn cookie(&self, key: &str, config: &SessionConfig) -> String {
+        let data = format!("{}.{}", self.id, self.login.as_str());
+        let signature = auth::sign(key, self.expires, data.as_bytes());
+        let secure = if config.secure { "; Secure" } else { "" };
+        format!(
+            "{COOKIE}={data}.{}.{signature}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax{secure}",
+            self.expires, config.lifetime
+        )
+    }
+
+    /// The `Set-Cookie` header value removing the session cookie
+    pub fn clear_cookie() -> String {
+        format!("{COOKIE}=; Path=/; Max-Age=0; HttpOnly; SameSite=Lax")
+    }
+
+    pub fn check_csrf(&self, token: &str) -> bool {
+        auth::secrets_equal(&self.csrf, token)
+    }
+}
+
+/// The key sessions are signed with, generated once per database and again by
+/// [`rotate_key`]
+pub async fn load_key(conn: &mut SqliteConnection) -> miette::Result<String> {
+    let existing = sqlx::query!("SELECT value FROM settings WHERE key = 'session_key'")
+        .fetch_optional(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    if let Some(existing) = existing {
+        return Ok(existing.value);
+    }
+
+    let key = Alphanumeric.sample_string(&mut thread_rng(), 64);
+    sqlx::query!(
+        "INSERT OR IGNORE INTO settings ( key, value ) VALUES ('session_key', ?)",
+        key
+    )
+    .execute(&mut *conn)
+    .await
+    .into_diagnostic()?;
+
+    Ok(
+        sqlx::query!("SELECT value FROM settings WHERE key = 'session_key'")
+            .fetch_one(conn)
+            .await
+            .into_diagnostic()?
+            .value,
+    )
+}
+
+/// Discards the session key, which logs out every session and invalidates preview links.
+/// A new key is generated when it is next needed.
+pub async fn rotate_key(conn: &mut SqliteConnection) -> miette::Result<()> {
+    sqlx::query!("DELETE FROM settings WHERE key = 'session_key'")
+        .execute(conn)
+        .await
+        .into_diagnostic()?;
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use std::{
+    future::Future,
+    sync::{Arc, RwLock},
+};
+
+use chrono::{Datelike, Utc};
+use miette::IntoDiagnostic;
+use sqlx::SqliteConnection;
+
+use crate::{
+    article::url_of,
+    datetime,
+    feed::{self, FeedVersion},
+    tag::{self, TagCount},
+    ServerConfig, TagConfig,
+};
+
+/// How many articles `recent` holds
+const RECENT_ARTICLES: i64 = 5;
+
+/// Information about the whole blog that every template can show, e.g. in a sidebar of
+/// a custom theme, through `config.site()`
+#[derive(Default)]
+pub struct SiteContext {
+    /// How many articles are published
+    pub article_count: i64,
+    /// The latest published articles, newest first
+    pub recent: Vec<RecentArticle>,
+    /// All tags used often enough to show up in the tag cloud
+    pub tags: Vec<TagCount>,
+}
+
+pub struct RecentArticle {
+    pub title: String,
+    pub slug: Option<String>,
+}
+
+impl RecentArticle {
+    pub fn url(&self) -> String {
+        url_of(&self.title, self.slug.as_deref())
+    }
+}
+
+impl SiteContext {
+    /// The current year in the blog's timezone, e.g. for a copyright notice
+    pub fn year(&self) -> i32 {
+        datetime::local(Utc::now().naive_utc()).year()
+    }
+
+    /// The version of thoughtkeeper serving the blog
+    pub fn version(&self) -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+}
+
+async fn build(
+    version: FeedVersion,
+    config: &TagConfig,
+    conn: &mut SqliteConnection,
+) -> miette::Result<SiteContext> {
+    let recent = sqlx::query!(
+        "SELECT title, slug FROM articles WHERE status = 'published' ORDER BY published DESC LIMIT ?",
+        RECENT_ARTICLES
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .into_diagnostic()?
+    .into_iter()
+    .map(|r| RecentArticle {
+        title: r.title,
+        slug: r.slug,
+    })
+    .collect();
+
+    Ok(SiteContext {
+        article_count: version.count,
+        recent,
+        tags: tag::tag_counts(config, conn).await?,
+    })
+}
+
+/// Keeps the site context until an article is published, edited or taken down
+#[derive(Default)]
+pub struct SiteCache {
+    entry: RwLock<Option<(FeedVersion, Arc<SiteContext>)>>,
+}
+
+impl SiteCache {
+    pub async fn get(
+        &self,
+        config: &TagConfig,
+        conn: &mut SqliteConnection,
+    ) -> miette::Result<Arc<SiteContext>> {
+        let version = feed::version(None, conn).await?;
+        if let Some((cached, site)) = &*self.entry.read().unwrap() {
+            if *cached == version {
+                return Ok(site.clone());
+            }
+        }
+
+        let site = Arc::new(build(version, config, conn).await?);
+        *self.entry.write().unwrap() = Some((version, site.clone()));
+        Ok(site)
+    }
+}
+
+tokio::task_local! {
+    /// The site context of the request being handled, so templates can use it without
+    /// every page passing it along
+    static CURRENT: Arc<SiteContext>;
+}
+
+/// Runs the future with the site context available to the templates it renders
+pub async fn scope<F: Future>(site: Arc<SiteContext>, f: F) -> F::Output {
+    CURRENT.scope(site, f).await
+}
+
+impl ServerConfig {
+    /// The site context of the request being handled, empty outside of one
+    pub fn site(&self) -> Arc<SiteContext> {
+        CURRENT.try_with(Arc::clone).unwrap_or_default()
+    }
+}
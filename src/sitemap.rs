@@ -0,0 +1,55 @@
+use crate::article::Article;
+
+/// Turns a path or relative URL into an absolute URL on the given domain
+pub fn absolute_url(domain: &str, url: &str) -> String {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        url.to_string()
+    } else {
+        format!("https://{domain}/{}", url.trim_start_matches('/'))
+    }
+}
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Renders a sitemap of the index and all articles, including the images they reference
+pub fn sitemap(domain: &str, articles: &[Article]) -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9" xmlns:image="http://www.google.com/schemas/sitemap-image/1.1">"#,
+        "\n"
+    ));
+
+    xml.push_str(&format!(
+        "  <url>\n    <loc>{}</loc>\n  </url>\n",
+        escape_xml(&absolute_url(domain, "/"))
+    ));
+
+    for article in articles {
+        xml.push_str("  <url>\n");
+        xml.push_str(&format!(
+            "    <loc>{}</loc>\n    <lastmod>{}</lastmod>\n",
+            escape_xml(&absolute_url(
+                domain,
+                &format!("/article/{}", article.url())
+            )),
+            article.last_modified().format("%Y-%m-%d")
+        ));
+        for image in article.images() {
+            xml.push_str(&format!(
+                "    <image:image>\n      <image:loc>{}</image:loc>\n    </image:image>\n",
+                escape_xml(&absolute_url(domain, &image))
+            ));
+        }
+        xml.push_str("  </url>\n");
+    }
+
+    xml.push_str("</urlset>\n");
+    xml
+}
@@ -0,0 +1,83 @@
+use std::{
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use miette::IntoDiagnostic;
+use sqlx::SqliteConnection;
+
+use crate::{article::url_of, PopularConfig};
+
+#[derive(Clone)]
+pub struct PopularArticle {
+    pub title: String,
+    pub slug: Option<String>,
+    pub views: i64,
+}
+
+impl PopularArticle {
+    pub fn url(&self) -> String {
+        url_of(&self.title, self.slug.as_deref())
+    }
+}
+
+/// Caches the most-read articles so the aggregation doesn't run on every page view
+#[derive(Default)]
+pub struct PopularCache {
+    entry: RwLock<Option<(Instant, Vec<PopularArticle>)>>,
+}
+
+impl PopularCache {
+    pub async fn get(
+        &self,
+        config: &PopularConfig,
+        conn: &mut SqliteConnection,
+    ) -> miette::Result<Vec<PopularArticle>> {
+        if let Some((computed, articles)) = &*self.entry.read().unwrap() {
+            if computed.elapsed() < Duration::from_secs(config.cache_secs) {
+                return Ok(articles.clone());
+            }
+        }
+
+        let articles = popular_articles(config, conn).await?;
+        *self.entry.write().unwrap() = Some((Instant::now(), articles.clone()));
+        Ok(articles)
+    }
+}
+
+pub async fn record_view(article: &str, conn: &mut SqliteConnection) -> miette::Result<()> {
+    let now = Utc::now().naive_utc();
+    sqlx::query!(
+        "INSERT INTO views ( article, viewed ) VALUES (?1, ?2)",
+        article,
+        now
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?;
+    Ok(())
+}
+
+/// The articles with the most views within the configured window
+async fn popular_articles(
+    config: &PopularConfig,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Vec<PopularArticle>> {
+    let since = Utc::now().naive_utc() - chrono::Duration::days(config.window_days);
+
+    Ok(sqlx::query_as!(
+        PopularArticle,
+        r#"SELECT articles.title AS "title!", articles.slug, COUNT(*) AS "views!: i64"
+        FROM views JOIN articles ON articles.id = views.article
+        WHERE views.viewed >= ?
+        GROUP BY views.article
+        ORDER BY COUNT(*) DESC
+        LIMIT ?"#,
+        since,
+        config.count
+    )
+    .fetch_all(conn)
+    .await
+    .into_diagnostic()?)
+}
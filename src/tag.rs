@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use miette::IntoDiagnostic;
+use serde::Deserialize;
+use sqlx::SqliteConnection;
+
+use crate::TagConfig;
+
+/// How the tag cloud is ordered
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TagOrder {
+    /// Alphabetically
+    #[default]
+    Name,
+    /// Most used tags first
+    Count,
+}
+
+#[derive(Clone)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: i64,
+    /// Relative usage from 1 (rarely used) to 5 (most used), for sizing the tag cloud
+    pub weight: i64,
+}
+
+/// Normalizes user-supplied tags so `Rust Lang` and `rust-lang` are the same tag
+pub fn normalize(tag: &str) -> String {
+    tag.split_whitespace().join("-").to_lowercase()
+}
+
+/// Replaces the tags of an article
+pub async fn set_tags(
+    article: &str,
+    tags: &[String],
+    conn: &mut SqliteConnection,
+) -> miette::Result<()> {
+    sqlx::query!("DELETE FROM article_tags WHERE article = ?", article)
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+
+    for tag in tags
+        .iter()
+        .map(|t| normalize(t))
+        .filter(|t| !t.is_empty())
+        .unique()
+    {
+        sqlx::query!(
+            "INSERT INTO article_tags ( article, tag ) VALUES (?1, ?2)",
+            article,
+            tag
+        )
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    }
+
+    Ok(())
+}
+
+pub async fn tags_for(article: &str, conn: &mut SqliteConnection) -> miette::Result<Vec<String>> {
+    Ok(sqlx::query!(
+        "SELECT tag FROM article_tags WHERE article = ? ORDER BY tag",
+        article
+    )
+    .fetch_all(conn)
+    .await
+    .into_diagnostic()?
+    .into_iter()
+    .map(|r| r.tag)
+    .collect())
+}
+
+/// The tags of every article, by article ID
+pub async fn all_tags(conn: &mut SqliteConnection) -> miette::Result<HashMap<String, Vec<String>>> {
+    Ok(
+        sqlx::query!("SELECT article, tag FROM article_tags ORDER BY tag")
+            .fetch_all(conn)
+            .await
+            .into_diagnostic()?
+            .into_iter()
+            .map(|r| (r.article, r.tag))
+            .into_group_map(),
+    )
+}
+
+/// Counts how often each tag is used, leaving out tags below the configured minimum
+pub async fn tag_counts(
+    config: &TagConfig,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Vec<TagCount>> {
+    let rows = sqlx::query!(
+        r#"SELECT tag, COUNT(*) AS "count!: i64" FROM article_tags GROUP BY tag HAVING COUNT(*) >= ?"#,
+        config.min_count
+    )
+    .fetch_all(conn)
+    .await
+    .into_diagnostic()?;
+
+    let max = rows.iter().map(|r| r.count).max().unwrap_or(1);
+    let mut tags = rows
+        .into_iter()
+        .map(|r| TagCount {
+            weight: 1 + (r.count - 1) * 4 / max.max(2).saturating_sub(1),
+            tag: r.tag,
+            count: r.count,
+        })
+        .collect_vec();
+
+    match config.order {
+        TagOrder::Name => tags.sort_by(|a, b| a.tag.cmp(&b.tag)),
+        TagOrder::Count => tags.sort_by(|a, b| b.count.cmp(&a.count).then(a.tag.cmp(&b.tag))),
+    }
+
+    Ok(tags)
+}
@@ -0,0 +1,161 @@
+//! Fixtures and helpers for testing the server against an in-memory database
+
+use std::net::SocketAddr;
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::ConnectInfo,
+    http::{header, Request as HttpRequest, StatusCode},
+    response::Response as AxumResponse,
+    Router,
+};
+use sqlx::{Pool, Sqlite};
+use tower::ServiceExt;
+
+use crate::{
+    article::Article,
+    request::{InnerRequest, Request, Response},
+    server, ServerConfig,
+};
+
+/// The bearer secret every test app accepts
+pub const SECRET: &str = "test-secret";
+/// A secret that may only submit articles
+pub const GUEST_SECRET: &str = "guest-secret";
+
+pub fn config() -> ServerConfig {
+    toml::from_str(
+        r#"
+        blog_name = "Test Blog"
+        author = "Tester"
+        description = "A blog for tests"
+        footer_links = {}
+        addr = "127.0.0.1:4444"
+        domain = "blog.example.com"
+        "#,
+    )
+    .unwrap()
+}
+
+/// The server with the default test config and both test secrets
+pub async fn test_app() -> (Router, Pool<Sqlite>) {
+    let (app, pool) = server::test_app(config()).await;
+    sqlx::query!(
+        "INSERT INTO secrets (secret, auth_mode, scope) VALUES (?1, 'bearer', 'publish'), (?2, 'bearer', 'submit')",
+        SECRET,
+        GUEST_SECRET
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    (app, pool)
+}
+
+/// Stores a published article directly in the database
+pub async fn article(pool: &Pool<Sqlite>, title: &str, content: &str) -> Article {
+    let article = Article::new(title.to_string(), content.to_string());
+    sqlx::query!(
+        "INSERT INTO articles ( id, title, content, published, status ) VALUES (?1, ?2, ?3, ?4, ?5)",
+        article.id,
+        article.title,
+        article.content,
+        article.published,
+        article.status
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    article
+}
+
+/// Sends a request as if it came from a local client
+pub async fn respond(app: &Router, mut request: HttpRequest<Body>) -> AxumResponse {
+    request
+        .extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+    app.clone().oneshot(request).await.unwrap()
+}
+
+pub async fn send(app: &Router, request: HttpRequest<Body>) -> (StatusCode, String) {
+    let response = respond(app, request).await;
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+/// The `name=value` part of the session cookie set by a response
+pub fn session_cookie(response: &AxumResponse) -> Option<String> {
+    let cookie = response.headers().get(header::SET_COOKIE)?.to_str().ok()?;
+    Some(cookie.split(';').next()?.to_string())
+}
+
+/// The CSRF token of the first form on a page
+pub fn csrf_token(page: &str) -> String {
+    page.split(r#"name="csrf" value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap()
+        .to_string()
+}
+
+/// Starts a session by visiting a page with a form, returning its cookie and CSRF token
+pub async fn session(app: &Router) -> (String, String) {
+    let response = respond(
+        app,
+        HttpRequest::get("/contact").body(Body::empty()).unwrap(),
+    )
+    .await;
+    let cookie = session_cookie(&response).unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    let csrf = csrf_token(&body);
+    (cookie, csrf)
+}
+
+/// Submits a form within the session
+pub async fn post_form(app: &Router, cookie: &str, uri: &str, form: &str) -> AxumResponse {
+    let request = HttpRequest::post(uri)
+        .header(header::COOKIE, cookie)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(form.to_string()))
+        .unwrap();
+    respond(app, request).await
+}
+
+pub async fn get(app: &Router, uri: &str) -> (StatusCode, String) {
+    send(app, HttpRequest::get(uri).body(Body::empty()).unwrap()).await
+}
+
+/// Requests an admin page after logging in with the given secret
+pub async fn admin(app: &Router, secret: &str, uri: &str) -> (StatusCode, String) {
+    let (cookie, csrf) = session(app).await;
+    let response = post_form(
+        app,
+        &cookie,
+        "/admin/login",
+        &format!("secret={secret}&csrf={csrf}"),
+    )
+    .await;
+    let cookie = session_cookie(&response).unwrap_or(cookie);
+
+    let request = HttpRequest::get(uri)
+        .header(header::COOKIE, cookie)
+        .body(Body::empty())
+        .unwrap();
+    send(app, request).await
+}
+
+/// Makes an API request authenticated with the given bearer secret
+pub async fn api(app: &Router, secret: &str, request: InnerRequest) -> Response {
+    let body = serde_json::to_vec(&Request {
+        secret: Some(secret.to_string()),
+        request,
+    })
+    .unwrap();
+    let request = HttpRequest::post("/api")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let (_, body) = send(app, request).await;
+    serde_json::from_str(&body).unwrap()
+}
@@ -0,0 +1,214 @@
+use chrono::Utc;
+use miette::{miette, IntoDiagnostic};
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    thread_rng,
+};
+use sha2::{Digest, Sha256};
+use sqlx::SqliteConnection;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::server::connect;
+
+/// Number of recovery codes handed out when two-factor authentication is enabled
+const RECOVERY_CODES: usize = 10;
+
+/// Builds the authenticator for a base32 encoded secret, labelled with the blog's name
+fn authenticator(secret: &str, blog_name: &str) -> miette::Result<TOTP> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| miette!("invalid TOTP secret: {e:?}"))?;
+    // Colons separate the issuer from the account in provisioning URLs
+    let issuer = blog_name.replace(':', " ");
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret,
+        Some(issuer),
+        "admin".to_string(),
+    )
+    .into_diagnostic()
+}
+
+async fn setting(key: &str, conn: &mut SqliteConnection) -> miette::Result<Option<String>> {
+    Ok(
+        sqlx::query!("SELECT value FROM settings WHERE key = ?", key)
+            .fetch_optional(conn)
+            .await
+            .into_diagnostic()?
+            .map(|r| r.value),
+    )
+}
+
+/// Whether logging in to the admin panel requires a second factor
+pub async fn is_enabled(conn: &mut SqliteConnection) -> miette::Result<bool> {
+    Ok(setting("totp_secret", conn).await?.is_some())
+}
+
+/// A secret that was generated but not yet confirmed with a code from the authenticator app
+pub struct Setup {
+    pub secret: String,
+    /// PNG of the provisioning QR code, base64 encoded
+    pub qr_code: String,
+}
+
+impl Setup {
+    fn new(secret: String, blog_name: &str) -> miette::Result<Self> {
+        let qr_code = authenticator(&secret, blog_name)?
+            .get_qr_base64()
+            .map_err(|e| miette!("could not render the QR code: {e}"))?;
+        Ok(Self { secret, qr_code })
+    }
+}
+
+/// Generates a new secret to be confirmed with [`confirm`]. Two-factor authentication
+/// stays as it is until then.
+pub async fn begin_setup(blog_name: &str, conn: &mut SqliteConnection) -> miette::Result<Setup> {
+    let Secret::Encoded(secret) = Secret::generate_secret().to_encoded() else {
+        unreachable!("to_encoded always returns an encoded secret")
+    };
+    sqlx::query!(
+        "INSERT OR REPLACE INTO settings ( key, value ) VALUES ('totp_pending', ?)",
+        secret
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?;
+
+    Setup::new(secret, blog_name)
+}
+
+/// The setup that is waiting to be confirmed, if any
+pub async fn pending_setup(
+    blog_name: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Option<Setup>> {
+    setting("totp_pending", conn)
+        .await?
+        .map(|secret| Setup::new(secret, blog_name))
+        .transpose()
+}
+
+/// Enables the pending secret if the code matches it, returning new recovery codes
+pub async fn confirm(
+    code: &str,
+    blog_name: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<Option<Vec<String>>> {
+    let Some(secret) = setting("totp_pending", conn).await? else {
+        return Ok(None);
+    };
+    if !authenticator(&secret, blog_name)?
+        .check_current(code.trim())
+        .into_diagnostic()?
+    {
+        return Ok(None);
+    }
+
+    sqlx::query!(
+        "INSERT OR REPLACE INTO settings ( key, value ) VALUES ('totp_secret', ?)",
+        secret
+    )
+    .execute(&mut *conn)
+    .await
+    .into_diagnostic()?;
+    sqlx::query!("DELETE FROM settings WHERE key = 'totp_pending'")
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+
+    Ok(Some(regenerate_recovery_codes(conn).await?))
+}
+
+fn hash_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.trim().to_lowercase().as_bytes()))
+}
+
+/// Replaces all recovery codes. Only their hashes are stored.
+pub async fn regenerate_recovery_codes(conn: &mut SqliteConnection) -> miette::Result<Vec<String>> {
+    sqlx::query!("DELETE FROM recovery_codes")
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+
+    let codes: Vec<String> = (0..RECOVERY_CODES)
+        .map(|_| {
+            Alphanumeric
+                .sample_string(&mut thread_rng(), 10)
+                .to_lowercase()
+        })
+        .collect();
+    for code in &codes {
+        let hash = hash_code(code);
+        sqlx::query!("INSERT INTO recovery_codes ( code_hash ) VALUES (?)", hash)
+            .execute(&mut *conn)
+            .await
+            .into_diagnostic()?;
+    }
+    Ok(codes)
+}
+
+pub async fn remaining_recovery_codes(conn: &mut SqliteConnection) -> miette::Result<i64> {
+    Ok(sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!: i64" FROM recovery_codes WHERE used_at IS NULL"#
+    )
+    .fetch_one(conn)
+    .await
+    .into_diagnostic()?
+    .count)
+}
+
+/// Checks a code from the authenticator app, or else an unused recovery code, which
+/// can't be used again afterwards
+pub async fn verify(
+    code: &str,
+    blog_name: &str,
+    conn: &mut SqliteConnection,
+) -> miette::Result<bool> {
+    let Some(secret) = setting("totp_secret", conn).await? else {
+        return Ok(false);
+    };
+    if authenticator(&secret, blog_name)?
+        .check_current(code.trim())
+        .into_diagnostic()?
+    {
+        return Ok(true);
+    }
+
+    let hash = hash_code(code);
+    let now = Utc::now().naive_utc();
+    let used = sqlx::query!(
+        "UPDATE recovery_codes SET used_at = ? WHERE code_hash = ? AND used_at IS NULL",
+        now,
+        hash
+    )
+    .execute(conn)
+    .await
+    .into_diagnostic()?
+    .rows_affected();
+    Ok(used > 0)
+}
+
+/// Turns two-factor authentication off and discards the recovery codes
+pub async fn disable(conn: &mut SqliteConnection) -> miette::Result<()> {
+    sqlx::query!("DELETE FROM settings WHERE key IN ('totp_secret', 'totp_pending')")
+        .execute(&mut *conn)
+        .await
+        .into_diagnostic()?;
+    sqlx::query!("DELETE FROM recovery_codes")
+        .execute(conn)
+        .await
+        .into_diagnostic()?;
+    Ok(())
+}
+
+/// Disables two-factor authentication from the server's shell, for when the
+/// authenticator app and all recovery codes are lost
+pub async fn reset() -> miette::Result<()> {
+    let mut conn = connect().await?;
+    disable(&mut conn).await?;
+    println!("Two-factor authentication is disabled.");
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use comrak::{nodes::NodeValue, Arena, Options};
+use serde::{Deserialize, Serialize};
+
+use crate::{article::Article, ValidationConfig};
+
+/// Something about an article that keeps it from being stored
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ValidationError {
+    /// `title` or `content`
+    pub field: String,
+    pub message: String,
+}
+
+impl ValidationError {
+    fn new(field: &str, message: String) -> Self {
+        Self {
+            field: field.to_string(),
+            message,
+        }
+    }
+}
+
+/// The lowercase names of the tags opened in a piece of HTML
+fn opened_tags(html: &str) -> impl Iterator<Item = String> + '_ {
+    html.split('<').skip(1).filter_map(|tag| {
+        let name: String = tag
+            .chars()
+            .take_while(char::is_ascii_alphanumeric)
+            .collect();
+        (!name.is_empty()).then(|| name.to_ascii_lowercase())
+    })
+}
+
+/// Whether a piece of HTML contains an `img` tag without an `alt` attribute
+fn has_img_without_alt(html: &str) -> bool {
+    html.split('<').skip(1).any(|tag| {
+        let tag = tag
+            .split('>')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        tag.starts_with("img") && !tag.contains("alt=")
+    })
+}
+
+impl ValidationConfig {
+    /// Everything that is wrong with an article, empty if it may be stored
+    pub fn check(&self, article: &Article) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if !self.enabled {
+            return errors;
+        }
+
+        if article.title.trim().is_empty() {
+            errors.push(ValidationError::new(
+                "title",
+                "must not be empty".to_string(),
+            ));
+        } else if article.title.chars().count() > self.max_title_length {
+            errors.push(ValidationError::new(
+                "title",
+                format!("must be at most {} characters long", self.max_title_length),
+            ));
+        }
+        if article.content.len() > self.max_content_size {
+            errors.push(ValidationError::new(
+                "content",
+                format!("must be at most {} bytes long", self.max_content_size),
+            ));
+        }
+
+        let arena = Arena::new();
+        let root = comrak::parse_document(&arena, &article.content, &Options::default());
+        for node in root.descendants() {
+            let line = node.data.borrow().sourcepos.start.line;
+            let html = match &node.data.borrow().value {
+                NodeValue::HtmlBlock(block) => block.literal.clone(),
+                NodeValue::HtmlInline(html) => html.clone(),
+                NodeValue::Image(link) => {
+                    let alt: String = node
+                        .descendants()
+                        .filter_map(|n| match &n.data.borrow().value {
+                            NodeValue::Text(text) => Some(text.clone()),
+                            _ => None,
+                        })
+                        .collect();
+                    if self.require_alt_text && alt.trim().is_empty() {
+                        errors.push(ValidationError::new(
+                            "content",
+                            format!("line {line}: the image {} has no alt text", link.url),
+                        ));
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+
+            for tag in opened_tags(&html) {
+                if self
+                    .forbidden_tags
+                    .iter()
+                    .any(|t| t.eq_ignore_ascii_case(&tag))
+                {
+                    errors.push(ValidationError::new(
+                        "content",
+                        format!("line {line}: the <{tag}> tag is not allowed"),
+                    ));
+                }
+            }
+            if self.require_alt_text && has_img_without_alt(&html) {
+                errors.push(ValidationError::new(
+                    "content",
+                    format!("line {line}: an <img> tag has no alt attribute"),
+                ));
+            }
+        }
+        errors
+    }
+}